@@ -0,0 +1,208 @@
+use crate::error::ExpenseError;
+use crate::files::write_atomic;
+use crate::paths::data_file;
+use crate::validation::{validate_description, Limits};
+use crate::{read_to_string_or, Expense, TransactionKind};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+/// The payment method an account stands for.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountKind {
+    Cash,
+    Card,
+    Bank,
+}
+
+/// Where an expense was paid from, such as a wallet, a credit card or a
+/// checking account.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Account {
+    pub name: String,
+    pub kind: AccountKind,
+    /// The balance before any expense recorded in the ledger.
+    #[serde(default)]
+    pub opening_balance: f64,
+}
+
+/// A row of the `balance` command.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Balance {
+    pub account: String,
+    pub kind: AccountKind,
+    pub opening_balance: f64,
+    /// The total of the income paid into the account.
+    pub income: f64,
+    /// The total of the expenses paid from the account.
+    pub spent: f64,
+    /// Transfers into the account minus transfers out of it.
+    pub transferred: f64,
+    /// `opening_balance + income - spent + transferred`.
+    pub balance: f64,
+}
+
+impl FromStr for AccountKind {
+    type Err = String;
+
+    fn from_str(kind: &str) -> Result<Self, Self::Err> {
+        match kind.to_lowercase().as_str() {
+            "cash" => Ok(AccountKind::Cash),
+            "card" | "credit" | "debit" => Ok(AccountKind::Card),
+            "bank" | "checking" | "savings" => Ok(AccountKind::Bank),
+            _ => Err(format!(
+                "unknown account kind '{}' (expected cash, card or bank)",
+                kind
+            )),
+        }
+    }
+}
+
+impl fmt::Display for AccountKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountKind::Cash => write!(f, "cash"),
+            AccountKind::Card => write!(f, "card"),
+            AccountKind::Bank => write!(f, "bank"),
+        }
+    }
+}
+
+/// Loads the current profile's accounts from `accounts.json`.
+///
+/// # Returns
+///
+/// The accounts, empty if none have been defined.
+pub fn load_accounts() -> Result<Vec<Account>, ExpenseError> {
+    let accounts_file = data_file("accounts.json");
+    let accounts = read_to_string_or(&accounts_file, "[]")?;
+
+    serde_json::from_str(&accounts).map_err(|e| ExpenseError::corrupt(&accounts_file, e))
+}
+
+/// Saves the current profile's accounts to `accounts.json`.
+///
+/// # Arguments
+///
+/// * `accounts` - The accounts to save.
+pub fn save_accounts(accounts: &[Account]) -> Result<(), ExpenseError> {
+    let contents = serde_json::to_vec(accounts).map_err(io::Error::from)?;
+
+    write_atomic(data_file("accounts.json"), &contents)
+}
+
+/// Adds an account.
+///
+/// # Arguments
+///
+/// * `accounts` - The existing accounts; updated in place.
+/// * `account` - The new account.
+/// * `limits` - The limits the name is checked against.
+///
+/// # Returns
+///
+/// `ExpenseError::Validation` if the name is empty or already taken.
+pub fn add_account(
+    accounts: &mut Vec<Account>,
+    mut account: Account,
+    limits: &Limits,
+) -> Result<(), ExpenseError> {
+    account.name = validate_description(&account.name, limits)?;
+    if !account.opening_balance.is_finite() {
+        return Err(ExpenseError::Validation(
+            "The opening balance must be a finite number".to_string(),
+        ));
+    }
+    if find_account(accounts, &account.name).is_ok() {
+        return Err(ExpenseError::Validation(format!(
+            "Account '{}' already exists",
+            account.name
+        )));
+    }
+
+    accounts.push(account);
+    Ok(())
+}
+
+/// Removes an account. Expenses already paid from it keep the name.
+///
+/// # Arguments
+///
+/// * `accounts` - The accounts; updated in place.
+/// * `name` - The account to remove, in any case.
+pub fn remove_account(accounts: &mut Vec<Account>, name: &str) -> Result<Account, ExpenseError> {
+    let index = accounts
+        .iter()
+        .position(|account| account.name.eq_ignore_ascii_case(name.trim()))
+        .ok_or_else(|| unknown_account(name))?;
+
+    Ok(accounts.remove(index))
+}
+
+/// Finds an account by name.
+///
+/// # Arguments
+///
+/// * `accounts` - The accounts.
+/// * `name` - The account name, in any case.
+///
+/// # Returns
+///
+/// `ExpenseError::NotFound` if there is no such account, so expenses cannot be
+/// filed under a misspelt one.
+pub fn find_account<'a>(accounts: &'a [Account], name: &str) -> Result<&'a Account, ExpenseError> {
+    accounts
+        .iter()
+        .find(|account| account.name.eq_ignore_ascii_case(name.trim()))
+        .ok_or_else(|| unknown_account(name))
+}
+
+/// Computes the running balance of every account.
+///
+/// # Arguments
+///
+/// * `accounts` - The accounts.
+/// * `expenses` - The ledger.
+///
+/// # Returns
+///
+/// One balance per account, in the order the accounts were added.
+pub fn balances(accounts: &[Account], expenses: &[Expense]) -> Vec<Balance> {
+    accounts
+        .iter()
+        .map(|account| {
+            let is = |name: &Option<String>| name.as_deref() == Some(account.name.as_str());
+            let (mut income, mut spent, mut transferred) = (0.0, 0.0, 0.0);
+            for expense in expenses {
+                match expense.kind {
+                    TransactionKind::Expense if is(&expense.account) => spent += expense.amount,
+                    TransactionKind::Income if is(&expense.account) => income += expense.amount,
+                    TransactionKind::Transfer => {
+                        if is(&expense.account) {
+                            transferred -= expense.amount;
+                        }
+                        if is(&expense.to_account) {
+                            transferred += expense.amount;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Balance {
+                account: account.name.clone(),
+                kind: account.kind,
+                opening_balance: account.opening_balance,
+                income,
+                spent,
+                transferred,
+                balance: account.opening_balance + income - spent + transferred,
+            }
+        })
+        .collect()
+}
+
+fn unknown_account(name: &str) -> ExpenseError {
+    ExpenseError::NotFound(format!("No account named '{}'", name.trim()))
+}
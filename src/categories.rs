@@ -0,0 +1,157 @@
+use crate::error::ExpenseError;
+use crate::files::write_atomic;
+use crate::paths::data_file;
+use crate::validation::{validate_description, Limits};
+use crate::{map_category, read_to_string_or, Category, Expense};
+use std::collections::HashSet;
+use std::io;
+
+/// The built-in categories, as accepted by `--category`.
+pub const BUILT_IN: [&str; 4] = ["food", "transportation", "entertainment", "other"];
+
+/// Loads the current profile's custom categories from `categories.json`.
+///
+/// # Returns
+///
+/// The category names, empty if none have been defined.
+pub fn load_categories() -> Result<Vec<String>, ExpenseError> {
+    let categories_file = data_file("categories.json");
+    let categories = read_to_string_or(&categories_file, "[]")?;
+
+    serde_json::from_str(&categories).map_err(|e| ExpenseError::corrupt(&categories_file, e))
+}
+
+/// Saves the current profile's custom categories to `categories.json`.
+///
+/// # Arguments
+///
+/// * `categories` - The category names.
+pub fn save_categories(categories: &[String]) -> Result<(), ExpenseError> {
+    let contents = serde_json::to_vec(categories).map_err(io::Error::from)?;
+
+    write_atomic(data_file("categories.json"), &contents)
+}
+
+/// Adds a custom category.
+///
+/// # Arguments
+///
+/// * `categories` - The existing custom categories; updated in place.
+/// * `name` - The new category.
+/// * `limits` - The limits the name is checked against.
+///
+/// # Returns
+///
+/// `ExpenseError::Validation` if the name is empty, built in or already defined.
+pub fn add_category(
+    categories: &mut Vec<String>,
+    name: &str,
+    limits: &Limits,
+) -> Result<(), ExpenseError> {
+    let name = validate_description(name, limits)?;
+    let taken = BUILT_IN
+        .iter()
+        .any(|built_in| built_in.eq_ignore_ascii_case(&name))
+        || categories
+            .iter()
+            .any(|category| category.eq_ignore_ascii_case(&name));
+    if taken {
+        return Err(ExpenseError::Validation(format!(
+            "Category '{}' already exists",
+            name
+        )));
+    }
+
+    categories.push(name);
+    Ok(())
+}
+
+/// Removes a custom category. Expenses already filed under it keep the name.
+///
+/// # Arguments
+///
+/// * `categories` - The custom categories; updated in place.
+/// * `name` - The category to remove, in any case.
+///
+/// # Returns
+///
+/// `ExpenseError::NotFound` if no such custom category exists.
+pub fn remove_category(categories: &mut Vec<String>, name: &str) -> Result<(), ExpenseError> {
+    let count = categories.len();
+    categories.retain(|category| !category.eq_ignore_ascii_case(name.trim()));
+    if categories.len() == count {
+        return Err(ExpenseError::NotFound(format!(
+            "No custom category named '{}'",
+            name
+        )));
+    }
+
+    Ok(())
+}
+
+/// Maps a category name to a `Category`, including the profile's custom ones.
+///
+/// # Arguments
+///
+/// * `category` - The name given on the command line.
+/// * `custom` - The profile's custom categories.
+///
+/// # Returns
+///
+/// The matching custom category, or whatever `map_category` makes of the name.
+pub fn resolve_category(category: &str, custom: &[String]) -> Category {
+    match custom
+        .iter()
+        .find(|name| name.eq_ignore_ascii_case(category.trim()))
+    {
+        Some(name) => Category::Custom(name.clone()),
+        None => map_category(category),
+    }
+}
+
+/// Suggests a category for a new expense from earlier ones with a similar description.
+///
+/// Descriptions are compared by their words of three or more letters, ignoring
+/// case; each earlier expense votes for its category with the share of words
+/// the two descriptions have in common.
+///
+/// # Arguments
+///
+/// * `expenses` - The earlier expenses.
+/// * `description` - The description of the new expense.
+///
+/// # Returns
+///
+/// The category with the most votes, or `None` if no earlier description shares a word.
+pub fn suggest_category(expenses: &[Expense], description: &str) -> Option<Category> {
+    let words = |text: &str| -> HashSet<String> {
+        text.to_lowercase()
+            .split(|character: char| !character.is_alphanumeric())
+            .filter(|word| word.chars().count() >= 3)
+            .map(String::from)
+            .collect()
+    };
+    let wanted = words(description);
+
+    let mut votes: Vec<(Category, f64)> = Vec::new();
+    for expense in expenses.iter().filter(|expense| expense.is_spending()) {
+        let theirs = words(&expense.description);
+        let common = wanted.intersection(&theirs).count();
+        if common == 0 {
+            continue;
+        }
+        let vote = common as f64 / wanted.union(&theirs).count() as f64;
+        match votes
+            .iter_mut()
+            .find(|(category, _)| *category == expense.category)
+        {
+            Some((_, total)) => *total += vote,
+            None => votes.push((expense.category.clone(), vote)),
+        }
+    }
+
+    votes
+        .into_iter()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(category, _)| category)
+}
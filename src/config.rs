@@ -0,0 +1,377 @@
+use crate::error::ExpenseError;
+use crate::files::write_atomic;
+use crate::paths::config_file;
+use crate::read_to_string_or;
+use crate::store::Backend;
+use crate::validation::Limits;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{NaiveDate, NaiveDateTime, Weekday};
+use comfy_table::{presets, Table};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::fs;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+/// The settings that can be read and changed with `config get` and `config set`.
+pub const KEYS: [&str; 12] = [
+    "currency",
+    "default_category",
+    "date_format",
+    "table_style",
+    "week_start",
+    "budget_thresholds",
+    "backend",
+    "limits.max_amount",
+    "limits.max_description_len",
+    "limits.max_note_len",
+    "limits.earliest_date",
+    "limits.max_days_ahead",
+];
+
+static CURRENT: RwLock<Option<Config>> = RwLock::new(None);
+
+/// How tables are drawn.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TableStyle {
+    /// ASCII borders, the `comfy_table` default.
+    #[default]
+    Ascii,
+    /// Unicode box-drawing borders.
+    Utf8,
+    /// A Markdown table.
+    Markdown,
+    /// No borders at all.
+    Plain,
+}
+
+/// The first day of the week, used by `summary --week`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WeekStart {
+    #[default]
+    Monday,
+    Sunday,
+}
+
+/// User preferences read from `config.toml`.
+///
+/// Every key is optional in the file; missing keys keep their defaults.
+/// Command-line flags and environment variables take precedence.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    /// Shown before amounts in tables, e.g. `$`.
+    pub currency: String,
+    /// The category used by `add` when `--category` is not given.
+    pub default_category: String,
+    /// `chrono` format used for dates in tables.
+    pub date_format: String,
+    pub table_style: TableStyle,
+    pub week_start: WeekStart,
+    /// Percentages of the monthly budget that trigger a warning when crossed by `add`.
+    pub budget_thresholds: Vec<u32>,
+    /// The storage backend used when `--backend` is not given.
+    pub backend: Backend,
+    /// The limits every new or changed expense is checked against.
+    pub limits: Limits,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            currency: String::new(),
+            default_category: "other".to_string(),
+            date_format: "%Y-%m-%d %H:%M:%S".to_string(),
+            table_style: TableStyle::default(),
+            week_start: WeekStart::default(),
+            budget_thresholds: vec![80, 100],
+            backend: Backend::default(),
+            limits: Limits::default(),
+        }
+    }
+}
+
+impl FromStr for TableStyle {
+    type Err = String;
+
+    fn from_str(style: &str) -> Result<Self, Self::Err> {
+        match style.to_lowercase().as_str() {
+            "ascii" => Ok(TableStyle::Ascii),
+            "utf8" => Ok(TableStyle::Utf8),
+            "markdown" => Ok(TableStyle::Markdown),
+            "plain" => Ok(TableStyle::Plain),
+            _ => Err(format!(
+                "unknown table style '{}' (expected ascii, utf8, markdown or plain)",
+                style
+            )),
+        }
+    }
+}
+
+impl FromStr for WeekStart {
+    type Err = String;
+
+    fn from_str(day: &str) -> Result<Self, Self::Err> {
+        match day.to_lowercase().as_str() {
+            "monday" => Ok(WeekStart::Monday),
+            "sunday" => Ok(WeekStart::Sunday),
+            _ => Err(format!(
+                "unknown week start '{}' (expected monday or sunday)",
+                day
+            )),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration file.
+    ///
+    /// # Returns
+    ///
+    /// The configuration, all defaults if the file does not exist.
+    pub fn load() -> Result<Self, ExpenseError> {
+        let file = config_file();
+        let contents = read_to_string_or(&file, "")?;
+
+        toml::from_str(&contents).map_err(|e| ExpenseError::corrupt(&file, e))
+    }
+
+    /// Saves the configuration file, creating its directory if needed.
+    pub fn save(&self) -> Result<(), ExpenseError> {
+        let file = config_file();
+        if let Some(parent) = file.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let contents = toml::to_string(self).map_err(|e| {
+            ExpenseError::Validation(format!("Cannot write the configuration: {}", e))
+        })?;
+
+        write_atomic(file, contents.as_bytes())
+    }
+
+    /// Reads a setting as text.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - One of `KEYS`.
+    ///
+    /// # Returns
+    ///
+    /// The value as it would be passed to `set`, or `ExpenseError::NotFound` for an unknown key.
+    pub fn get(&self, key: &str) -> Result<String, ExpenseError> {
+        let value = match key {
+            "currency" => self.currency.clone(),
+            "default_category" => self.default_category.clone(),
+            "date_format" => self.date_format.clone(),
+            "table_style" => format!("{:?}", self.table_style).to_lowercase(),
+            "week_start" => format!("{:?}", self.week_start).to_lowercase(),
+            "budget_thresholds" => self
+                .budget_thresholds
+                .iter()
+                .map(|threshold| threshold.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+            "backend" => format!("{:?}", self.backend).to_lowercase(),
+            "limits.max_amount" => self.limits.max_amount.to_string(),
+            "limits.max_description_len" => self.limits.max_description_len.to_string(),
+            "limits.max_note_len" => self.limits.max_note_len.to_string(),
+            "limits.earliest_date" => self.limits.earliest_date.to_string(),
+            "limits.max_days_ahead" => self.limits.max_days_ahead.to_string(),
+            _ => return Err(unknown_key(key)),
+        };
+
+        Ok(value)
+    }
+
+    /// Changes a setting.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - One of `KEYS`.
+    /// * `value` - The new value; `budget_thresholds` takes a comma-separated list.
+    ///
+    /// # Returns
+    ///
+    /// `ExpenseError::NotFound` for an unknown key or `ExpenseError::Validation`
+    /// for a value the key does not accept.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ExpenseError> {
+        let invalid = |message: String| ExpenseError::Validation(format!("{}: {}", key, message));
+        match key {
+            "currency" => self.currency = value.to_string(),
+            "default_category" => self.default_category = value.to_string(),
+            "date_format" => {
+                if StrftimeItems::new(value).any(|item| item == Item::Error) {
+                    return Err(invalid(format!("invalid date format '{}'", value)));
+                }
+                self.date_format = value.to_string();
+            }
+            "table_style" => self.table_style = value.parse().map_err(invalid)?,
+            "week_start" => self.week_start = value.parse().map_err(invalid)?,
+            "budget_thresholds" => {
+                self.budget_thresholds = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|threshold| !threshold.is_empty())
+                    .map(|threshold| {
+                        threshold.parse::<u32>().map_err(|_| {
+                            invalid(format!("'{}' is not a whole percentage", threshold))
+                        })
+                    })
+                    .collect::<Result<_, _>>()?;
+                self.budget_thresholds.sort_unstable();
+            }
+            "backend" => self.backend = value.parse().map_err(invalid)?,
+            "limits.max_amount" => {
+                self.limits.max_amount = value
+                    .trim()
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|amount| amount.is_finite() && *amount > 0.0)
+                    .ok_or_else(|| invalid(format!("'{}' is not a positive amount", value)))?;
+            }
+            "limits.max_description_len" => {
+                self.limits.max_description_len = parse_length(value).map_err(invalid)?
+            }
+            "limits.max_note_len" => {
+                self.limits.max_note_len = parse_length(value).map_err(invalid)?
+            }
+            "limits.earliest_date" => {
+                self.limits.earliest_date = NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+                    .map_err(|_| invalid(format!("'{}' is not a YYYY-MM-DD date", value)))?;
+            }
+            "limits.max_days_ahead" => {
+                self.limits.max_days_ahead = value
+                    .trim()
+                    .parse::<u32>()
+                    .map_err(|_| invalid(format!("'{}' is not a whole number of days", value)))?
+                    .into();
+            }
+            _ => return Err(unknown_key(key)),
+        }
+
+        Ok(())
+    }
+
+    /// Formats an amount for display, with the configured currency.
+    ///
+    /// # Arguments
+    ///
+    /// * `amount` - The amount.
+    pub fn format_amount(&self, amount: f64) -> String {
+        format!("{}{}", self.currency, amount)
+    }
+
+    /// Reformats a stored `YYYY-MM-DD HH:MM:SS` timestamp with `date_format`.
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamp` - The stored timestamp; returned unchanged if it does not parse.
+    pub fn format_date(&self, timestamp: &str) -> String {
+        // A bad format in a hand-edited config.toml must not abort the listing.
+        let mut formatted = String::new();
+        match NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S") {
+            Ok(datetime) if write!(formatted, "{}", datetime.format(&self.date_format)).is_ok() => {
+                formatted
+            }
+            _ => timestamp.to_string(),
+        }
+    }
+
+    /// Creates a table drawn in the configured style.
+    pub fn table(&self) -> Table {
+        let mut table = Table::new();
+        match self.table_style {
+            TableStyle::Ascii => {}
+            TableStyle::Utf8 => {
+                table.load_preset(presets::UTF8_FULL);
+            }
+            TableStyle::Markdown => {
+                table.load_preset(presets::ASCII_MARKDOWN);
+            }
+            TableStyle::Plain => {
+                table.load_preset(presets::NOTHING);
+            }
+        }
+
+        table
+    }
+
+    /// The first day of the week containing `date`.
+    ///
+    /// # Arguments
+    ///
+    /// * `date` - Any day of the week.
+    pub fn week_of(&self, date: NaiveDate) -> NaiveDate {
+        let start = match self.week_start {
+            WeekStart::Monday => Weekday::Mon,
+            WeekStart::Sunday => Weekday::Sun,
+        };
+
+        date.week(start).first_day()
+    }
+
+    /// The highest budget threshold crossed by going from `before` to `after`.
+    ///
+    /// # Arguments
+    ///
+    /// * `before` - The month's spending before the change.
+    /// * `after` - The month's spending after the change.
+    /// * `budget` - The monthly budget; no threshold applies while it is not set.
+    ///
+    /// # Returns
+    ///
+    /// The crossed percentage, if any.
+    pub fn crossed_threshold(&self, before: f64, after: f64, budget: f64) -> Option<u32> {
+        if budget <= 0.0 {
+            return None;
+        }
+
+        self.budget_thresholds
+            .iter()
+            .rev()
+            .find(|&&threshold| {
+                let limit = budget * threshold as f64 / 100.0;
+                before < limit && after >= limit
+            })
+            .copied()
+    }
+}
+
+/// Makes `config` the configuration used for display.
+///
+/// # Arguments
+///
+/// * `config` - The loaded configuration.
+pub fn set_current(config: Config) {
+    if let Ok(mut current) = CURRENT.write() {
+        *current = Some(config);
+    }
+}
+
+/// The configuration used for display, or the defaults if none was set.
+pub fn current() -> Config {
+    CURRENT
+        .read()
+        .ok()
+        .and_then(|current| current.clone())
+        .unwrap_or_default()
+}
+
+fn parse_length(value: &str) -> Result<usize, String> {
+    value
+        .trim()
+        .parse::<usize>()
+        .ok()
+        .filter(|length| *length > 0)
+        .ok_or_else(|| format!("'{}' is not a positive number of characters", value))
+}
+
+fn unknown_key(key: &str) -> ExpenseError {
+    ExpenseError::NotFound(format!(
+        "Unknown configuration key '{}' (expected one of {})",
+        key,
+        KEYS.join(", ")
+    ))
+}
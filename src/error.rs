@@ -0,0 +1,134 @@
+use crate::query::QueryError;
+use std::path::Path;
+use std::{fmt, io};
+
+/// Process exit code for a successful run.
+pub const EXIT_OK: i32 = 0;
+/// Process exit code when an expense (or other record) does not exist.
+pub const EXIT_NOT_FOUND: i32 = 3;
+/// Process exit code when user input fails validation.
+pub const EXIT_VALIDATION: i32 = 4;
+/// Process exit code when a file cannot be read or written.
+pub const EXIT_STORAGE: i32 = 5;
+/// Process exit code when a query, date or stored file cannot be parsed.
+pub const EXIT_PARSE: i32 = 6;
+
+/// Errors returned by the expense tracker library.
+#[derive(Debug)]
+pub enum ExpenseError {
+    /// No expense matched the given ID or filter.
+    NotFound(String),
+    /// An amount was rejected, e.g. negative or not a number.
+    InvalidAmount(String),
+    /// A month outside `1..=12` was requested.
+    InvalidMonth(u32),
+    /// A date string could not be parsed.
+    InvalidDate(String),
+    /// Any other rejected input.
+    Validation(String),
+    /// A `--where` expression could not be parsed.
+    InvalidQuery { source: String, error: QueryError },
+    /// A stored file exists but does not contain what we expect.
+    Corrupt { file: String, message: String },
+    /// Reading or writing a file failed.
+    Storage(io::Error),
+}
+
+impl ExpenseError {
+    /// A stable, machine-readable code for the error.
+    ///
+    /// # Returns
+    ///
+    /// The code emitted in JSON error objects, e.g. `not_found`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ExpenseError::NotFound(_) => "not_found",
+            ExpenseError::InvalidAmount(_) => "invalid_amount",
+            ExpenseError::InvalidMonth(_) => "invalid_month",
+            ExpenseError::InvalidDate(_) => "invalid_date",
+            ExpenseError::Validation(_) => "validation",
+            ExpenseError::InvalidQuery { .. } => "invalid_query",
+            ExpenseError::Corrupt { .. } => "corrupt_data",
+            ExpenseError::Storage(_) => "storage",
+        }
+    }
+
+    /// The process exit code for the error.
+    ///
+    /// # Returns
+    ///
+    /// One of `EXIT_NOT_FOUND`, `EXIT_VALIDATION`, `EXIT_STORAGE` or `EXIT_PARSE`.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ExpenseError::NotFound(_) => EXIT_NOT_FOUND,
+            ExpenseError::InvalidAmount(_)
+            | ExpenseError::InvalidMonth(_)
+            | ExpenseError::Validation(_) => EXIT_VALIDATION,
+            ExpenseError::Storage(_) => EXIT_STORAGE,
+            ExpenseError::InvalidDate(_)
+            | ExpenseError::InvalidQuery { .. }
+            | ExpenseError::Corrupt { .. } => EXIT_PARSE,
+        }
+    }
+
+    /// The error message with a caret under the bad token for query errors.
+    ///
+    /// # Returns
+    ///
+    /// A possibly multi-line message for terminal output.
+    pub fn pretty(&self) -> String {
+        match self {
+            ExpenseError::InvalidQuery { source, error } => {
+                format!("Invalid --where expression: {}", error.pretty(source))
+            }
+            _ => self.to_string(),
+        }
+    }
+
+    /// Wraps a JSON or TOML error raised while reading `file`.
+    pub(crate) fn corrupt(file: impl AsRef<Path>, error: impl fmt::Display) -> Self {
+        ExpenseError::Corrupt {
+            file: file.as_ref().display().to_string(),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ExpenseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpenseError::NotFound(message)
+            | ExpenseError::InvalidAmount(message)
+            | ExpenseError::Validation(message) => write!(f, "{}", message),
+            ExpenseError::InvalidMonth(month) => write!(
+                f,
+                "Invalid month {}. Month should be between 1 and 12",
+                month
+            ),
+            ExpenseError::InvalidDate(date) => write!(f, "Unable to parse the date '{}'", date),
+            ExpenseError::InvalidQuery { error, .. } => {
+                write!(f, "Invalid --where expression: {}", error)
+            }
+            ExpenseError::Corrupt { file, message } => {
+                write!(f, "{} is corrupt: {}", file, message)
+            }
+            ExpenseError::Storage(error) => write!(f, "Storage error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for ExpenseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ExpenseError::InvalidQuery { error, .. } => Some(error),
+            ExpenseError::Storage(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ExpenseError {
+    fn from(error: io::Error) -> Self {
+        ExpenseError::Storage(error)
+    }
+}
@@ -0,0 +1,234 @@
+use crate::error::ExpenseError;
+use crate::files::write_atomic;
+use crate::journal::diff;
+use crate::paths::data_file;
+use crate::{convert_from_system_time, read_to_string_or, Expense};
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::time::SystemTime;
+
+/// A snapshot is written after this many events have been appended since the last one.
+pub const SNAPSHOT_INTERVAL: u64 = 100;
+
+/// A change to the ledger, as stored in the event log.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum Event {
+    ExpenseAdded { expense: Expense },
+    ExpenseUpdated { expense: Expense },
+    ExpenseDeleted { id: String },
+    BudgetSet { budget: f64 },
+}
+
+/// An event with its sequence number and time, one per line of `events.jsonl`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Record {
+    pub seq: u64,
+    pub at: String,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+/// The ledger state after applying every event up to `seq`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    pub seq: u64,
+    pub expenses: Vec<Expense>,
+    pub budget: f64,
+}
+
+/// The result of `compact`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Compaction {
+    pub seq: u64,
+    pub events_removed: usize,
+}
+
+impl Snapshot {
+    /// Applies an event to the state.
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - The event to apply.
+    pub fn apply(&mut self, record: &Record) {
+        match &record.event {
+            Event::ExpenseAdded { expense } => self.expenses.push(expense.clone()),
+            Event::ExpenseUpdated { expense } => {
+                if let Some(existing) = self.expenses.iter_mut().find(|e| e.id == expense.id) {
+                    *existing = expense.clone();
+                }
+            }
+            Event::ExpenseDeleted { id } => self.expenses.retain(|expense| &expense.id != id),
+            Event::BudgetSet { budget } => self.budget = *budget,
+        }
+        self.seq = record.seq;
+    }
+}
+
+/// Turns the difference between two ledger states into events.
+///
+/// # Arguments
+///
+/// * `before` - The ledger before the change.
+/// * `after` - The ledger after the change.
+///
+/// # Returns
+///
+/// The events that replay `before` into `after`.
+pub fn events_for_changes(before: &[Expense], after: &[Expense]) -> Vec<Event> {
+    diff(before, after)
+        .into_iter()
+        .filter_map(|change| match (change.before, change.after) {
+            (None, Some(expense)) => Some(Event::ExpenseAdded { expense }),
+            (Some(_), Some(expense)) => Some(Event::ExpenseUpdated { expense }),
+            (Some(expense), None) => Some(Event::ExpenseDeleted { id: expense.id }),
+            (None, None) => None,
+        })
+        .collect()
+}
+
+/// Reads every complete event from `events.jsonl`.
+///
+/// A trailing line without a newline is the remains of an interrupted write and
+/// is ignored.
+///
+/// # Returns
+///
+/// The events in the order they were appended.
+pub fn read_events() -> Result<Vec<Record>, ExpenseError> {
+    let events_file = data_file("events.jsonl");
+    let contents = read_to_string_or(&events_file, "")?;
+    let complete = match contents.rfind('\n') {
+        Some(end) => &contents[..end],
+        None => "",
+    };
+
+    complete
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(|e| ExpenseError::corrupt(&events_file, e)))
+        .collect()
+}
+
+/// Reads the latest snapshot from `snapshot.json`.
+///
+/// # Returns
+///
+/// The snapshot, or `None` if none has been written yet.
+pub fn read_snapshot() -> Result<Option<Snapshot>, ExpenseError> {
+    let snapshot_file = data_file("snapshot.json");
+    let contents = read_to_string_or(&snapshot_file, "")?;
+    if contents.trim().is_empty() {
+        return Ok(None);
+    }
+
+    serde_json::from_str(&contents)
+        .map(Some)
+        .map_err(|e| ExpenseError::corrupt(&snapshot_file, e))
+}
+
+/// Rebuilds the current state from the latest snapshot and the events after it.
+///
+/// # Arguments
+///
+/// * `initial` - Produces the state to start from when there is no snapshot yet.
+///
+/// # Returns
+///
+/// The current state.
+pub fn load_state<F>(initial: F) -> Result<Snapshot, ExpenseError>
+where
+    F: FnOnce() -> Result<Snapshot, ExpenseError>,
+{
+    let mut state = match read_snapshot()? {
+        Some(snapshot) => snapshot,
+        None => initial()?,
+    };
+    for record in read_events()? {
+        if record.seq > state.seq {
+            state.apply(&record);
+        }
+    }
+
+    Ok(state)
+}
+
+/// Appends events to the log, writing a snapshot every `SNAPSHOT_INTERVAL` events.
+///
+/// # Arguments
+///
+/// * `state` - The state the events apply to; updated in place.
+/// * `events` - The events to append.
+pub fn append_events(state: &mut Snapshot, events: Vec<Event>) -> Result<(), ExpenseError> {
+    if events.is_empty() {
+        return Ok(());
+    }
+
+    // The first write pins down the state we started from, e.g. an imported expenses.json.
+    let snapshot_seq = match read_snapshot()? {
+        Some(snapshot) => snapshot.seq,
+        None => {
+            write_snapshot(state)?;
+            state.seq
+        }
+    };
+
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(data_file("events.jsonl"))?;
+    let mut writer = io::BufWriter::new(file);
+    let at = convert_from_system_time(SystemTime::now());
+    for event in events {
+        let record = Record {
+            seq: state.seq + 1,
+            at: at.clone(),
+            event,
+        };
+        serde_json::to_writer(&mut writer, &record).map_err(io::Error::from)?;
+        writer.write_all(b"\n")?;
+        state.apply(&record);
+    }
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+
+    if state.seq - snapshot_seq >= SNAPSHOT_INTERVAL {
+        write_snapshot(state)?;
+    }
+
+    Ok(())
+}
+
+/// Writes a snapshot of the current state and drops the events it covers.
+///
+/// # Arguments
+///
+/// * `state` - The current state.
+///
+/// # Returns
+///
+/// The snapshot sequence number and how many events were removed from the log.
+pub fn compact(state: &Snapshot) -> Result<Compaction, ExpenseError> {
+    let events = read_events()?;
+    write_snapshot(state)?;
+
+    let remaining: Vec<&Record> = events.iter().filter(|r| r.seq > state.seq).collect();
+    let mut contents = String::new();
+    for record in &remaining {
+        contents.push_str(&serde_json::to_string(record).map_err(io::Error::from)?);
+        contents.push('\n');
+    }
+    write_atomic(data_file("events.jsonl"), contents.as_bytes())?;
+
+    Ok(Compaction {
+        seq: state.seq,
+        events_removed: events.len() - remaining.len(),
+    })
+}
+
+fn write_snapshot(state: &Snapshot) -> Result<(), ExpenseError> {
+    let contents = serde_json::to_vec(state).map_err(io::Error::from)?;
+
+    write_atomic(data_file("snapshot.json"), &contents)
+}
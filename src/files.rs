@@ -0,0 +1,88 @@
+use crate::error::ExpenseError;
+use crate::paths::{data_dir, data_file};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+/// Holds the advisory lock on the ledger until dropped.
+#[derive(Debug)]
+pub struct LedgerLock {
+    _file: File,
+}
+
+/// Takes the advisory lock that serializes access to the ledger.
+///
+/// Commands that change the ledger take the lock exclusively so that two
+/// concurrent runs wait for each other instead of overwriting each other's
+/// changes; read-only commands share it. The lock lives on `expenses.lock` in
+/// the data directory rather than on the ledger itself because atomic writes
+/// replace the ledger file.
+///
+/// # Arguments
+///
+/// * `exclusive` - Whether the lock is needed for writing.
+///
+/// # Returns
+///
+/// The held lock, released when it goes out of scope.
+pub fn lock_ledger(exclusive: bool) -> Result<LedgerLock, ExpenseError> {
+    fs::create_dir_all(data_dir())?;
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(data_file("expenses.lock"))?;
+
+    if exclusive {
+        file.lock()?;
+    } else {
+        file.lock_shared()?;
+    }
+
+    Ok(LedgerLock { _file: file })
+}
+
+/// Replaces a file's contents atomically.
+///
+/// The contents are written to a temporary file next to `path`, flushed to
+/// disk, and renamed over `path`, so readers see either the old or the new
+/// file and a crash mid-write never leaves a truncated ledger behind.
+///
+/// # Arguments
+///
+/// * `path` - The file to replace.
+/// * `contents` - The new contents.
+///
+/// # Returns
+///
+/// `Ok` once the new contents are durable, or an `ExpenseError::Storage`.
+pub fn write_atomic(path: impl AsRef<Path>, contents: &[u8]) -> Result<(), ExpenseError> {
+    let path = path.as_ref();
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let temporary = directory.join(format!(".{}.{}.tmp", file_name, std::process::id()));
+
+    let written = File::create(&temporary).and_then(|mut file| {
+        file.write_all(contents)?;
+        file.sync_all()
+    });
+    if let Err(error) = written.and_then(|_| fs::rename(&temporary, path)) {
+        let _ = fs::remove_file(&temporary);
+        return Err(error.into());
+    }
+
+    // Make the rename itself durable. Directories cannot be opened this way on
+    // every platform, so this is best effort.
+    if let Ok(directory) = File::open(directory) {
+        let _ = directory.sync_all();
+    }
+
+    Ok(())
+}
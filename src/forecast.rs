@@ -0,0 +1,118 @@
+use crate::recurring::Template;
+use crate::{sum_amounts, Expense};
+use chrono::{Datelike, Days, Months, NaiveDate};
+use serde::Serialize;
+
+/// The projected spending for the rest of a month.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Forecast {
+    /// The month, as `YYYY-MM`.
+    pub month: String,
+    /// Spent so far this month.
+    pub actual: f64,
+    /// Recurring expenses still scheduled this month.
+    pub scheduled: f64,
+    /// Other spending expected from this month's daily average so far.
+    pub trend: f64,
+    /// `actual + scheduled + trend`.
+    pub projected: f64,
+    pub budget: f64,
+    /// Whether `projected` exceeds a budget that is set.
+    pub over_budget: bool,
+}
+
+/// The recurring expenses falling due after `today`, within `days` days.
+///
+/// # Arguments
+///
+/// * `templates` - The recurring templates.
+/// * `today` - The current date, which is not included.
+/// * `days` - How far ahead to look.
+///
+/// # Returns
+///
+/// One expense per occurrence, dated on the occurrence and carrying the
+/// template's ID as its ID, ordered by date.
+pub fn upcoming(templates: &[Template], today: NaiveDate, days: u32) -> Vec<Expense> {
+    let until = today
+        .checked_add_days(Days::new(days.into()))
+        .unwrap_or(today);
+    let mut expenses: Vec<Expense> = templates
+        .iter()
+        .flat_map(|template| {
+            template
+                .occurrences(Some(today), until)
+                .into_iter()
+                .map(|date| {
+                    let mut expense = template.expense_on(date);
+                    expense.id = template.id.clone();
+                    expense
+                })
+        })
+        .collect();
+    expenses.sort_by(|a, b| a.added_at.cmp(&b.added_at));
+
+    expenses
+}
+
+/// Forecasts the spending for the month containing `today`.
+///
+/// The forecast adds three parts: what has been spent so far, the recurring
+/// expenses still scheduled before the month ends, and a trend for everything
+/// else, which assumes the rest of the month continues at this month's average
+/// daily rate of non-recurring spending.
+///
+/// # Arguments
+///
+/// * `expenses` - The ledger.
+/// * `templates` - The recurring templates.
+/// * `budget` - The monthly budget, or 0 if none is set.
+/// * `today` - The current date.
+///
+/// # Returns
+///
+/// The forecast for the month.
+pub fn forecast(
+    expenses: &[Expense],
+    templates: &[Template],
+    budget: f64,
+    today: NaiveDate,
+) -> Forecast {
+    let month = today.format("%Y-%m").to_string();
+    let month_end = today
+        .with_day(1)
+        .and_then(|first| first.checked_add_months(Months::new(1)))
+        .and_then(|next| next.pred_opt())
+        .unwrap_or(today);
+
+    let this_month: Vec<&Expense> = expenses
+        .iter()
+        .filter(|expense| expense.is_spending() && expense.added_at.starts_with(&month))
+        .collect();
+    let actual = sum_amounts(this_month.iter().map(|expense| expense.amount));
+    let one_off = sum_amounts(
+        this_month
+            .iter()
+            .filter(|expense| expense.recurring.is_none())
+            .map(|expense| expense.amount),
+    );
+
+    let scheduled = sum_amounts(
+        upcoming(templates, today, (month_end - today).num_days() as u32)
+            .iter()
+            .map(|expense| expense.amount),
+    );
+    let remaining_days = (month_end - today).num_days() as f64;
+    let trend = one_off / today.day() as f64 * remaining_days;
+    let projected = actual + scheduled + trend;
+
+    Forecast {
+        month,
+        actual,
+        scheduled,
+        trend,
+        projected,
+        budget,
+        over_budget: budget > 0.0 && projected > budget,
+    }
+}
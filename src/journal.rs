@@ -0,0 +1,271 @@
+use crate::error::ExpenseError;
+use crate::files::write_atomic;
+use crate::paths::data_file;
+use crate::{convert_from_system_time, read_to_string_or, Expense};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::time::SystemTime;
+
+/// How many operations are kept in the journal before the oldest are dropped.
+pub const MAX_OPERATIONS: usize = 200;
+
+/// The kind of change an operation made to the ledger.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum OperationKind {
+    Add,
+    Update,
+    Delete,
+    Clear,
+}
+
+/// A single expense changed by an operation.
+///
+/// `before` is `None` for an added expense and `after` is `None` for a removed
+/// one. `index` is the position of the expense in the ledger where it exists:
+/// before the operation for removals, after it for additions.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Change {
+    pub index: usize,
+    pub before: Option<Expense>,
+    pub after: Option<Expense>,
+}
+
+/// A recorded add, update, delete or clear.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Operation {
+    pub kind: OperationKind,
+    pub at: String,
+    pub changes: Vec<Change>,
+}
+
+/// The operation journal backing `undo`, `redo` and `history`.
+///
+/// `operations[..cursor]` have been applied; `operations[cursor..]` were undone
+/// and can be redone until a new operation is recorded.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Journal {
+    pub operations: Vec<Operation>,
+    pub cursor: usize,
+}
+
+impl Operation {
+    /// A one-line description of the operation, e.g. `Delete 3 expenses`.
+    ///
+    /// # Returns
+    ///
+    /// The description shown by `history`, `undo` and `redo`.
+    pub fn summary(&self) -> String {
+        let verb = format!("{:?}", self.kind);
+        match self.changes.as_slice() {
+            [change] => {
+                let expense = change
+                    .after
+                    .as_ref()
+                    .or(change.before.as_ref())
+                    .map(|expense| {
+                        format!(
+                            "{} '{}' ({})",
+                            expense.id, expense.description, expense.amount
+                        )
+                    })
+                    .unwrap_or_default();
+                format!("{} {}", verb, expense)
+            }
+            changes => format!("{} {} expenses", verb, changes.len()),
+        }
+    }
+}
+
+impl Journal {
+    /// Loads the journal from `journal.json` next to the ledger.
+    ///
+    /// # Returns
+    ///
+    /// The journal, empty if the file does not exist yet.
+    pub fn load() -> Result<Self, ExpenseError> {
+        let journal_file = data_file("journal.json");
+        let journal = read_to_string_or(&journal_file, "")?;
+        if journal.trim().is_empty() {
+            return Ok(Self::default());
+        }
+
+        serde_json::from_str(&journal).map_err(|e| ExpenseError::corrupt(&journal_file, e))
+    }
+
+    /// Saves the journal to `journal.json` next to the ledger.
+    pub fn save(&self) -> Result<(), ExpenseError> {
+        let journal_file = data_file("journal.json");
+        let contents = serde_json::to_vec(self).map_err(io::Error::from)?;
+
+        write_atomic(journal_file, &contents)
+    }
+
+    /// Records the difference between two ledger states as a new operation.
+    ///
+    /// Any undone operations are discarded, as they can no longer be redone.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - The kind of operation.
+    /// * `before` - The ledger before the operation.
+    /// * `after` - The ledger after the operation.
+    ///
+    /// # Returns
+    ///
+    /// `true` if anything changed and an operation was recorded.
+    pub fn record(&mut self, kind: OperationKind, before: &[Expense], after: &[Expense]) -> bool {
+        let changes = diff(before, after);
+        if changes.is_empty() {
+            return false;
+        }
+
+        self.operations.truncate(self.cursor);
+        self.operations.push(Operation {
+            kind,
+            at: convert_from_system_time(SystemTime::now()),
+            changes,
+        });
+        if self.operations.len() > MAX_OPERATIONS {
+            let excess = self.operations.len() - MAX_OPERATIONS;
+            self.operations.drain(..excess);
+        }
+        self.cursor = self.operations.len();
+
+        true
+    }
+
+    /// Reverts the most recent applied operation.
+    ///
+    /// # Arguments
+    ///
+    /// * `expenses` - The ledger to revert.
+    ///
+    /// # Returns
+    ///
+    /// The reverted operation, `ExpenseError::NotFound` if there is nothing to
+    /// undo, or `ExpenseError::Validation` if the ledger no longer matches it.
+    pub fn undo(&mut self, expenses: &mut Vec<Expense>) -> Result<Operation, ExpenseError> {
+        if self.cursor == 0 {
+            return Err(ExpenseError::NotFound("Nothing to undo".to_string()));
+        }
+
+        let operation = self.operations[self.cursor - 1].clone();
+        apply(expenses, &operation.changes, false)?;
+        self.cursor -= 1;
+
+        Ok(operation)
+    }
+
+    /// Re-applies the most recently undone operation.
+    ///
+    /// # Arguments
+    ///
+    /// * `expenses` - The ledger to update.
+    ///
+    /// # Returns
+    ///
+    /// The re-applied operation, `ExpenseError::NotFound` if there is nothing to
+    /// redo, or `ExpenseError::Validation` if the ledger no longer matches it.
+    pub fn redo(&mut self, expenses: &mut Vec<Expense>) -> Result<Operation, ExpenseError> {
+        let Some(operation) = self.operations.get(self.cursor).cloned() else {
+            return Err(ExpenseError::NotFound("Nothing to redo".to_string()));
+        };
+
+        apply(expenses, &operation.changes, true)?;
+        self.cursor += 1;
+
+        Ok(operation)
+    }
+}
+
+/// Computes the changes turning `before` into `after`, matching expenses by ID.
+///
+/// # Arguments
+///
+/// * `before` - The ledger before the operation.
+/// * `after` - The ledger after the operation.
+///
+/// # Returns
+///
+/// One `Change` per added, updated or removed expense.
+pub fn diff(before: &[Expense], after: &[Expense]) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    for (index, old) in before.iter().enumerate() {
+        match after.iter().find(|expense| expense.id == old.id) {
+            None => changes.push(Change {
+                index,
+                before: Some(old.clone()),
+                after: None,
+            }),
+            Some(new) if new != old => changes.push(Change {
+                index,
+                before: Some(old.clone()),
+                after: Some(new.clone()),
+            }),
+            Some(_) => {}
+        }
+    }
+
+    for (index, new) in after.iter().enumerate() {
+        if !before.iter().any(|expense| expense.id == new.id) {
+            changes.push(Change {
+                index,
+                before: None,
+                after: Some(new.clone()),
+            });
+        }
+    }
+
+    changes
+}
+
+/// Applies changes forwards (`before` -> `after`) or backwards.
+fn apply(
+    expenses: &mut Vec<Expense>,
+    changes: &[Change],
+    forward: bool,
+) -> Result<(), ExpenseError> {
+    let sides = |change: &Change| {
+        if forward {
+            (change.before.clone(), change.after.clone())
+        } else {
+            (change.after.clone(), change.before.clone())
+        }
+    };
+
+    // Every expense we expect to find must still be in the ledger unchanged.
+    for change in changes {
+        if let (Some(from), _) = sides(change) {
+            if !expenses.contains(&from) {
+                return Err(ExpenseError::Validation(format!(
+                    "Expense {} changed since this operation; it can no longer be {}",
+                    from.id,
+                    if forward { "redone" } else { "undone" }
+                )));
+            }
+        }
+    }
+
+    let mut inserts = Vec::new();
+    for change in changes {
+        match sides(change) {
+            (Some(from), None) => expenses.retain(|expense| expense.id != from.id),
+            (Some(from), Some(to)) => {
+                if let Some(expense) = expenses.iter_mut().find(|expense| expense.id == from.id) {
+                    *expense = to;
+                }
+            }
+            (None, Some(to)) => inserts.push((change.index, to)),
+            (None, None) => {}
+        }
+    }
+
+    inserts.sort_by_key(|(index, _)| *index);
+    for (index, expense) in inserts {
+        let index = index.min(expenses.len());
+        expenses.insert(index, expense);
+    }
+
+    Ok(())
+}
@@ -1,16 +1,47 @@
+pub mod accounts; // Accounts and payment methods
+pub mod categories; // Custom categories of the current profile
+pub mod config; // User preferences from config.toml
+pub mod error; // Error type and exit codes
+pub mod events; // Append-only event log backend
+pub mod files; // Atomic writes and ledger locking
+pub mod forecast; // Upcoming recurring expenses and month-end forecasts
+pub mod journal; // Operation journal for undo and redo
+pub mod output; // Table, JSON, CSV and plain output
+pub mod paths; // Data directory and ledger file location
+pub mod payees; // Payee list with aliases and default categories
 pub mod pdb; // Postgres interactions
+pub mod profiles; // Named ledgers
+pub mod query; // Query language for --where filters
+pub mod receipts; // Receipt attachments
+pub mod recurring; // Recurring expense templates
+pub mod shared; // Shared expenses and settling up
+pub mod shell; // Line editing and completion for the interactive shell and prompts
+pub mod store; // Backend selection for loading and saving the ledger
 pub mod surrealdb; // SurrealDB
+pub mod tui; // Full-screen terminal UI
+pub mod validation; // Input validation shared by every write path
 
 use chrono::{DateTime, Datelike, NaiveDateTime, Utc};
-use comfy_table::Table;
+use config::Config;
+use error::ExpenseError;
+use files::write_atomic;
+use output::{csv_field, Group};
+use paths::{data_file, ledger_file};
+use query::Query;
 use rand::Rng;
+use receipts::Receipt;
 use serde::{Deserialize, Serialize};
+use shared::Shared;
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs::OpenOptions;
-use std::io::{self, stdin, stdout, Write};
+use std::io::{self, stderr, stdin, Write};
+use std::path::Path;
+use std::str::FromStr;
 use std::time::SystemTime;
 
 /// Represents an expense in the expense tracker.
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Expense {
     pub id: String,
     pub description: String,
@@ -18,6 +49,41 @@ pub struct Expense {
     pub category: Category,
     pub added_at: String,
     pub updated_at: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Who was paid, normalized through the payee list.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payee: Option<String>,
+    /// The account the expense was paid from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    /// Whether this is money spent, received or moved between accounts.
+    #[serde(default, skip_serializing_if = "TransactionKind::is_expense")]
+    pub kind: TransactionKind,
+    /// The account a transfer paid into; `account` is the one it came from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to_account: Option<String>,
+    /// The parts of the amount filed under different categories; they add up to `amount`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub splits: Vec<Split>,
+    /// Who paid and who owes what, for an expense shared with other people.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shared: Option<Shared>,
+    /// The receipt attached with `--receipt` or `attach`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receipt: Option<Receipt>,
+    /// A free-form note, which unlike the description may span several lines.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Where the expense was made, e.g. a shop or a city.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    /// User-defined fields set with `--field KEY=VALUE`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, String>,
+    /// The ID of the recurring template this expense was generated from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recurring: Option<String>,
 }
 
 /// Represents the category of an expense.
@@ -27,6 +93,78 @@ pub enum Category {
     Transportation,
     Entertainment,
     Other,
+    /// A category defined for the current profile, stored by name.
+    #[serde(untagged)]
+    Custom(String),
+}
+
+/// A part of an expense filed under its own category, e.g. the household
+/// goods on a supermarket receipt.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Split {
+    pub amount: f64,
+    pub category: Category,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// The direction of a ledger entry.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionKind {
+    /// Money spent; the only kind counted against the budget.
+    #[default]
+    Expense,
+    /// Money received, such as a salary, a refund or a sale.
+    Income,
+    /// Money moved from one account to another, e.g. paying off a card.
+    Transfer,
+    /// A payment between people settling shared expenses.
+    Settlement,
+}
+
+impl TransactionKind {
+    /// Whether this is the default kind, which is left out of the JSON files.
+    pub fn is_expense(&self) -> bool {
+        *self == TransactionKind::Expense
+    }
+}
+
+impl fmt::Display for TransactionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransactionKind::Expense => write!(f, "expense"),
+            TransactionKind::Income => write!(f, "income"),
+            TransactionKind::Transfer => write!(f, "transfer"),
+            TransactionKind::Settlement => write!(f, "settlement"),
+        }
+    }
+}
+
+impl FromStr for TransactionKind {
+    type Err = String;
+
+    fn from_str(kind: &str) -> Result<Self, Self::Err> {
+        match kind.to_lowercase().as_str() {
+            "expense" => Ok(TransactionKind::Expense),
+            "income" => Ok(TransactionKind::Income),
+            "transfer" => Ok(TransactionKind::Transfer),
+            "settlement" => Ok(TransactionKind::Settlement),
+            _ => Err(format!(
+                "unknown kind '{}' (expected expense, income, transfer or settlement)",
+                kind
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Category::Custom(name) => write!(f, "{}", name),
+            category => write!(f, "{:?}", category),
+        }
+    }
 }
 
 impl Expense {
@@ -39,9 +177,47 @@ impl Expense {
             category,
             added_at: convert_from_system_time(SystemTime::now()),
             updated_at: convert_from_system_time(SystemTime::now()),
+            tags: vec![],
+            payee: None,
+            account: None,
+            kind: TransactionKind::Expense,
+            to_account: None,
+            splits: vec![],
+            shared: None,
+            receipt: None,
+            note: None,
+            location: None,
+            fields: BTreeMap::new(),
+            recurring: None,
         }
     }
 
+    /// Whether the entry counts as spending, as opposed to income or a transfer.
+    pub fn is_spending(&self) -> bool {
+        self.kind == TransactionKind::Expense
+    }
+
+    /// Whether the entry is income.
+    pub fn is_income(&self) -> bool {
+        self.kind == TransactionKind::Income
+    }
+
+    /// Creates a transfer of `amount` between two accounts.
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The account the money leaves.
+    /// * `to` - The account the money goes to.
+    /// * `amount` - The amount moved.
+    pub fn transfer(from: String, to: String, amount: f64) -> Self {
+        let mut transfer = Expense::new(format!("Transfer to {}", to), amount, Category::Other);
+        transfer.kind = TransactionKind::Transfer;
+        transfer.account = Some(from);
+        transfer.to_account = Some(to);
+
+        transfer
+    }
+
     /// Updates an expense in the expense tracker.
     ///
     /// # Arguments
@@ -52,16 +228,16 @@ impl Expense {
     ///
     /// # Returns
     ///
-    /// The updated expense.
+    /// The updated expense, or an `ExpenseError` if it could not be saved.
     pub fn update(
         update_ready_expense: Expense,
         all_expenses: &mut [Expense],
         index: usize,
-    ) -> Self {
+    ) -> Result<Self, ExpenseError> {
         all_expenses[index] = update_ready_expense.clone();
-        save_expenses(all_expenses).unwrap();
+        save_expenses(all_expenses)?;
 
-        update_ready_expense
+        Ok(update_ready_expense)
     }
 }
 
@@ -105,36 +281,37 @@ pub fn convert_from_system_time(system_time: SystemTime) -> String {
 ///
 /// # Returns
 ///
-/// An `Ok` result if the expenses are successfully saved, or an `Err` containing a `Box<dyn std::error::Error>` otherwise.
-pub fn save_expenses(expenses: &[Expense]) -> Result<(), Box<dyn std::error::Error>> {
-    let file_path = "expenses.json";
-    let file = OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .create(true)
-        .open(file_path)?;
-
-    let mut writer = io::BufWriter::new(file);
-    serde_json::to_writer(&mut writer, expenses)?;
-    writer.write_all(b"\n")?;
+/// An `Ok` result if the expenses are successfully saved, or an `ExpenseError::Storage` otherwise.
+pub fn save_expenses(expenses: &[Expense]) -> Result<(), ExpenseError> {
+    let file_path = ledger_file();
 
-    writer.flush()?;
+    let mut contents = serde_json::to_vec(expenses).map_err(io::Error::from)?;
+    contents.push(b'\n');
 
-    Ok(())
+    write_atomic(file_path, &contents)
 }
 
-/// Exports the expenses to a CSV file.
+/// Exports the expenses to a CSV file, or to a JSON file if the name ends in `.json`.
 ///
 /// # Arguments
 ///
-/// * `file` - The path of the CSV file to export to.
+/// * `file` - The path of the CSV or JSON file to export to.
 /// * `all_expenses` - The array of all expenses to export.
 ///
 /// # Returns
 ///
-/// An `Ok` result if the expenses are successfully exported, or an `Err` containing an `io::Error` otherwise.
-pub fn export_expenses(file: &str, all_expenses: &Vec<Expense>) -> Result<(), io::Error> {
+/// An `Ok` result if the expenses are successfully exported, or an `ExpenseError::Storage` otherwise.
+pub fn export_expenses(file: &str, all_expenses: &Vec<Expense>) -> Result<(), ExpenseError> {
     let file_path = file;
+    if Path::new(file_path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+    {
+        let mut contents = serde_json::to_vec_pretty(all_expenses).map_err(io::Error::from)?;
+        contents.push(b'\n');
+        return write_atomic(file_path, &contents);
+    }
+
     let file = OpenOptions::new()
         .write(true)
         .truncate(true)
@@ -146,19 +323,22 @@ pub fn export_expenses(file: &str, all_expenses: &Vec<Expense>) -> Result<(), io
     // Write the header
     writeln!(
         _writer,
-        "ID,Description,Amount,Category,Added At,Updated At"
+        "ID,Description,Amount,Category,Added At,Updated At,Note,Location,Fields"
     )?;
 
     for expense in all_expenses {
         writeln!(
             _writer,
-            "{},{},{},{:?},{},{}",
+            "{},{},{},{},{},{},{},{},{}",
             expense.id,
-            expense.description,
+            csv_field(&expense.description),
             expense.amount,
-            expense.category,
+            csv_field(&expense.category.to_string()),
             expense.added_at,
-            expense.updated_at
+            expense.updated_at,
+            csv_field(expense.note.as_deref().unwrap_or_default()),
+            csv_field(expense.location.as_deref().unwrap_or_default()),
+            csv_field(&format_fields(&expense.fields))
         )?;
     }
 
@@ -171,19 +351,39 @@ pub fn export_expenses(file: &str, all_expenses: &Vec<Expense>) -> Result<(), io
 ///
 /// # Returns
 ///
-/// An `Ok` result containing a vector of expenses if the expenses are successfully loaded, or an `Err` containing a `Box<dyn std::error::Error>` otherwise.
-pub fn load_expenses() -> Result<Vec<Expense>, Box<dyn std::error::Error>> {
-    let expense_file = "expenses.json";
+/// An `Ok` result containing a vector of expenses if the expenses are successfully loaded,
+/// `ExpenseError::Corrupt` if the file is not valid JSON, or `ExpenseError::Storage` if it cannot be read.
+pub fn load_expenses() -> Result<Vec<Expense>, ExpenseError> {
+    let expense_file = ledger_file();
 
     // Load existing expense from expenses.json file or use empty array if file does
     // not exist
-    let expenses = std::fs::read_to_string(expense_file).unwrap_or_else(|_| "[]".to_string());
+    let expenses = read_to_string_or(&expense_file, "[]")?;
 
-    let all_expenses: Vec<Expense> = serde_json::from_str(&expenses)?;
+    let all_expenses: Vec<Expense> =
+        serde_json::from_str(&expenses).map_err(|e| ExpenseError::corrupt(&expense_file, e))?;
 
     Ok(all_expenses)
 }
 
+/// Reads a file, falling back to `default` when it does not exist.
+///
+/// # Arguments
+///
+/// * `path` - The file to read.
+/// * `default` - The contents to use if the file is missing.
+///
+/// # Returns
+///
+/// The file contents, or an `ExpenseError::Storage` for any other I/O error.
+pub fn read_to_string_or(path: impl AsRef<Path>, default: &str) -> Result<String, ExpenseError> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => Ok(contents),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(default.to_string()),
+        Err(error) => Err(error.into()),
+    }
+}
+
 /// Searches for an expense by its ID in the array of expenses.
 ///
 /// # Arguments
@@ -198,6 +398,334 @@ pub fn search_expense_by_id(expenses: &[Expense], id: &str) -> Option<usize> {
     expenses.iter().position(|expense| expense.id == id)
 }
 
+/// Filters expenses with an optional query.
+///
+/// # Arguments
+///
+/// * `expenses` - The array of expenses to filter.
+/// * `query` - The query to apply, or `None` to keep every expense.
+///
+/// # Returns
+///
+/// The expenses matching the query.
+pub fn filter_expenses(expenses: &[Expense], query: Option<&Query>) -> Vec<Expense> {
+    expenses
+        .iter()
+        .filter(|expense| query.is_none_or(|query| query.matches(expense)))
+        .cloned()
+        .collect()
+}
+
+/// Replaces every split expense with one entry per split line.
+///
+/// The entries keep the ID, date and other fields of their expense, take the
+/// amount and category of the split, and add the split's note to the
+/// description. Expenses without splits are kept as they are.
+///
+/// # Arguments
+///
+/// * `expenses` - The expenses to expand.
+///
+/// # Returns
+///
+/// The expanded expenses, in the original order.
+pub fn expand_splits(expenses: &[Expense]) -> Vec<Expense> {
+    expenses
+        .iter()
+        .flat_map(|expense| {
+            if expense.splits.is_empty() {
+                return vec![expense.clone()];
+            }
+            expense
+                .splits
+                .iter()
+                .map(|split| {
+                    let mut line = expense.clone();
+                    line.amount = split.amount;
+                    line.category = split.category.clone();
+                    if let Some(note) = &split.note {
+                        line.description = format!("{} ({})", expense.description, note);
+                    }
+                    line.splits = vec![];
+                    line
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Formats custom fields as `key=value` pairs separated by `; `.
+///
+/// # Arguments
+///
+/// * `fields` - The fields of an expense.
+///
+/// # Returns
+///
+/// The formatted fields, in key order; empty if there are none.
+pub fn format_fields(fields: &BTreeMap<String, String>) -> String {
+    fields
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Field used to order expenses in `list`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortKey {
+    Amount,
+    Date,
+    Category,
+    Description,
+    Payee,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        match key.to_lowercase().as_str() {
+            "amount" => Ok(SortKey::Amount),
+            "date" => Ok(SortKey::Date),
+            "category" => Ok(SortKey::Category),
+            "description" => Ok(SortKey::Description),
+            "payee" => Ok(SortKey::Payee),
+            _ => Err(format!(
+                "unknown sort key '{}' (expected amount, date, category, description or payee)",
+                key
+            )),
+        }
+    }
+}
+
+/// A column that can be shown in the expense table.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Column {
+    No,
+    Id,
+    Date,
+    Description,
+    Amount,
+    Category,
+    Updated,
+    Tags,
+    Payee,
+    Account,
+    Kind,
+    Receipt,
+    Note,
+    Location,
+    Fields,
+}
+
+impl Column {
+    /// The columns shown when none are requested.
+    pub const DEFAULT: [Column; 6] = [
+        Column::No,
+        Column::Id,
+        Column::Description,
+        Column::Amount,
+        Column::Category,
+        Column::Updated,
+    ];
+
+    /// The header of the column.
+    pub fn header(&self) -> &'static str {
+        match self {
+            Column::No => "No",
+            Column::Id => "ID",
+            Column::Date => "Date",
+            Column::Description => "Description",
+            Column::Amount => "Amount",
+            Column::Category => "Category",
+            Column::Updated => "Last Updated",
+            Column::Tags => "Tags",
+            Column::Payee => "Payee",
+            Column::Account => "Account",
+            Column::Kind => "Kind",
+            Column::Receipt => "Receipt",
+            Column::Note => "Note",
+            Column::Location => "Location",
+            Column::Fields => "Fields",
+        }
+    }
+
+    fn cell(&self, number: usize, expense: &Expense) -> String {
+        match self {
+            Column::No => number.to_string(),
+            Column::Id => expense.id.clone(),
+            Column::Date => expense.added_at.clone(),
+            Column::Description => expense.description.clone(),
+            Column::Amount => expense.amount.to_string(),
+            Column::Category => expense.category.to_string(),
+            Column::Updated => expense.updated_at.clone(),
+            Column::Tags => expense.tags.join(", "),
+            Column::Payee => expense.payee.clone().unwrap_or_default(),
+            Column::Account => match (&expense.account, &expense.to_account) {
+                (Some(from), Some(to)) => format!("{} -> {}", from, to),
+                (account, _) => account.clone().unwrap_or_default(),
+            },
+            Column::Kind => expense.kind.to_string(),
+            Column::Receipt => expense
+                .receipt
+                .as_ref()
+                .map(|receipt| receipt.name.clone())
+                .unwrap_or_default(),
+            Column::Note => expense.note.clone().unwrap_or_default(),
+            Column::Location => expense.location.clone().unwrap_or_default(),
+            Column::Fields => format_fields(&expense.fields),
+        }
+    }
+
+    /// The table cell for an expense, formatted with the user's configuration.
+    fn display(&self, number: usize, expense: &Expense, config: &Config) -> String {
+        match self {
+            Column::Date => config.format_date(&expense.added_at),
+            Column::Amount if !expense.is_spending() => {
+                format!("+{}", config.format_amount(expense.amount))
+            }
+            Column::Amount => config.format_amount(expense.amount),
+            Column::Updated => config.format_date(&expense.updated_at),
+            // Only the first line of a note fits in a table row.
+            Column::Note => match expense.note.as_deref().map(|note| note.split_once('\n')) {
+                Some(Some((first, _))) => format!("{} ...", first.trim_end()),
+                _ => self.cell(number, expense),
+            },
+            _ => self.cell(number, expense),
+        }
+    }
+}
+
+impl FromStr for Column {
+    type Err = String;
+
+    fn from_str(column: &str) -> Result<Self, Self::Err> {
+        match column.trim().to_lowercase().as_str() {
+            "no" => Ok(Column::No),
+            "id" => Ok(Column::Id),
+            "date" => Ok(Column::Date),
+            "description" => Ok(Column::Description),
+            "amount" => Ok(Column::Amount),
+            "category" => Ok(Column::Category),
+            "updated" => Ok(Column::Updated),
+            "tags" => Ok(Column::Tags),
+            "payee" => Ok(Column::Payee),
+            "account" => Ok(Column::Account),
+            "kind" => Ok(Column::Kind),
+            "receipt" => Ok(Column::Receipt),
+            "note" => Ok(Column::Note),
+            "location" => Ok(Column::Location),
+            "fields" => Ok(Column::Fields),
+            _ => Err(format!(
+                "unknown column '{}' (expected no, id, date, description, amount, category, updated, tags, payee, account, kind, receipt, note, location or fields)",
+                column
+            )),
+        }
+    }
+}
+
+/// Sorts expenses in place.
+///
+/// # Arguments
+///
+/// * `expenses` - The array of expenses to sort.
+/// * `key` - The field to sort by.
+/// * `descending` - Whether to reverse the order.
+pub fn sort_expenses(expenses: &mut [Expense], key: SortKey, descending: bool) {
+    expenses.sort_by(|a, b| {
+        let ordering = match key {
+            SortKey::Amount => a.amount.total_cmp(&b.amount),
+            SortKey::Date => a.added_at.cmp(&b.added_at),
+            SortKey::Category => a.category.to_string().cmp(&b.category.to_string()),
+            SortKey::Description => a
+                .description
+                .to_lowercase()
+                .cmp(&b.description.to_lowercase()),
+            SortKey::Payee => a
+                .payee
+                .as_deref()
+                .map(str::to_lowercase)
+                .cmp(&b.payee.as_deref().map(str::to_lowercase)),
+        };
+        if descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+}
+
+/// Field used to group expenses in `summary --group-by`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GroupBy {
+    Category,
+    Payee,
+    Account,
+}
+
+impl FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        match key.to_lowercase().as_str() {
+            "category" => Ok(GroupBy::Category),
+            "payee" => Ok(GroupBy::Payee),
+            "account" => Ok(GroupBy::Account),
+            _ => Err(format!(
+                "unknown grouping '{}' (expected category, payee or account)",
+                key
+            )),
+        }
+    }
+}
+
+/// Totals expenses per category, payee or account.
+///
+/// # Arguments
+///
+/// * `expenses` - The expenses to group.
+/// * `key` - The field to group by; expenses without a payee or account form the
+///   `(none)` group. Income is left out.
+///
+/// # Returns
+///
+/// One group per distinct value, largest total first.
+pub fn group_expenses(expenses: &[Expense], key: GroupBy) -> Vec<Group> {
+    let mut groups: Vec<Group> = Vec::new();
+    for expense in expenses.iter().filter(|expense| expense.is_spending()) {
+        let name = match key {
+            GroupBy::Category => expense.category.to_string(),
+            GroupBy::Payee => expense
+                .payee
+                .clone()
+                .unwrap_or_else(|| "(none)".to_string()),
+            GroupBy::Account => expense
+                .account
+                .clone()
+                .unwrap_or_else(|| "(none)".to_string()),
+        };
+        match groups.iter_mut().find(|group| group.name == name) {
+            Some(group) => {
+                group.count += 1;
+                group.total += expense.amount;
+            }
+            None => groups.push(Group {
+                name,
+                count: 1,
+                total: expense.amount,
+            }),
+        }
+    }
+    groups.sort_by(|a, b| {
+        b.total
+            .total_cmp(&a.total)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+
+    groups
+}
+
 /// Displays the expenses in a table
 ///
 /// # Arguments
@@ -208,35 +736,82 @@ pub fn search_expense_by_id(expenses: &[Expense], id: &str) -> Option<usize> {
 ///
 /// A table of the expenses
 pub fn prettify_expense_display(expenses: &[Expense]) {
-    let mut table = Table::new();
-    let mut expense_number = 1;
-    table.set_header(vec![
-        "No",
-        "ID",
-        "Description",
-        "Amount",
-        "Category",
-        "Last Updated",
-    ]);
+    prettify_expense_table(expenses, &Column::DEFAULT, 1, false);
+}
+
+/// Displays the expenses in a table with the chosen columns.
+///
+/// # Arguments
+///
+/// * `expenses` - The array of expenses to display
+/// * `columns` - The columns to show, in order
+/// * `first_number` - The number shown in the `No` column of the first row
+/// * `show_totals` - Whether to add a footer row with the total spent, leaving out income
+pub fn prettify_expense_table(
+    expenses: &[Expense],
+    columns: &[Column],
+    first_number: usize,
+    show_totals: bool,
+) {
+    let config = config::current();
+    let mut table = config.table();
+    table.set_header(columns.iter().map(Column::header).collect::<Vec<_>>());
+
     if !expenses.is_empty() {
-        for expense in expenses {
-            table.add_row(vec![
-                expense_number.to_string(),
-                expense.id.clone(),
-                expense.description.clone(),
-                expense.amount.to_string(),
-                format!("{:?}", expense.category),
-                expense.updated_at.clone(),
-            ]);
-            expense_number += 1;
+        for (offset, expense) in expenses.iter().enumerate() {
+            table.add_row(
+                columns
+                    .iter()
+                    .map(|column| column.display(first_number + offset, expense, &config))
+                    .collect::<Vec<_>>(),
+            );
+        }
+
+        if show_totals {
+            // Income and transfers are listed but not part of the total or its count.
+            let spending: Vec<&Expense> = expenses
+                .iter()
+                .filter(|expense| expense.is_spending())
+                .collect();
+            let total = sum_amounts(spending.iter().map(|expense| expense.amount));
+            table.add_row(
+                columns
+                    .iter()
+                    .enumerate()
+                    .map(|(index, column)| match column {
+                        Column::Amount => config.format_amount(total),
+                        _ if index == 0 => format!("Total ({})", spending.len()),
+                        _ => String::new(),
+                    })
+                    .collect::<Vec<_>>(),
+            );
         }
     } else {
-        table.add_row(vec!["No expenses found"; 5]);
+        table.add_row(vec![
+            "No expenses found";
+            columns.len().saturating_sub(1).max(1)
+        ]);
     }
 
     println!("{}", table);
 }
 
+/// Adds up amounts.
+///
+/// An empty sum of `f64` is -0.0, which would be printed as `-0`; adding zero
+/// turns it into a plain zero. Every total that is shown goes through here.
+///
+/// # Arguments
+///
+/// * `amounts` - The amounts to add.
+///
+/// # Returns
+///
+/// The sum, never negative zero.
+pub fn sum_amounts(amounts: impl IntoIterator<Item = f64>) -> f64 {
+    amounts.into_iter().sum::<f64>() + 0.0
+}
+
 /// Generates a random ID for an expense.
 ///
 /// # Returns
@@ -267,7 +842,7 @@ pub fn delete_expense(index: usize, all_expenses: &mut Vec<Expense>) -> Expense
 ///
 /// A message indicating that no expenses were found.
 pub fn prettify_expense_not_found() {
-    let mut table = Table::new();
+    let mut table = config::current().table();
     let no_expense_found = "No expenses found".to_string();
 
     table.set_header(vec![
@@ -299,11 +874,11 @@ pub fn prettify_expense_not_found() {
 ///
 /// # Returns
 ///
-/// The month as a u32
-pub fn get_month_from_date_string(datetime: &str) -> u32 {
+/// The month as a u32, or `ExpenseError::InvalidDate` if the string is malformed
+pub fn get_month_from_date_string(datetime: &str) -> Result<u32, ExpenseError> {
     let datetime = NaiveDateTime::parse_from_str(datetime, "%Y-%m-%d %H:%M:%S")
-        .expect("Unable to parse the date");
-    datetime.month()
+        .map_err(|_| ExpenseError::InvalidDate(datetime.to_string()))?;
+    Ok(datetime.month())
 }
 
 /// Set the monthly budget
@@ -314,65 +889,71 @@ pub fn get_month_from_date_string(datetime: &str) -> u32 {
 ///
 /// # Returns
 ///
-/// The budget as a f64
-pub fn set_budget(budget: f64) {
-    let budget_file = "budget.json";
-    let file = OpenOptions::new()
-        .write(true)
-        .truncate(true)
-        .create(true)
-        .open(budget_file)
-        .expect("Unable to open the file");
+/// `Ok` if the budget was saved, or an `ExpenseError::Storage` otherwise
+pub fn set_budget(budget: f64) -> Result<(), ExpenseError> {
+    let budget_file = data_file("budget.json");
 
-    let mut writer = io::BufWriter::new(file);
-    serde_json::to_writer(&mut writer, &budget).expect("Unable to write to the file");
+    let contents = serde_json::to_vec(&budget).map_err(io::Error::from)?;
 
-    writer.flush().expect("Unable to flush the writer");
+    write_atomic(budget_file, &contents)
 }
 
 /// Get the monthly budget
 ///
 /// # Returns
 ///
-/// The budget as a f64
-pub fn get_budget() -> f64 {
-    let budget_file = "budget.json";
-    let budget = std::fs::read_to_string(budget_file).unwrap_or_else(|_| "0.00".to_string());
+/// The budget as a f64 (`0.00` if none was set), or `ExpenseError::Corrupt` if
+/// `budget.json` does not hold a number
+pub fn get_budget() -> Result<f64, ExpenseError> {
+    let budget_file = data_file("budget.json");
+    let budget = read_to_string_or(&budget_file, "0.00")?;
 
-    let budget: f64 = serde_json::from_str(&budget).expect("Unable to parse the budget");
+    let budget: f64 =
+        serde_json::from_str(&budget).map_err(|e| ExpenseError::corrupt(&budget_file, e))?;
 
-    budget
+    Ok(budget)
 }
 
-/// Clear all expense
+/// Ask the user to confirm an action on the terminal
+///
+/// The prompt is written to stderr so that it never mixes with machine-readable
+/// output on stdout. Only `y` or `yes` (any case) confirms; an empty answer or
+/// end of input declines.
 ///
 /// # Arguments
-/// No arguments
+///
+/// * `prompt` - The question to ask
 ///
 /// # Returns
 ///
-/// Result `Ok` if the expenses were successfully cleared and `Err` std::error::Error on error
-pub fn clear_all_expenses() -> Result<(), Box<dyn std::error::Error>> {
-    let mut confirm_clear = "n".to_string();
-    print!("\n >> << Are you sure you want to clear all expenses? [y][N] << >> ");
-    stdout()
-        .flush()
-        .expect("Hard to display something here. Try agin.");
-    stdin()
-        .read_line(&mut confirm_clear)
-        .expect("Could not read you terminal. Try again");
-
-    match confirm_clear.as_str() {
-        "y" | "Y" => {
-            let blank_expenses = [];
-            save_expenses(&blank_expenses)?;
-        }
+/// `true` if the user confirmed
+pub fn confirm(prompt: &str) -> Result<bool, ExpenseError> {
+    let mut answer = String::new();
+    eprint!("\n >> << {} [y][N] << >> ", prompt);
+    stderr().flush()?;
+    stdin().read_line(&mut answer)?;
 
-        "n" | "N" => println!("User quit!"),
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
 
-        _ => {
-            println!("\n\tMaybe that is not the command you wanted to run: Try [y] or [N]\n");
-        }
-    }
-    Ok(())
+/// Clear the expenses matching a predicate
+///
+/// # Arguments
+///
+/// * `all_expenses` - The array of all expenses
+/// * `should_clear` - Returns `true` for every expense to remove
+///
+/// # Returns
+///
+/// The removed expenses
+pub fn clear_expenses<F>(all_expenses: &mut Vec<Expense>, should_clear: F) -> Vec<Expense>
+where
+    F: Fn(&Expense) -> bool,
+{
+    let (cleared, kept): (Vec<Expense>, Vec<Expense>) = all_expenses
+        .drain(..)
+        .partition(|expense| should_clear(expense));
+    *all_expenses = kept;
+
+    cleared
 }
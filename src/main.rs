@@ -1,53 +1,324 @@
-use clap::{Parser, Subcommand};
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Utc, Weekday};
+use clap::{parser::ValueSource, CommandFactory, FromArgMatches, Parser, Subcommand};
 use expense_tracker::{
-    clear_all_expenses, convert_from_system_time, delete_expense, export_expenses, get_budget,
-    get_month_from_date_string, load_expenses, map_category, prettify_expense_display,
-    prettify_expense_not_found, save_expenses, search_expense_by_id, set_budget, Expense,
+    accounts::{
+        add_account, balances, find_account, load_accounts, remove_account, save_accounts, Account,
+        AccountKind,
+    },
+    categories::{
+        add_category, load_categories, remove_category, resolve_category, save_categories,
+        suggest_category, BUILT_IN,
+    },
+    clear_expenses,
+    config::{set_current, Config, KEYS},
+    confirm, convert_from_system_time, delete_expense,
+    error::ExpenseError,
+    events::{compact, load_state, Snapshot},
+    expand_splits, export_expenses,
+    files::{lock_ledger, LedgerLock},
+    filter_expenses,
+    forecast::{forecast, upcoming},
+    generate_random_id, get_month_from_date_string, group_expenses,
+    journal::{Journal, OperationKind},
+    map_category,
+    output::{
+        print_accounts, print_balances, print_categories, print_error, print_expense,
+        print_expenses, print_history, print_json, print_message, print_payees, print_payments,
+        print_profiles, print_settings, print_summary, print_templates, CategoryEntry,
+        HistoryEntry, OutputFormat, Summary,
+    },
+    paths::{
+        config_file, data_file, ledger_override, profile, set_ledger, set_profile, with_profile,
+    },
+    payees::{
+        add_alias, add_payee, find_payee, load_payees, normalize_payee, remove_payee, save_payees,
+        Payee,
+    },
+    prettify_expense_display,
+    profiles::{create_profile, delete_profile, list_profiles, require_profile, switch_profile},
+    query::Query,
+    receipts::{export_receipt, open_receipt, store_receipt},
+    recurring::{
+        any_due, load_templates, materialize, save_templates, Frequency, NthWeekday, Template,
+    },
+    search_expense_by_id,
+    shared::{divide, net_balances, rescale, settle, Share, ShareMethod, Shared},
+    shell::{ask, history_file, split_line, ShellHelper},
+    sort_expenses,
+    store::{keep_in_memory, load_ledger, save_budget, save_ledger, Backend},
+    sum_amounts,
+    tui::run_tui,
+    validation::{
+        validate_amount, validate_budget, validate_date, validate_description, validate_expense,
+        validate_field, validate_note, Limits,
+    },
+    Category, Column, Expense, GroupBy, SortKey, Split, TransactionKind,
+};
+use rustyline::{error::ReadlineError, history::DefaultHistory, Editor};
+use std::{
+    fs,
+    io::{stdin, IsTerminal},
+    path::{Path, PathBuf},
+    process,
+    time::SystemTime,
 };
-use std::{process, time::SystemTime};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about=None)]
 struct ExpenseTracker {
+    #[arg(
+        long,
+        global = true,
+        default_value = "table",
+        help = "Output format: table, json, csv or plain"
+    )]
+    output: OutputFormat,
+
+    #[arg(
+        long,
+        global = true,
+        env = "EXPENSE_TRACKER_BACKEND",
+        help = "Storage backend: json or events [default: json, or `backend` in config.toml]"
+    )]
+    backend: Option<Backend>,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "PATH",
+        help = "Ledger file to use; the budget, journal and event log are kept next to it"
+    )]
+    ledger: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        conflicts_with = "ledger",
+        help = "Profile (named ledger) to use instead of the active one"
+    )]
+    profile: Option<String>,
+
     #[command(subcommand)]
-    command: Commands,
+    command: TopLevel,
+}
+
+// Every subcommand. Those outside `Commands` run without opening the ledger, so
+// they are dispatched before it is locked and loaded. These are not doc comments,
+// which clap would show as the description of the program.
+#[derive(Subcommand)]
+enum TopLevel {
+    #[command(flatten)]
+    Ledger(Box<Commands>),
+    #[command(about = "Create, list, switch or delete profiles (named ledgers).")]
+    Profile {
+        #[command(subcommand)]
+        action: ProfileAction,
+    },
+    #[command(about = "Run commands interactively, keeping the ledger in memory.")]
+    Shell,
+    #[command(about = "Browse and edit expenses in a full-screen terminal UI.")]
+    Tui,
+    #[command(about = "Read or change settings in config.toml.")]
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
 }
 
+// The subcommands that work on the ledger of the current profile.
 #[derive(Subcommand)]
 enum Commands {
-    #[command(about = "Add an expense.")]
+    #[command(about = "Add an expense; without --description and --amount, asks for each field.")]
     Add {
-        #[arg(short, long)]
-        description: String,
+        #[arg(short, long, requires = "amount")]
+        description: Option<String>,
 
-        #[arg(short, long)]
-        amount: f64,
+        #[arg(short, long, allow_negative_numbers = true, requires = "description")]
+        amount: Option<f64>,
 
-        #[arg(short, long, default_value = "other")]
-        category: String,
+        #[arg(
+            short,
+            long,
+            help = "Category [default: other, or `default_category` in config.toml]"
+        )]
+        category: Option<String>,
+
+        #[arg(short, long = "tag")]
+        tags: Vec<String>,
+
+        #[arg(short, long, help = "Who was paid; known aliases are normalized")]
+        payee: Option<String>,
+
+        #[arg(long, help = "Account the expense was paid from (see `account add`)")]
+        account: Option<String>,
+
+        #[arg(long, help = "Record money received, such as a salary or a refund")]
+        income: bool,
+
+        #[arg(
+            long = "split",
+            value_name = "AMOUNT:CATEGORY[:NOTE]",
+            help = "File part of the amount under another category; repeat for each part"
+        )]
+        splits: Vec<String>,
+
+        #[arg(long, requires = "shared_with", help = "Who paid for a shared expense")]
+        paid_by: Option<String>,
+
+        #[arg(
+            long,
+            value_delimiter = ',',
+            value_name = "PERSON[:VALUE]",
+            requires = "paid_by",
+            help = "People sharing the expense, e.g. alice,bob or alice:2,bob:1"
+        )]
+        shared_with: Vec<String>,
+
+        #[arg(
+            long,
+            help = "How to share the expense: equal (the default), shares, exact or percent"
+        )]
+        share_by: Option<ShareMethod>,
+
+        #[arg(long, help = "Attach a receipt file, e.g. a PDF or photo")]
+        receipt: Option<PathBuf>,
+
+        #[arg(short, long, help = "A longer note; may span several lines")]
+        note: Option<String>,
+
+        #[arg(long, help = "Where the expense was made")]
+        location: Option<String>,
+
+        #[arg(
+            long = "field",
+            value_name = "KEY=VALUE",
+            help = "Set a custom field; repeat for each field"
+        )]
+        fields: Vec<String>,
+
+        #[arg(long, help = "Date of the expense as YYYY-MM-DD (defaults to now)")]
+        date: Option<String>,
     },
+    #[command(about = "Clear all expenses, or the ones matching the given filters.")]
     Clear {
+        #[arg(short, long, help = "Do not ask for confirmation")]
+        yes: bool,
+
+        #[arg(short, long, help = "Only clear expenses of this month (1-12)")]
+        month: Option<u32>,
+
+        #[arg(
+            long,
+            requires = "month",
+            help = "Year of --month (defaults to this year)"
+        )]
+        year: Option<i32>,
+
+        #[arg(long, help = "Only clear expenses on or after this date (YYYY-MM-DD)")]
+        from: Option<String>,
+
+        #[arg(long, help = "Only clear expenses on or before this date (YYYY-MM-DD)")]
+        to: Option<String>,
+
         #[arg(short, long, default_value = "all")]
-        clear: String,
+        category: String,
+
+        #[arg(short = 'w', long = "where")]
+        filter: Option<String>,
     },
     #[command(about = "Update an expense.")]
     Update {
+        #[arg(short, long, required_unless_present = "filter")]
+        id: Option<String>,
+
+        #[arg(short = 'w', long = "where", conflicts_with = "id")]
+        filter: Option<String>,
+
         #[arg(short, long)]
-        id: String,
+        description: Option<String>,
 
-        #[arg(short, long, default_value = "no_update")]
-        description: String,
+        #[arg(short, long, allow_negative_numbers = true)]
+        amount: Option<f64>,
 
-        #[arg(short, long, default_value = "-1.00")]
-        amount: f64,
+        #[arg(short, long)]
+        category: Option<String>,
 
-        #[arg(short, long, default_value = "no_update")]
-        category: String,
+        #[arg(short, long, help = "Who was paid; known aliases are normalized")]
+        payee: Option<String>,
+
+        #[arg(long, help = "Account the expense was paid from (see `account add`)")]
+        account: Option<String>,
+
+        #[arg(long, help = "Change the entry to an expense or income")]
+        kind: Option<TransactionKind>,
+
+        #[arg(
+            long = "split",
+            value_name = "AMOUNT:CATEGORY[:NOTE]",
+            conflicts_with = "no_splits",
+            help = "Replace the splits; repeat for each part"
+        )]
+        splits: Vec<String>,
+
+        #[arg(long, help = "Remove the splits")]
+        no_splits: bool,
+
+        #[arg(long, help = "Who paid for a shared expense")]
+        paid_by: Option<String>,
+
+        #[arg(
+            long,
+            value_delimiter = ',',
+            value_name = "PERSON[:VALUE]",
+            conflicts_with = "not_shared",
+            help = "Share the expense again between these people"
+        )]
+        shared_with: Vec<String>,
+
+        #[arg(
+            long,
+            help = "How to share the expense: equal (the default), shares, exact or percent"
+        )]
+        share_by: Option<ShareMethod>,
+
+        #[arg(long, help = "Stop sharing the expense")]
+        not_shared: bool,
+
+        #[arg(short, long, help = "Replace the note; an empty note removes it")]
+        note: Option<String>,
+
+        #[arg(long, help = "Replace the location; an empty location removes it")]
+        location: Option<String>,
+
+        #[arg(
+            long = "field",
+            value_name = "KEY=VALUE",
+            help = "Set a custom field, or remove it with KEY=; repeat for each field"
+        )]
+        fields: Vec<String>,
+
+        #[arg(long, help = "Date of the expense as YYYY-MM-DD")]
+        date: Option<String>,
     },
-    #[command(about = "Delete an expense.")]
-    Delete {
+    #[command(about = "Attach a receipt file to an expense.")]
+    Attach {
         #[arg(short, long)]
         id: String,
+
+        file: PathBuf,
+    },
+    #[command(about = "Open or export the receipt attached to an expense.")]
+    Receipt {
+        #[command(subcommand)]
+        action: ReceiptAction,
+    },
+    #[command(about = "Delete an expense.")]
+    Delete {
+        #[arg(short, long, required_unless_present = "filter")]
+        id: Option<String>,
+
+        #[arg(short = 'w', long = "where", conflicts_with = "id")]
+        filter: Option<String>,
     },
     #[command(about = "View all expenses.")]
     List {
@@ -65,6 +336,35 @@ enum Commands {
 
         #[arg(long, default_value = "now")]
         added_at: String,
+
+        #[arg(short = 'w', long = "where")]
+        filter: Option<String>,
+
+        #[arg(
+            short,
+            long,
+            help = "Sort by amount, date, category, description or payee"
+        )]
+        sort: Option<SortKey>,
+
+        #[arg(long, requires = "sort", help = "Sort in descending order")]
+        desc: bool,
+
+        #[arg(short, long, help = "Show at most this many expenses")]
+        limit: Option<usize>,
+
+        #[arg(short, long, default_value = "0", help = "Skip this many expenses")]
+        offset: usize,
+
+        #[arg(
+            long,
+            value_delimiter = ',',
+            help = "Comma separated columns: no,id,date,description,amount,category,updated,tags,payee,account,kind,receipt,note,location,fields"
+        )]
+        columns: Vec<Column>,
+
+        #[arg(long, help = "Show each split of an expense as its own row")]
+        expand: bool,
     },
     #[command(about = "View summary of expenses.")]
     Summary {
@@ -76,87 +376,1200 @@ enum Commands {
 
         #[arg(short, long, default_value = "13")]
         month: u32,
+
+        #[arg(
+            long,
+            help = "Only count expenses from the current week (see `week_start` in config.toml)"
+        )]
+        week: bool,
+
+        #[arg(short = 'w', long = "where")]
+        filter: Option<String>,
+
+        #[arg(long, help = "Only count expenses paid from this account")]
+        account: Option<String>,
+
+        #[arg(short, long, help = "Show totals per category, payee or account")]
+        group_by: Option<GroupBy>,
+    },
+    #[command(about = "List the recurring expenses due in the coming days.")]
+    Upcoming {
+        #[arg(
+            short,
+            long,
+            default_value = "30",
+            help = "How many days ahead to look"
+        )]
+        days: u32,
+    },
+    #[command(about = "Revert the most recent changes to the ledger.")]
+    Undo {
+        #[arg(
+            short,
+            long,
+            default_value = "1",
+            help = "Number of operations to undo"
+        )]
+        steps: usize,
+    },
+    #[command(about = "Re-apply changes reverted by undo.")]
+    Redo {
+        #[arg(
+            short,
+            long,
+            default_value = "1",
+            help = "Number of operations to redo"
+        )]
+        steps: usize,
+    },
+    #[command(about = "Snapshot the event log and drop the events it covers.")]
+    Compact,
+    #[command(about = "Show the journal of ledger changes.")]
+    History {
+        #[arg(short, long, help = "Show only the most recent operations")]
+        limit: Option<usize>,
     },
     #[command(about = "Export expenses to a file.")]
     Export {
         #[arg(short, long)]
         file: String,
+
+        #[arg(short = 'w', long = "where")]
+        filter: Option<String>,
     },
     #[command(about = "Control budget")]
     Budget {
         #[arg(short, long)]
         budget: f64,
     },
+    #[command(about = "Move or copy expenses to another profile.")]
+    Move {
+        #[arg(short, long, required_unless_present = "filter")]
+        id: Option<String>,
+
+        #[arg(short = 'w', long = "where", conflicts_with = "id")]
+        filter: Option<String>,
+
+        #[arg(long, help = "Profile to move the expenses to")]
+        to: String,
+
+        #[arg(long, help = "Copy the expenses instead of moving them")]
+        copy: bool,
+    },
+    #[command(about = "Manage the current profile's custom categories.")]
+    Category {
+        #[command(subcommand)]
+        action: CategoryAction,
+    },
+    #[command(about = "Move money between two accounts without counting it as spending.")]
+    Transfer {
+        #[arg(long, help = "Account the money leaves")]
+        from: String,
+
+        #[arg(long, help = "Account the money goes to")]
+        to: String,
+
+        #[arg(short, long)]
+        amount: f64,
+
+        #[arg(short, long, help = "Description [default: Transfer to <to>]")]
+        description: Option<String>,
+
+        #[arg(long, help = "Date of the transfer as YYYY-MM-DD (defaults to now)")]
+        date: Option<String>,
+    },
+    #[command(
+        about = "Work out who owes whom for shared expenses and record the payments.",
+        long_about = "Work out who owes whom for shared expenses and record the fewest payments that settle everyone. With more than 20 people owing or owed money, the plan is not guaranteed to be the shortest."
+    )]
+    Settle {
+        #[arg(long, help = "Only show the payments; do not record them")]
+        dry_run: bool,
+    },
+    #[command(about = "Show the running balance of each account.")]
+    Balance,
+    #[command(about = "Manage the accounts and payment methods expenses are paid from.")]
+    Account {
+        #[command(subcommand)]
+        action: AccountAction,
+    },
+    #[command(about = "Manage the current profile's payees and their aliases.")]
+    Payee {
+        #[command(subcommand)]
+        action: PayeeAction,
+    },
+    #[command(about = "Manage recurring expenses such as rent or subscriptions.")]
+    Recurring {
+        #[command(subcommand)]
+        action: RecurringAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProfileAction {
+    #[command(about = "Create an empty profile.")]
+    Create { name: String },
+    #[command(about = "List the profiles; the active one is marked.")]
+    List,
+    #[command(about = "Use a profile when no --profile is given.")]
+    Switch { name: String },
+    #[command(about = "Delete a profile and all of its expenses.")]
+    Delete {
+        name: String,
+
+        #[arg(short, long, help = "Delete without asking for confirmation")]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum CategoryAction {
+    #[command(about = "Define a custom category.")]
+    Add { name: String },
+    #[command(about = "List the built-in and custom categories.")]
+    List,
+    #[command(about = "Remove a custom category.")]
+    Remove { name: String },
+}
+
+#[derive(Subcommand)]
+enum ReceiptAction {
+    #[command(about = "Open the receipt with the default viewer.")]
+    Open {
+        #[arg(short, long)]
+        id: String,
+    },
+    #[command(about = "Copy the receipt out of the attachments directory.")]
+    Export {
+        #[arg(short, long)]
+        id: String,
+
+        #[arg(
+            long,
+            default_value = ".",
+            help = "File to write, or a directory to write it into under its original name"
+        )]
+        to: PathBuf,
+
+        #[arg(long, help = "Replace the file if it already exists")]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum AccountAction {
+    #[command(about = "Define an account.")]
+    Add {
+        name: String,
+
+        #[arg(short, long, help = "Payment method: cash, card or bank")]
+        kind: AccountKind,
+
+        #[arg(
+            short,
+            long,
+            default_value = "0",
+            allow_negative_numbers = true,
+            help = "Balance before the expenses recorded in the ledger"
+        )]
+        opening_balance: f64,
+    },
+    #[command(about = "List the accounts.")]
+    List,
+    #[command(about = "Remove an account.")]
+    Remove { name: String },
+}
+
+#[derive(Subcommand)]
+enum PayeeAction {
+    #[command(about = "Define a payee.")]
+    Add {
+        name: String,
+
+        #[arg(
+            long = "alias",
+            help = "Another spelling of the payee, e.g. as on a bank statement"
+        )]
+        aliases: Vec<String>,
+
+        #[arg(
+            short,
+            long,
+            help = "Category used when an expense gives no --category"
+        )]
+        category: Option<String>,
+    },
+    #[command(about = "List the payees with their aliases and default categories.")]
+    List,
+    #[command(about = "Add an alias to a payee.")]
+    Alias { name: String, alias: String },
+    #[command(about = "Remove a payee.")]
+    Remove { name: String },
+}
+
+#[derive(Subcommand)]
+enum RecurringAction {
+    #[command(about = "Add a recurring expense template.")]
+    Add {
+        #[arg(short, long)]
+        description: String,
+
+        #[arg(short, long, allow_negative_numbers = true)]
+        amount: f64,
+
+        #[arg(
+            short,
+            long,
+            help = "Category [default: other, or `default_category` in config.toml]"
+        )]
+        category: Option<String>,
+
+        #[arg(short, long = "tag")]
+        tags: Vec<String>,
+
+        #[arg(long, help = "daily, weekly, monthly or yearly")]
+        every: Frequency,
+
+        #[arg(
+            long,
+            default_value = "1",
+            help = "Repeat every N days/weeks/months/years"
+        )]
+        interval: u32,
+
+        #[arg(long, help = "First occurrence as YYYY-MM-DD (defaults to today)")]
+        start: Option<String>,
+
+        #[arg(long, help = "Last possible occurrence as YYYY-MM-DD")]
+        end: Option<String>,
+
+        #[arg(
+            long,
+            requires = "weekday",
+            allow_negative_numbers = true,
+            help = "With --every monthly: the Nth weekday of the month (1-5, or -1 for the last)"
+        )]
+        nth: Option<i8>,
+
+        #[arg(long, requires = "nth", help = "The weekday used with --nth, e.g. tue")]
+        weekday: Option<Weekday>,
+    },
+    #[command(about = "List the recurring expense templates.")]
+    List,
+    #[command(about = "Remove a template; expenses already generated are kept.")]
+    Remove { id: String },
+    #[command(about = "Generate the expenses that have fallen due.")]
+    Run,
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    #[command(about = "Print a setting.")]
+    Get { key: String },
+    #[command(about = "Change a setting.")]
+    Set { key: String, value: String },
+    #[command(about = "Print every setting.")]
+    List,
 }
 
 #[tokio::main]
 async fn main() {
     let args = ExpenseTracker::parse();
-    let mut all_expenses = load_expenses().unwrap_or_else(|_| vec![]);
-    let budget = get_budget();
+    let output = args.output;
+    set_ledger(args.ledger);
+    set_profile(args.profile.clone());
+
+    let result = match args.command {
+        TopLevel::Shell => run_shell(ShellDefaults {
+            output,
+            backend: args.backend,
+            ledger: ledger_override(),
+            profile: args.profile,
+        }),
+        command => dispatch(command, output, args.backend),
+    };
+    if let Err(error) = result {
+        report_error(output, &error);
+        process::exit(error.exit_code());
+    }
+}
+
+/// Runs a subcommand, reading `config.toml` only for those that need it so that
+/// a broken file can still be repaired with `config set`.
+///
+/// # Arguments
+///
+/// * `command` - The subcommand.
+/// * `output` - The output format.
+/// * `backend` - The `--backend` flag; `backend` in `config.toml` applies without it.
+fn dispatch(
+    command: TopLevel,
+    output: OutputFormat,
+    backend: Option<Backend>,
+) -> Result<(), ExpenseError> {
+    match command {
+        TopLevel::Ledger(command) => {
+            let config = load_config()?;
+            run(*command, output, backend.unwrap_or(config.backend), &config)
+        }
+        TopLevel::Tui => {
+            let config = load_config()?;
+            let backend = backend.unwrap_or(config.backend);
+            open_ledger(backend, &config.limits, None)?;
+            // The TUI saves each change on its own, under its own lock.
+            run_tui(backend, config.limits)
+        }
+        TopLevel::Config { action } => run_config(action, output),
+        TopLevel::Profile { action } => {
+            // Profiles do not depend on the settings; they only style the output.
+            if let Ok(config) = Config::load() {
+                set_current(config);
+            }
+            run_profile(action, output)
+        }
+        TopLevel::Shell => Err(ExpenseError::Validation("Already in the shell".to_string())),
+    }
+}
+
+/// Loads `config.toml` and makes it the configuration used for display.
+fn load_config() -> Result<Config, ExpenseError> {
+    let config = Config::load()?;
+    set_current(config.clone());
+    Ok(config)
+}
+
+/// Checks that the current profile exists and adds the recurring expenses that
+/// have fallen due, before a command reads the ledger.
+///
+/// # Arguments
+///
+/// * `backend` - The backend the ledger is stored with.
+/// * `limits` - The limits the generated expenses are checked against.
+/// * `command` - The command about to run, or `None` for the TUI. `recurring`
+///   commands are not told how many expenses were added, and all but `recurring
+///   run` only report a template that would add an invalid expense, so that it
+///   can still be listed or removed.
+///
+/// # Returns
+///
+/// The expenses that were added.
+fn open_ledger(
+    backend: Backend,
+    limits: &Limits,
+    command: Option<&Commands>,
+) -> Result<Vec<Expense>, ExpenseError> {
+    if ledger_override().is_none() {
+        require_profile(&profile())?;
+    }
+
+    let generated = match (materialize_due(backend, limits), command) {
+        (Ok(generated), _) => generated,
+        (Err(error @ ExpenseError::Validation(_)), Some(Commands::Recurring { action }))
+            if !matches!(action, RecurringAction::Run) =>
+        {
+            eprintln!("{}", error);
+            Vec::new()
+        }
+        (Err(error), _) => return Err(error),
+    };
+    let announce = !matches!(command, Some(Commands::Recurring { .. }));
+    if announce && !generated.is_empty() {
+        eprintln!(
+            "Added {} recurring expense(s) that fell due",
+            generated.len()
+        );
+    }
+
+    Ok(generated)
+}
+
+/// Prints an error in the requested output format.
+fn report_error(output: OutputFormat, error: &ExpenseError) {
+    let message = match output {
+        OutputFormat::Json => error.to_string(),
+        _ => error.pretty(),
+    };
+    print_error(output, error.code(), &message);
+}
+
+/// The global flags `shell` was started with, used for every line that does not override them.
+struct ShellDefaults {
+    output: OutputFormat,
+    backend: Option<Backend>,
+    ledger: Option<PathBuf>,
+    profile: Option<String>,
+}
+
+/// Runs the interactive shell until `exit`, `quit` or end of input.
+///
+/// Each line is parsed like the arguments of the binary, so every command and
+/// flag works the same way. The ledger is kept in memory between lines and only
+/// read again when its files change.
+///
+/// # Arguments
+///
+/// * `defaults` - The global flags given to `shell`.
+fn run_shell(defaults: ShellDefaults) -> Result<(), ExpenseError> {
+    keep_in_memory();
+    // Completion is best effort, so a broken config.toml only falls back to the default backend.
+    let backend = defaults
+        .backend
+        .or_else(|| Config::load().ok().map(|config| config.backend))
+        .unwrap_or_default();
+    let readline_error = |error: ReadlineError| ExpenseError::Storage(std::io::Error::other(error));
+    let mut editor: Editor<ShellHelper, DefaultHistory> = Editor::new().map_err(readline_error)?;
+    editor.set_helper(Some(ShellHelper::new(ExpenseTracker::command(), backend)));
+    let history = history_file();
+    // There is no history before the first session.
+    let _ = editor.load_history(&history);
+
+    loop {
+        let line = match editor.readline("expense-tracker> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(error) => return Err(readline_error(error)),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line).map_err(readline_error)?;
+        if matches!(line, "exit" | "quit") {
+            break;
+        }
+
+        let output = run_shell_line(line, &defaults).unwrap_or_else(|(output, error)| {
+            report_error(output, &error);
+            output
+        });
+        if output == OutputFormat::Table {
+            println!();
+        }
+        if let Some(helper) = editor.helper_mut() {
+            helper.refresh();
+        }
+    }
+
+    set_ledger(defaults.ledger);
+    set_profile(defaults.profile);
+    if let Some(directory) = history.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    editor.save_history(&history).map_err(readline_error)
+}
+
+/// Runs one line of the shell.
+///
+/// # Returns
+///
+/// The output format the line used, or the error it failed with in that format.
+fn run_shell_line(
+    line: &str,
+    defaults: &ShellDefaults,
+) -> Result<OutputFormat, (OutputFormat, ExpenseError)> {
+    let fail = |error| (defaults.output, error);
+    let words = split_line(line).map_err(fail)?;
+    let matches = match ExpenseTracker::command()
+        .try_get_matches_from(std::iter::once("expense-tracker".to_string()).chain(words))
+    {
+        Ok(matches) => matches,
+        Err(error) => {
+            // Usage errors and --help are printed the way clap prints them.
+            let _ = error.print();
+            return Ok(defaults.output);
+        }
+    };
+    let args = ExpenseTracker::from_arg_matches(&matches)
+        .map_err(|error| fail(ExpenseError::Validation(error.to_string())))?;
+
+    let output = match matches.value_source("output") {
+        Some(ValueSource::DefaultValue) => defaults.output,
+        _ => args.output,
+    };
+    let fail = |error| (output, error);
+
+    let (ledger, profile) = match (args.ledger, args.profile) {
+        (None, None) => (defaults.ledger.clone(), defaults.profile.clone()),
+        given => given,
+    };
+    set_ledger(ledger);
+    set_profile(profile);
+    // The configuration is read again, so `config set` takes effect on the next line.
+    dispatch(args.command, output, args.backend.or(defaults.backend)).map_err(fail)?;
+    Ok(output)
+}
+
+/// Runs a `config` subcommand.
+fn run_config(action: ConfigAction, output: OutputFormat) -> Result<(), ExpenseError> {
+    let mut config = match (Config::load(), &action) {
+        (Ok(config), _) => config,
+        // A file that cannot be read is put aside, so that `config set` can repair it.
+        (Err(error @ ExpenseError::Corrupt { .. }), ConfigAction::Set { .. }) => {
+            let file = config_file();
+            let backup = file.with_extension("toml.bak");
+            fs::rename(&file, &backup)?;
+            eprintln!("{}", error.pretty());
+            eprintln!(
+                "Moved it to {} and started again from the default settings.",
+                backup.display()
+            );
+            Config::default()
+        }
+        (Err(error), _) => return Err(error),
+    };
+    set_current(config.clone());
+
+    match action {
+        ConfigAction::Get { key } => {
+            let value = config.get(&key)?;
+            match output {
+                OutputFormat::Json => print_settings(output, &[(key, value)]),
+                _ => println!("{}", value),
+            }
+        }
+        ConfigAction::Set { key, value } => {
+            config.set(&key, &value)?;
+            config.save()?;
+            print_message(output, &format!("{} set to '{}'", key, config.get(&key)?));
+        }
+        ConfigAction::List => {
+            let settings = KEYS
+                .iter()
+                .map(|key| Ok((key.to_string(), config.get(key)?)))
+                .collect::<Result<Vec<_>, ExpenseError>>()?;
+            print_settings(output, &settings);
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs a `profile` subcommand.
+fn run_profile(action: ProfileAction, output: OutputFormat) -> Result<(), ExpenseError> {
+    match action {
+        ProfileAction::Create { name } => {
+            create_profile(&name)?;
+            print_message(output, &format!("Profile '{}' created", name));
+        }
+        ProfileAction::List => print_profiles(output, &list_profiles()?),
+        ProfileAction::Switch { name } => {
+            switch_profile(&name)?;
+            print_message(output, &format!("Switched to profile '{}'", name));
+        }
+        ProfileAction::Delete { name, yes } => {
+            require_profile(&name)?;
+            if !yes
+                && !confirm(&format!(
+                    "Delete profile '{}' and all of its expenses?",
+                    name
+                ))?
+            {
+                print_message(output, "Nothing deleted.");
+                return Ok(());
+            }
+            delete_profile(&name)?;
+            print_message(output, &format!("Profile '{}' deleted", name));
+        }
+    }
+
+    Ok(())
+}
+
+/// Takes the write locks of the current ledger and of the ledger of `profile`.
+///
+/// The locks are always taken in the order of their lock files, so two moves in
+/// opposite directions cannot each hold one lock while waiting for the other.
+fn lock_both_ledgers(profile: &str) -> Result<Vec<LedgerLock>, ExpenseError> {
+    let current = data_file("expenses.lock");
+    let other = with_profile(profile, || data_file("expenses.lock"));
+    if current == other {
+        return Ok(vec![lock_ledger(true)?]);
+    }
+
+    let mut locks = Vec::new();
+    if current < other {
+        locks.push(lock_ledger(true)?);
+        locks.push(with_profile(profile, || lock_ledger(true))?);
+    } else {
+        locks.push(with_profile(profile, || lock_ledger(true))?);
+        locks.push(lock_ledger(true)?);
+    }
+
+    Ok(locks)
+}
+
+/// Adds expenses to the ledger of the current profile, as the receiving end of `move`.
+///
+/// IDs that are already taken are regenerated, custom categories the profile
+/// does not know yet are added to it, and receipts are copied from
+/// `attachments`. The caller holds the lock of the ledger and journals the change.
+///
+/// # Returns
+///
+/// The expenses of the ledger before and after the change.
+fn receive_expenses(
+    backend: Backend,
+    expenses: &[Expense],
+    attachments: &Path,
+) -> Result<(Vec<Expense>, Vec<Expense>), ExpenseError> {
+    let before = load_ledger(backend)?.expenses;
+    let mut after = before.clone();
+    let mut categories = load_categories()?;
+    let known_categories = categories.len();
 
-    match args.command {
+    for expense in expenses {
+        let mut expense = expense.clone();
+        while search_expense_by_id(&after, &expense.id).is_some() {
+            expense.id = generate_random_id().to_string();
+        }
+        if let Some(receipt) = &expense.receipt {
+            let target = receipt.path()?;
+            if let Some(file) = target.file_name().filter(|_| !target.exists()) {
+                fs::create_dir_all(data_file("attachments"))?;
+                fs::copy(attachments.join(file), &target)?;
+            }
+        }
+        let split_categories = expense.splits.iter().map(|split| &split.category);
+        for category in std::iter::once(&expense.category).chain(split_categories) {
+            if let Category::Custom(name) = category {
+                if !categories
+                    .iter()
+                    .any(|known| known.eq_ignore_ascii_case(name))
+                {
+                    categories.push(name.clone());
+                }
+            }
+        }
+        after.push(expense);
+    }
+
+    save_ledger(backend, &before, &after)?;
+    if categories.len() != known_categories {
+        save_categories(&categories)?;
+    }
+
+    Ok((before, after))
+}
+
+/// Asks for the fields of an `add` given without `--description` and `--amount`.
+///
+/// The category is suggested from earlier expenses with a similar description,
+/// and flags that were given, such as `--category` or `--date`, become the
+/// suggested answers. Other commands are returned unchanged.
+///
+/// # Arguments
+///
+/// * `command` - The command to complete.
+/// * `backend` - The backend to read earlier expenses from.
+/// * `config` - Supplies the default category.
+///
+/// # Returns
+///
+/// The command with every field filled in.
+fn ask_missing_fields(
+    mut command: Commands,
+    backend: Backend,
+    config: &Config,
+) -> Result<Commands, ExpenseError> {
+    let Commands::Add {
+        description: description @ None,
+        amount,
+        category,
+        tags,
+        date,
+        ..
+    } = &mut command
+    else {
+        return Ok(command);
+    };
+
+    let expenses = {
+        let _lock = lock_ledger(false)?;
+        load_ledger(backend)?.expenses
+    };
+    let custom = load_categories()?;
+    let limits = config.limits.clone();
+
+    let answer = ask_until_valid("Description", "", &[], |answer| {
+        validate_description(answer, &limits)
+    })?;
+    *amount = Some(ask_until_valid("Amount", "", &[], |answer| {
+        let amount = answer
+            .parse::<f64>()
+            .map_err(|_| ExpenseError::Validation(format!("'{}' is not an amount", answer)))?;
+        validate_amount(amount, &limits)
+    })?);
+
+    let suggested = match category.take() {
+        Some(category) => category,
+        None => match suggest_category(&expenses, &answer) {
+            Some(Category::Custom(name)) => name,
+            Some(category) => category.to_string().to_lowercase(),
+            None => config.default_category.clone(),
+        },
+    };
+    let choices: Vec<String> = BUILT_IN
+        .iter()
+        .map(|name| name.to_string())
+        .chain(custom)
+        .collect();
+    *category = Some(ask("Category", &suggested, &choices)?).filter(|name| !name.is_empty());
+
+    let today = Utc::now().date_naive().format("%Y-%m-%d").to_string();
+    let default_date = date.take().unwrap_or(today);
+    *date = Some(ask_until_valid("Date", &default_date, &[], |answer| {
+        validate_date(answer, &limits).map(|_| answer.to_string())
+    })?);
+    *tags = ask("Tags, separated by commas", &tags.join(", "), &[])?
+        .split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(String::from)
+        .collect();
+    *description = Some(answer);
+
+    Ok(command)
+}
+
+/// Asks a question until the answer passes `check`.
+///
+/// Without a terminal to correct the answer on, the first invalid answer is an error.
+fn ask_until_valid<T>(
+    question: &str,
+    default: &str,
+    choices: &[String],
+    check: impl Fn(&str) -> Result<T, ExpenseError>,
+) -> Result<T, ExpenseError> {
+    loop {
+        match check(&ask(question, default, choices)?) {
+            Ok(value) => return Ok(value),
+            Err(error) if stdin().is_terminal() => eprintln!("{}", error.pretty()),
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Adds the expenses of recurring templates that have fallen due.
+///
+/// The ledger lock is only taken when something is due, so read-only commands
+/// stay concurrent on the common path.
+fn materialize_due(backend: Backend, limits: &Limits) -> Result<Vec<Expense>, ExpenseError> {
+    let today = Utc::now().date_naive();
+    if !any_due(&load_templates()?, today) {
+        return Ok(Vec::new());
+    }
+
+    let _lock = lock_ledger(true)?;
+    let mut templates = load_templates()?;
+    let before = load_ledger(backend)?.expenses;
+    let mut after = before.clone();
+    let generated = materialize(&mut templates, &mut after, today, limits)?;
+
+    save_ledger(backend, &before, &after)?;
+    save_templates(&templates)?;
+    let mut journal = Journal::load()?;
+    if journal.record(OperationKind::Add, &before, &after) {
+        journal.save()?;
+    }
+
+    Ok(generated)
+}
+
+/// Runs a single command against the ledger.
+fn run(
+    command: Commands,
+    output: OutputFormat,
+    backend: Backend,
+    config: &Config,
+) -> Result<(), ExpenseError> {
+    let generated = open_ledger(backend, &config.limits, Some(&command))?;
+    // Questions are asked before the lock is taken, so other commands are not kept waiting.
+    let command = ask_missing_fields(command, backend, config)?;
+
+    let _locks = match &command {
+        Commands::Move { to, .. } => {
+            require_profile(to)?;
+            lock_both_ledgers(to)?
+        }
+        command => vec![lock_ledger(!is_read_only(command))?],
+    };
+    let ledger = load_ledger(backend)?;
+    let mut all_expenses = ledger.expenses;
+    let budget = ledger.budget;
+    let limits = config.limits.clone();
+    let categories = load_categories()?;
+    let operation = operation_kind(&command);
+    let before = all_expenses.clone();
+
+    match command {
         Commands::Add {
             description,
             amount,
             category,
+            tags,
+            payee,
+            account,
+            income,
+            splits,
+            paid_by,
+            shared_with,
+            share_by,
+            receipt,
+            note,
+            location,
+            fields,
+            date,
         } => {
+            let (Some(description), Some(amount)) = (description, amount) else {
+                unreachable!("asked for before the ledger is loaded")
+            };
             // Create a new task
-            let new_expense = Expense::new(description, amount, map_category(&category));
+            let splits = splits
+                .iter()
+                .map(|split| parse_split(split, &categories))
+                .collect::<Result<Vec<_>, _>>()?;
+            let account = account
+                .map(|account| find_account(&load_accounts()?, &account).map(|a| a.name.clone()))
+                .transpose()?;
+            let payees = load_payees()?;
+            let payee_category = payee
+                .as_ref()
+                .and_then(|payee| find_payee(&payees, payee))
+                .and_then(|payee| payee.category.clone());
+            // Without --category, a split expense is filed under its largest part.
+            let largest_split = splits
+                .iter()
+                .max_by(|a, b| a.amount.total_cmp(&b.amount))
+                .map(|split| split.category.clone());
+            let category = match (category, largest_split.or(payee_category)) {
+                (Some(category), _) => resolve_category(&category, &categories),
+                (None, Some(category)) => category,
+                (None, None) => resolve_category(&config.default_category, &categories),
+            };
+            let mut new_expense = Expense::new(
+                validate_description(&description, &limits)?,
+                validate_amount(amount, &limits)?,
+                category,
+            );
+            new_expense.tags = tags;
+            new_expense.payee = payee
+                .map(|payee| normalize_payee(&payees, &payee, &limits))
+                .transpose()?;
+            new_expense.account = account;
+            if income {
+                new_expense.kind = TransactionKind::Income;
+            }
+            new_expense.splits = splits;
+            new_expense.receipt = receipt.map(store_receipt).transpose()?;
+            new_expense.note = note
+                .map(|note| validate_note(&note, &limits))
+                .transpose()?
+                .filter(|note| !note.is_empty());
+            new_expense.location = location
+                .map(|location| validate_description(&location, &limits))
+                .transpose()?;
+            for field in &fields {
+                let (key, value) = validate_field(field, &limits)?;
+                if value.is_empty() {
+                    return Err(ExpenseError::Validation(format!(
+                        "Field '{}' needs a value",
+                        key
+                    )));
+                }
+                new_expense.fields.insert(key, value);
+            }
+            if let Some(paid_by) = paid_by {
+                new_expense.shared = Some(Shared {
+                    paid_by: validate_description(&paid_by, &limits)?,
+                    shares: share(new_expense.amount, share_by, &shared_with, &limits)?,
+                });
+            }
+            if let Some(date) = date {
+                new_expense.added_at = date_to_timestamp(validate_date(&date, &limits)?);
+            }
+            validate_expense(&new_expense, &limits)?;
+            while search_expense_by_id(&all_expenses, &new_expense.id).is_some() {
+                new_expense.id = generate_random_id().to_string();
+            }
             all_expenses.push(new_expense.clone());
-            save_expenses(&all_expenses).unwrap();
-            prettify_expense_display(&all_expenses);
+            save_ledger(backend, &before, &all_expenses)?;
+
+            let month = new_expense.added_at.get(..7).unwrap_or_default();
+            if let Some(threshold) = config.crossed_threshold(
+                month_total(&before, month),
+                month_total(&all_expenses, month),
+                budget,
+            ) {
+                eprintln!(
+                    "Warning: spending for {} has reached {}% of the monthly budget ({})",
+                    month,
+                    threshold,
+                    config.format_amount(budget)
+                );
+            }
+            match output {
+                OutputFormat::Table => prettify_expense_display(&all_expenses),
+                _ => print_expense(output, &new_expense),
+            }
         }
         Commands::Update {
             id,
+            filter,
             description,
             amount,
             category,
+            payee,
+            account,
+            kind,
+            splits,
+            no_splits,
+            paid_by,
+            shared_with,
+            share_by,
+            not_shared,
+            note,
+            location,
+            fields,
+            date,
         } => {
-            let expense_index: usize = match search_expense_by_id(&all_expenses, id.as_str()) {
-                Some(index) => index,
-                None => {
-                    println!("No expense was found");
-                    process::exit(0);
+            let paid_by = paid_by
+                .map(|paid_by| validate_description(&paid_by, &limits))
+                .transpose()?;
+            let splits = splits
+                .iter()
+                .map(|split| parse_split(split, &categories))
+                .collect::<Result<Vec<_>, _>>()?;
+            let payee = payee
+                .map(|payee| normalize_payee(&load_payees()?, &payee, &limits))
+                .transpose()?;
+            let account = account
+                .map(|account| find_account(&load_accounts()?, &account).map(|a| a.name.clone()))
+                .transpose()?;
+            let description = description
+                .map(|description| validate_description(&description, &limits))
+                .transpose()?;
+            let amount = amount
+                .map(|amount| validate_amount(amount, &limits))
+                .transpose()?;
+            let added_at = date
+                .map(|date| validate_date(&date, &limits).map(date_to_timestamp))
+                .transpose()?;
+            let note = note.map(|note| validate_note(&note, &limits)).transpose()?;
+            let location = location
+                .map(|location| match location.trim() {
+                    "" => Ok(String::new()),
+                    location => validate_description(location, &limits),
+                })
+                .transpose()?;
+            let fields = fields
+                .iter()
+                .map(|field| validate_field(field, &limits))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            match kind {
+                Some(TransactionKind::Transfer) => {
+                    return Err(ExpenseError::Validation(
+                        "Use the transfer command to record a transfer".to_string(),
+                    ))
+                }
+                Some(TransactionKind::Settlement) => {
+                    return Err(ExpenseError::Validation(
+                        "Settlements are recorded by the settle command".to_string(),
+                    ))
                 }
+                _ => {}
+            }
+
+            let expense_indexes: Vec<usize> = match (id, parse_filter(filter)?) {
+                (Some(id), _) => match search_expense_by_id(&all_expenses, id.as_str()) {
+                    Some(index) => vec![index],
+                    None => return Err(not_found()),
+                },
+                (None, query) => all_expenses
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, expense)| query.as_ref().is_some_and(|q| q.matches(expense)))
+                    .map(|(index, _)| index)
+                    .collect(),
             };
 
-            if description != "no_update" {
-                all_expenses[expense_index].description = description.clone();
+            if expense_indexes.is_empty() {
+                return Err(not_found());
             }
 
-            // Update amount. Amount should be > 0.00
-            if amount <= 0.00 && amount != all_expenses[expense_index].amount && amount != -1.00 {
-                println!("Amount should be greater than 0.00");
-                process::exit(0);
-            } else if amount != -1.00 {
-                all_expenses[expense_index].amount = amount;
+            if description.is_none()
+                && amount.is_none()
+                && category.is_none()
+                && payee.is_none()
+                && account.is_none()
+                && kind.is_none()
+                && splits.is_empty()
+                && !no_splits
+                && paid_by.is_none()
+                && shared_with.is_empty()
+                && !not_shared
+                && note.is_none()
+                && location.is_none()
+                && fields.is_empty()
+                && added_at.is_none()
+            {
+                return Err(ExpenseError::Validation(
+                    "Nothing to update: pass --description, --amount, --category, --payee, --account, --kind, --split, --shared-with, --note, --location, --field or --date"
+                        .to_string(),
+                ));
             }
 
-            // Update category
-            if category != "no_update" {
-                all_expenses[expense_index].category = map_category(&category);
+            for &expense_index in &expense_indexes {
+                let expense = &mut all_expenses[expense_index];
+                if let Some(description) = &description {
+                    expense.description = description.clone();
+                }
+                if let Some(amount) = amount {
+                    expense.amount = amount;
+                }
+                if let Some(category) = &category {
+                    expense.category = resolve_category(category, &categories);
+                }
+                if let Some(payee) = &payee {
+                    expense.payee = Some(payee.clone());
+                }
+                if let Some(account) = &account {
+                    expense.account = Some(account.clone());
+                }
+                if !splits.is_empty() || no_splits {
+                    expense.splits = splits.clone();
+                }
+                if not_shared {
+                    expense.shared = None;
+                }
+                if let Some(paid_by) = &paid_by {
+                    match &mut expense.shared {
+                        Some(shared) => shared.paid_by = paid_by.clone(),
+                        None if shared_with.is_empty() => {
+                            return Err(ExpenseError::Validation(
+                                "The expense is not shared; pass --shared-with too".to_string(),
+                            ))
+                        }
+                        None => {}
+                    }
+                }
+                if !shared_with.is_empty() {
+                    let paid_by = paid_by
+                        .clone()
+                        .or_else(|| expense.shared.as_ref().map(|s| s.paid_by.clone()))
+                        .ok_or_else(|| {
+                            ExpenseError::Validation(
+                                "Pass --paid-by to share the expense".to_string(),
+                            )
+                        })?;
+                    expense.shared = Some(Shared {
+                        paid_by,
+                        shares: share(expense.amount, share_by, &shared_with, &limits)?,
+                    });
+                } else if let (Some(amount), Some(shared)) = (amount, &mut expense.shared) {
+                    // Without new participants, everyone keeps their part of the new amount.
+                    rescale(shared, amount)?;
+                }
+                if let Some(kind) = kind {
+                    expense.kind = kind;
+                    if kind != TransactionKind::Transfer {
+                        expense.to_account = None;
+                    }
+                }
+                if let Some(note) = &note {
+                    expense.note = Some(note.clone()).filter(|note| !note.is_empty());
+                }
+                if let Some(location) = &location {
+                    expense.location =
+                        Some(location.clone()).filter(|location| !location.is_empty());
+                }
+                for (key, value) in &fields {
+                    if value.is_empty() {
+                        expense.fields.remove(key);
+                    } else {
+                        expense.fields.insert(key.clone(), value.clone());
+                    }
+                }
+                if let Some(added_at) = &added_at {
+                    expense.added_at = added_at.clone();
+                }
+
+                // Update updated time
+                expense.updated_at = convert_from_system_time(SystemTime::now());
+                validate_expense(expense, &limits)?;
             }
 
-            // Update updated time
-            all_expenses[expense_index].updated_at = convert_from_system_time(SystemTime::now());
+            save_ledger(backend, &before, &all_expenses)?;
+            match output {
+                OutputFormat::Table => prettify_expense_display(&all_expenses),
+                _ => {
+                    let updated: Vec<Expense> = expense_indexes
+                        .iter()
+                        .map(|&index| all_expenses[index].clone())
+                        .collect();
+                    print_expenses(output, &updated, &Column::DEFAULT, 1, false);
+                }
+            }
+        }
+        Commands::Attach { id, file } => {
+            let index = search_expense_by_id(&all_expenses, &id).ok_or_else(not_found)?;
+            let receipt = store_receipt(&file)?;
+            let expense = &mut all_expenses[index];
+            expense.receipt = Some(receipt);
+            expense.updated_at = convert_from_system_time(SystemTime::now());
+            save_ledger(backend, &before, &all_expenses)?;
 
-            save_expenses(&all_expenses).unwrap();
-            prettify_expense_display(&all_expenses);
+            let expense = &all_expenses[index];
+            match output {
+                OutputFormat::Json => print_json(expense),
+                _ => print_message(
+                    output,
+                    &format!("Attached {} to expense {}", file.display(), expense.id),
+                ),
+            }
         }
-        Commands::Delete { id } => {
-            let expense_index = match search_expense_by_id(&all_expenses, id.as_str()) {
-                Some(index) => index,
-                None => {
-                    prettify_expense_not_found();
-                    process::exit(0);
+        Commands::Receipt { action } => {
+            let (ReceiptAction::Open { id } | ReceiptAction::Export { id, .. }) = &action;
+            let index = search_expense_by_id(&all_expenses, id).ok_or_else(not_found)?;
+            let receipt = all_expenses[index]
+                .receipt
+                .as_ref()
+                .ok_or_else(|| ExpenseError::NotFound(format!("Expense {} has no receipt", id)))?;
+            match action {
+                ReceiptAction::Open { .. } => open_receipt(receipt)?,
+                ReceiptAction::Export { to, force, .. } => {
+                    let path = export_receipt(receipt, &to, force)?;
+                    print_message(
+                        output,
+                        &format!("Exported {} to {}", receipt.name, path.display()),
+                    );
+                }
+            }
+        }
+        Commands::Delete { id, filter } => {
+            let deleted_expenses = match (id, parse_filter(filter)?) {
+                (Some(id), _) => {
+                    let expense_index =
+                        search_expense_by_id(&all_expenses, id.as_str()).ok_or_else(not_found)?;
+
+                    vec![delete_expense(expense_index, &mut all_expenses)]
+                }
+                (None, query) => {
+                    let deleted = filter_expenses(&all_expenses, query.as_ref());
+                    all_expenses
+                        .retain(|expense| !query.as_ref().is_some_and(|q| q.matches(expense)));
+                    deleted
                 }
             };
 
-            let deleted_expense = vec![delete_expense(expense_index, &mut all_expenses)];
-            save_expenses(&all_expenses).unwrap();
+            if deleted_expenses.is_empty() {
+                return Err(not_found());
+            }
+
+            save_ledger(backend, &before, &all_expenses)?;
 
-            prettify_expense_display(&deleted_expense);
+            print_expenses(output, &deleted_expenses, &Column::DEFAULT, 1, false);
         }
         Commands::List {
             id,
@@ -164,86 +1577,784 @@ async fn main() {
             amount,
             category,
             added_at,
+            filter,
+            sort,
+            desc,
+            limit,
+            offset,
+            columns,
+            expand,
         } => {
-            let mut filtered_expenses = all_expenses.clone();
+            let mut filtered_expenses =
+                filter_expenses(&all_expenses, parse_filter(filter)?.as_ref());
+            if expand {
+                filtered_expenses = expand_splits(&filtered_expenses);
+            }
 
             filtered_expenses.retain(|expense| {
                 (id == "all" || expense.id == id)
                     && (description == "all" || expense.description == description)
                     && (amount == 0.00 || expense.amount == amount)
-                    && (category == "all" || expense.category == map_category(&category))
+                    && (category == "all"
+                        || expense.category == resolve_category(&category, &categories))
                     && (added_at == "now" || expense.added_at == added_at)
             });
 
             if filtered_expenses.is_empty() {
-                prettify_expense_not_found();
+                print_expenses(output, &[], &Column::DEFAULT, 1, false);
+                return Ok(());
+            }
+
+            if let Some(sort) = sort {
+                sort_expenses(&mut filtered_expenses, sort, desc);
+            }
+
+            let matched = filtered_expenses.len();
+            let page: Vec<Expense> = filtered_expenses
+                .into_iter()
+                .skip(offset)
+                .take(limit.unwrap_or(usize::MAX))
+                .collect();
+            let columns = if columns.is_empty() {
+                let mut columns = Column::DEFAULT.to_vec();
+                if page.iter().any(|expense| expense.receipt.is_some()) {
+                    columns.push(Column::Receipt);
+                }
+                columns
             } else {
-                prettify_expense_display(&filtered_expenses);
+                columns
+            };
+
+            print_expenses(output, &page, &columns, offset + 1, true);
+            if output == OutputFormat::Table && page.len() < matched {
+                println!(
+                    "Showing {} of {} expenses (offset {})",
+                    page.len(),
+                    matched,
+                    offset
+                );
             }
         }
         Commands::Summary {
             category,
             amount,
             month,
+            week,
+            filter,
+            account,
+            group_by,
         } => {
-            let mut filtered_expenses = all_expenses.clone();
+            if !(1..=12).contains(&month) && month != 13 {
+                return Err(ExpenseError::InvalidMonth(month));
+            }
+            let today = Utc::now().date_naive();
+            let this_week = config.week_of(today);
+            // The forecast covers the whole ledger for the current month.
+            let forecast = (category == "all"
+                && amount == 0.00
+                && filter.is_none()
+                && account.is_none()
+                && !week
+                && (month == 13 || month == today.month()))
+            .then(|| {
+                load_templates().map(|templates| forecast(&all_expenses, &templates, budget, today))
+            })
+            .transpose()?;
 
+            let account = account
+                .map(|account| find_account(&load_accounts()?, &account).map(|a| a.name.clone()))
+                .transpose()?;
+            let mut filtered_expenses = Vec::new();
+            for expense in filter_expenses(&all_expenses, parse_filter(filter)?.as_ref()) {
+                if (amount == 0.00 || expense.amount == amount)
+                    && (account.is_none() || expense.account == account)
+                    && (month == 13 || get_month_from_date_string(&expense.added_at)? == month)
+                    && (!week || config.week_of(parse_date(expense_day(&expense))?) == this_week)
+                {
+                    filtered_expenses.push(expense);
+                }
+            }
+            // Split expenses count towards the category of each split.
+            let mut filtered_expenses = expand_splits(&filtered_expenses);
             filtered_expenses.retain(|expense| {
-                (category == "all" || expense.category == map_category(&category))
-                    && (amount == 0.00 || expense.amount == amount)
-                    && (month == 13 || get_month_from_date_string(&expense.added_at) == month)
+                category == "all" || expense.category == resolve_category(&category, &categories)
             });
 
-            if !(1..=12).contains(&month) && month != 13 {
-                println!("Invalid month. Month should be between 1 and 12");
-                process::exit(0);
+            // Get sum of all expenses in filtered expenses
+            let spending: Vec<&Expense> = filtered_expenses
+                .iter()
+                .filter(|expense| expense.is_spending())
+                .collect();
+            let mut spending_ids: Vec<&str> =
+                spending.iter().map(|expense| expense.id.as_str()).collect();
+            spending_ids.sort_unstable();
+            spending_ids.dedup();
+            let total = sum_amounts(spending.iter().map(|expense| expense.amount));
+            let income = sum_amounts(
+                filtered_expenses
+                    .iter()
+                    .filter(|expense| expense.is_income())
+                    .map(|expense| expense.amount),
+            );
+            let net = income - total;
+
+            let summary = Summary {
+                category: (category != "all")
+                    .then(|| resolve_category(&category, &categories).to_string()),
+                month: (1..=12).contains(&month).then_some(month),
+                week: week.then(|| this_week.to_string()),
+                count: spending_ids.len(),
+                total,
+                budget,
+                difference: budget - total,
+                income,
+                net,
+                savings_rate: (income > 0.0).then(|| net / income * 100.0),
+                forecast,
+                group_by: group_by.map(|key| format!("{:?}", key)),
+                groups: group_by
+                    .map(|key| group_expenses(&filtered_expenses, key))
+                    .unwrap_or_default(),
+            };
+
+            print_summary(output, &summary);
+            if output == OutputFormat::Table && group_by.is_none() {
+                prettify_expense_display(&filtered_expenses);
+            }
+        }
+        Commands::Upcoming { days } => {
+            let upcoming = upcoming(&load_templates()?, Utc::now().date_naive(), days);
+            let columns = [
+                Column::Date,
+                Column::Id,
+                Column::Description,
+                Column::Amount,
+                Column::Category,
+            ];
+            match output {
+                OutputFormat::Table if upcoming.is_empty() => print_message(
+                    output,
+                    &format!("No recurring expenses are due in the next {} days.", days),
+                ),
+                _ => print_expenses(output, &upcoming, &columns, 1, true),
+            }
+        }
+        Commands::Undo { steps } | Commands::Redo { steps } => {
+            let undo = matches!(command, Commands::Undo { .. });
+            let mut journal = Journal::load()?;
+            let mut applied = Vec::new();
+            for _ in 0..steps {
+                let operation = if undo {
+                    journal.undo(&mut all_expenses)?
+                } else {
+                    journal.redo(&mut all_expenses)?
+                };
+                applied.push(operation);
             }
 
-            // Get sum of all expenses in filtered expenses
-            let total: f64 = filtered_expenses.iter().map(|expense| expense.amount).sum();
+            save_ledger(backend, &before, &all_expenses)?;
+            journal.save()?;
 
-            if category != "all" {
-                println!(
-                    "\n\t\tTotal spent on {:?} stuff: {}\n\t\tBudget: {}\n\t\tDifference: {}\n",
-                    map_category(&category),
-                    total,
-                    budget,
-                    budget - total
-                );
-            } else if (1..=12).contains(&month) {
-                println!(
-                    "\n\t\tTotal spent on the month of {} stuff: {}\n\t\tBudget: {}\n\t\tDifference: {}\n",
-                    month, total, budget, budget - total
-                );
-            } else {
-                println!(
-                    "\n\t\tTotal spent on all stuff: {}\n\t\tBudget: {}\n\t\tDifference: {}\n",
-                    total,
+            let verb = if undo { "Undid" } else { "Redid" };
+            match output {
+                OutputFormat::Json => print_json(&applied),
+                _ => {
+                    for operation in &applied {
+                        print_message(output, &format!("{}: {}", verb, operation.summary()));
+                    }
+                }
+            }
+        }
+        Commands::Compact => {
+            if backend != Backend::Events {
+                return Err(ExpenseError::Validation(
+                    "compact only applies to the events backend (--backend events)".to_string(),
+                ));
+            }
+
+            let state = load_state(|| {
+                Ok(Snapshot {
+                    seq: 0,
+                    expenses: all_expenses.clone(),
                     budget,
-                    budget - total
+                })
+            })?;
+            let compaction = compact(&state)?;
+            match output {
+                OutputFormat::Json => print_json(&compaction),
+                _ => print_message(
+                    output,
+                    &format!(
+                        "Compacted {} events into a snapshot at sequence {}.",
+                        compaction.events_removed, compaction.seq
+                    ),
+                ),
+            }
+        }
+        Commands::History { limit } => {
+            let journal = Journal::load()?;
+            let skip = limit.map_or(0, |limit| journal.operations.len().saturating_sub(limit));
+            let entries: Vec<HistoryEntry> = journal
+                .operations
+                .iter()
+                .enumerate()
+                .skip(skip)
+                .map(|(index, operation)| HistoryEntry {
+                    number: index + 1,
+                    kind: operation.kind,
+                    summary: operation.summary(),
+                    at: operation.at.clone(),
+                    undone: index >= journal.cursor,
+                })
+                .collect();
+
+            print_history(output, &entries);
+        }
+        Commands::Export { file, filter } => {
+            let filtered_expenses = filter_expenses(&all_expenses, parse_filter(filter)?.as_ref());
+            export_expenses(&file, &filtered_expenses)?;
+            if output != OutputFormat::Table {
+                print_message(
+                    output,
+                    &format!("Exported {} expenses to {}", filtered_expenses.len(), file),
                 );
             }
+        }
+        Commands::Move {
+            id,
+            filter,
+            to,
+            copy,
+        } => {
+            if ledger_override().is_none() && to == profile() {
+                return Err(ExpenseError::Validation(format!(
+                    "The expenses are already in profile '{}'",
+                    to
+                )));
+            }
+
+            let moving = match (id, parse_filter(filter)?) {
+                (Some(id), _) => {
+                    let index =
+                        search_expense_by_id(&all_expenses, id.as_str()).ok_or_else(not_found)?;
+                    vec![all_expenses[index].clone()]
+                }
+                (None, query) => filter_expenses(&all_expenses, query.as_ref()),
+            };
+            if moving.is_empty() {
+                return Err(not_found());
+            }
+
+            let attachments = data_file("attachments");
+            let (received_before, received) =
+                with_profile(&to, || receive_expenses(backend, &moving, &attachments))?;
+            if !copy {
+                all_expenses.retain(|expense| !moving.iter().any(|moved| moved.id == expense.id));
+                if let Err(error) = save_ledger(backend, &before, &all_expenses) {
+                    // Otherwise the expenses would now be in both profiles.
+                    with_profile(&to, || save_ledger(backend, &received, &received_before))?;
+                    return Err(error);
+                }
+            }
+            with_profile(&to, || {
+                let mut journal = Journal::load()?;
+                if journal.record(OperationKind::Add, &received_before, &received) {
+                    journal.save()?;
+                }
+                Ok::<_, ExpenseError>(())
+            })?;
+            let count = moving.len();
 
-            prettify_expense_display(&filtered_expenses);
+            let verb = if copy { "Copied" } else { "Moved" };
+            print_message(
+                output,
+                &format!("{} {} expense(s) to profile '{}'", verb, count, to),
+            );
         }
-        Commands::Export { file } => export_expenses(&file, &all_expenses).unwrap(),
-        Commands::Budget { budget } => set_budget(budget),
-        Commands::Clear { clear } => {
-            if clear == "all" {
-                match clear_all_expenses() {
-                    Ok(()) => println!("All clear."),
-                    Err(e) => println!("{}", e),
+        Commands::Category { action } => match action {
+            CategoryAction::Add { name } => {
+                let mut categories = categories;
+                add_category(&mut categories, &name, &limits)?;
+                save_categories(&categories)?;
+                print_message(output, &format!("Category '{}' added", name.trim()));
+            }
+            CategoryAction::List => {
+                let entries: Vec<CategoryEntry> = BUILT_IN
+                    .iter()
+                    .map(|name| CategoryEntry {
+                        name: map_category(name).to_string(),
+                        custom: false,
+                    })
+                    .chain(categories.iter().map(|name| CategoryEntry {
+                        name: name.clone(),
+                        custom: true,
+                    }))
+                    .collect();
+                print_categories(output, &entries);
+            }
+            CategoryAction::Remove { name } => {
+                let mut categories = categories;
+                remove_category(&mut categories, &name)?;
+                save_categories(&categories)?;
+                print_message(output, &format!("Category '{}' removed", name.trim()));
+            }
+        },
+        Commands::Transfer {
+            from,
+            to,
+            amount,
+            description,
+            date,
+        } => {
+            let accounts = load_accounts()?;
+            let from = find_account(&accounts, &from)?.name.clone();
+            let to = find_account(&accounts, &to)?.name.clone();
+            if from == to {
+                return Err(ExpenseError::Validation(
+                    "A transfer needs two different accounts".to_string(),
+                ));
+            }
+            if amount <= 0.0 {
+                return Err(ExpenseError::Validation(
+                    "The amount of a transfer must be positive".to_string(),
+                ));
+            }
+
+            let mut transfer = Expense::transfer(from, to, validate_amount(amount, &limits)?);
+            if let Some(description) = description {
+                transfer.description = validate_description(&description, &limits)?;
+            }
+            if let Some(date) = date {
+                transfer.added_at = date_to_timestamp(validate_date(&date, &limits)?);
+            }
+            validate_expense(&transfer, &limits)?;
+            while search_expense_by_id(&all_expenses, &transfer.id).is_some() {
+                transfer.id = generate_random_id().to_string();
+            }
+            all_expenses.push(transfer.clone());
+            save_ledger(backend, &before, &all_expenses)?;
+
+            match output {
+                OutputFormat::Table => prettify_expense_display(&all_expenses),
+                _ => print_expense(output, &transfer),
+            }
+        }
+        Commands::Settle { dry_run } => {
+            let payments = settle(&net_balances(&all_expenses));
+            if payments.is_empty() {
+                print_message(output, "Everyone is settled up.");
+                return Ok(());
+            }
+
+            if !dry_run {
+                for payment in &payments {
+                    let mut entry = payment.to_expense();
+                    while search_expense_by_id(&all_expenses, &entry.id).is_some() {
+                        entry.id = generate_random_id().to_string();
+                    }
+                    all_expenses.push(entry);
                 }
-            } else {
-                print!("Maybe that is no the argument you wanted.");
-                println!("Try this: ");
-                println!(
-                    r#"
-                        -c all: To clear all expenses
-                        -c <month>: To clear for certain month
-                    "#
-                )
+                save_ledger(backend, &before, &all_expenses)?;
+            }
+            print_payments(output, &payments);
+        }
+        Commands::Balance => {
+            let balances = balances(&load_accounts()?, &all_expenses);
+            match output {
+                OutputFormat::Table if balances.is_empty() => print_message(
+                    output,
+                    "No accounts yet. Add one with `account add <name> --kind <kind>`.",
+                ),
+                _ => print_balances(output, &balances),
+            }
+        }
+        Commands::Account { action } => {
+            let mut accounts = load_accounts()?;
+            match action {
+                AccountAction::Add {
+                    name,
+                    kind,
+                    opening_balance,
+                } => {
+                    let name = name.trim().to_string();
+                    add_account(
+                        &mut accounts,
+                        Account {
+                            name: name.clone(),
+                            kind,
+                            opening_balance,
+                        },
+                        &limits,
+                    )?;
+                    save_accounts(&accounts)?;
+                    print_message(output, &format!("Account '{}' added", name));
+                }
+                AccountAction::List => print_accounts(output, &accounts),
+                AccountAction::Remove { name } => {
+                    let removed = remove_account(&mut accounts, &name)?;
+                    save_accounts(&accounts)?;
+                    print_message(output, &format!("Account '{}' removed", removed.name));
+                }
+            }
+        }
+        Commands::Payee { action } => {
+            let mut payees = load_payees()?;
+            match action {
+                PayeeAction::Add {
+                    name,
+                    aliases,
+                    category,
+                } => {
+                    let payee = Payee {
+                        name,
+                        aliases,
+                        category: category.map(|category| resolve_category(&category, &categories)),
+                    };
+                    let name = payee.name.trim().to_string();
+                    add_payee(&mut payees, payee, &limits)?;
+                    save_payees(&payees)?;
+                    print_message(output, &format!("Payee '{}' added", name));
+                }
+                PayeeAction::List => print_payees(output, &payees),
+                PayeeAction::Alias { name, alias } => {
+                    add_alias(&mut payees, &name, &alias, &limits)?;
+                    save_payees(&payees)?;
+                    print_message(
+                        output,
+                        &format!("'{}' is now an alias of '{}'", alias.trim(), name.trim()),
+                    );
+                }
+                PayeeAction::Remove { name } => {
+                    let removed = remove_payee(&mut payees, &name)?;
+                    save_payees(&payees)?;
+                    print_message(output, &format!("Payee '{}' removed", removed.name));
+                }
+            }
+        }
+        Commands::Recurring { action } => match action {
+            RecurringAction::Add {
+                description,
+                amount,
+                category,
+                tags,
+                every,
+                interval,
+                start,
+                end,
+                nth,
+                weekday,
+            } => {
+                let parse = |date: String| validate_date(&date, &limits);
+                let category = category.unwrap_or_else(|| config.default_category.clone());
+                let mut templates = load_templates()?;
+                let mut template = Template {
+                    id: generate_random_id().to_string(),
+                    description: validate_description(&description, &limits)?,
+                    amount: validate_amount(amount, &limits)?,
+                    category: resolve_category(&category, &categories),
+                    tags,
+                    frequency: every,
+                    interval,
+                    start: start
+                        .map(parse)
+                        .transpose()?
+                        .unwrap_or(Utc::now().date_naive()),
+                    end: end.map(parse).transpose()?,
+                    nth_weekday: nth
+                        .zip(weekday)
+                        .map(|(nth, weekday)| NthWeekday { nth, weekday }),
+                    last_generated: None,
+                };
+                template.validate()?;
+                while templates.iter().any(|existing| existing.id == template.id) {
+                    template.id = generate_random_id().to_string();
+                }
+                templates.push(template.clone());
+
+                // Occurrences up to today are due straight away.
+                materialize(
+                    &mut templates,
+                    &mut all_expenses,
+                    Utc::now().date_naive(),
+                    &limits,
+                )?;
+                save_ledger(backend, &before, &all_expenses)?;
+                save_templates(&templates)?;
+
+                let template = templates.pop().unwrap_or(template);
+                print_templates(output, &[template], Utc::now().date_naive());
+            }
+            RecurringAction::List => {
+                print_templates(output, &load_templates()?, Utc::now().date_naive())
+            }
+            RecurringAction::Remove { id } => {
+                let mut templates = load_templates()?;
+                let count = templates.len();
+                templates.retain(|template| template.id != id);
+                if templates.len() == count {
+                    return Err(ExpenseError::NotFound(format!(
+                        "No recurring expense with ID '{}'",
+                        id
+                    )));
+                }
+                save_templates(&templates)?;
+                print_message(output, &format!("Recurring expense {} removed", id));
+            }
+            RecurringAction::Run => match output {
+                OutputFormat::Table if generated.is_empty() => {
+                    print_message(output, "No recurring expenses are due.")
+                }
+                _ => print_expenses(output, &generated, &Column::DEFAULT, 1, false),
+            },
+        },
+        Commands::Budget { budget } => {
+            let budget = validate_budget(budget, &limits)?;
+            save_budget(backend, budget)?;
+            if output != OutputFormat::Table {
+                print_message(output, &format!("Budget set to {}", budget));
+            }
+        }
+        Commands::Clear {
+            yes,
+            month,
+            year,
+            from,
+            to,
+            category,
+            filter,
+        } => {
+            if let Some(month) = month.filter(|month| !(1..=12).contains(month)) {
+                return Err(ExpenseError::InvalidMonth(month));
+            }
+            let year = year.unwrap_or_else(|| Utc::now().year());
+            let from = from.map(|date| parse_date(&date)).transpose()?;
+            let to = to.map(|date| parse_date(&date)).transpose()?;
+            let query = parse_filter(filter)?;
+
+            let should_clear = |expense: &Expense| {
+                let added_at =
+                    NaiveDateTime::parse_from_str(&expense.added_at, "%Y-%m-%d %H:%M:%S")
+                        .ok()
+                        .map(|added_at| added_at.date());
+                let in_dates = match (month, from, to) {
+                    (None, None, None) => true,
+                    _ => added_at.is_some_and(|date| {
+                        month.is_none_or(|month| date.month() == month && date.year() == year)
+                            && from.is_none_or(|from| date >= from)
+                            && to.is_none_or(|to| date <= to)
+                    }),
+                };
+
+                in_dates
+                    && (category == "all"
+                        || expense.category == resolve_category(&category, &categories))
+                    && query.as_ref().is_none_or(|query| query.matches(expense))
+            };
+
+            let matching: Vec<&Expense> = all_expenses
+                .iter()
+                .filter(|expense| should_clear(expense))
+                .collect();
+            if matching.is_empty() {
+                print_message(output, "No expenses to clear.");
+                return Ok(());
+            }
+
+            // Income and transfers are cleared too, but only spending adds up to a total.
+            let total = sum_amounts(
+                matching
+                    .iter()
+                    .filter(|expense| expense.is_spending())
+                    .map(|expense| expense.amount),
+            );
+            let prompt = format!(
+                "This will clear {} of {} expenses (total spending {}). Are you sure?",
+                matching.len(),
+                all_expenses.len(),
+                total
+            );
+            if !yes && !confirm(&prompt)? {
+                print_message(output, "User quit!");
+                return Ok(());
             }
+
+            let cleared = clear_expenses(&mut all_expenses, should_clear);
+            save_ledger(backend, &before, &all_expenses)?;
+            print_message(output, &format!("Cleared {} expenses.", cleared.len()));
+        }
+    }
+
+    if let Some(kind) = operation {
+        let mut journal = Journal::load()?;
+        if journal.record(kind, &before, &all_expenses) {
+            journal.save()?;
         }
     }
+
+    Ok(())
+}
+
+/// Whether a command only reads the ledger and can share the ledger lock.
+fn is_read_only(command: &Commands) -> bool {
+    matches!(
+        command,
+        Commands::List { .. }
+            | Commands::Summary { .. }
+            | Commands::Upcoming { .. }
+            | Commands::Export { .. }
+            | Commands::History { .. }
+            | Commands::Balance
+            | Commands::Receipt { .. }
+            | Commands::Settle { dry_run: true }
+            | Commands::Account {
+                action: AccountAction::List
+            }
+            | Commands::Payee {
+                action: PayeeAction::List
+            }
+            | Commands::Category {
+                action: CategoryAction::List
+            }
+            | Commands::Recurring {
+                action: RecurringAction::List | RecurringAction::Run
+            }
+    )
+}
+
+/// The journal operation a command performs, if it changes the ledger.
+fn operation_kind(command: &Commands) -> Option<OperationKind> {
+    match command {
+        Commands::Add { .. } => Some(OperationKind::Add),
+        Commands::Update { .. } | Commands::Attach { .. } => Some(OperationKind::Update),
+        Commands::Delete { .. } => Some(OperationKind::Delete),
+        Commands::Clear { .. } => Some(OperationKind::Clear),
+        Commands::Move { .. } => Some(OperationKind::Delete),
+        Commands::Transfer { .. } => Some(OperationKind::Add),
+        Commands::Settle { dry_run: false } => Some(OperationKind::Add),
+        Commands::Recurring {
+            action: RecurringAction::Add { .. },
+        } => Some(OperationKind::Add),
+        _ => None,
+    }
+}
+
+/// Divides an amount between the people given to `--shared-with`.
+///
+/// Each entry is `PERSON` or `PERSON:VALUE`, where the value is a weight, an
+/// exact amount or a percentage depending on `method`. Without `--share-by` the
+/// expense is shared equally; values are then refused rather than ignored, as
+/// they could mean any of the other methods.
+fn share(
+    amount: f64,
+    method: Option<ShareMethod>,
+    people: &[String],
+    limits: &Limits,
+) -> Result<Vec<Share>, ExpenseError> {
+    let method = match (method, people.iter().find(|person| person.contains(':'))) {
+        (None | Some(ShareMethod::Equal), Some(person)) => {
+            return Err(ExpenseError::Validation(format!(
+                "'{}' gives a value, which needs --share-by shares, exact or percent",
+                person
+            )))
+        }
+        (method, _) => method.unwrap_or(ShareMethod::Equal),
+    };
+    let mut participants = Vec::new();
+    for person in people {
+        let (name, value) = match person.split_once(':') {
+            Some((name, value)) => {
+                let value = value.trim().parse::<f64>().map_err(|_| {
+                    ExpenseError::Validation(format!("Invalid share '{}' in '{}'", value, person))
+                })?;
+                (name, Some(value))
+            }
+            None => (person.as_str(), None),
+        };
+        let value = match (method, value) {
+            (ShareMethod::Equal, _) => 0.0,
+            (_, Some(value)) => value,
+            (_, None) => {
+                return Err(ExpenseError::Validation(format!(
+                    "'{}' needs a value, e.g. {}:1",
+                    person,
+                    person.trim()
+                )))
+            }
+        };
+        participants.push((validate_description(name, limits)?, value));
+    }
+
+    divide(amount, method, &participants)
+}
+
+/// Parses a `--split` value of the form `AMOUNT:CATEGORY[:NOTE]`.
+fn parse_split(split: &str, categories: &[String]) -> Result<Split, ExpenseError> {
+    let invalid = || {
+        ExpenseError::Validation(format!(
+            "Invalid split '{}' (expected AMOUNT:CATEGORY[:NOTE], e.g. 12.50:food)",
+            split
+        ))
+    };
+    let mut parts = split.splitn(3, ':');
+    let amount = parts
+        .next()
+        .and_then(|amount| amount.trim().parse::<f64>().ok())
+        .ok_or_else(invalid)?;
+    let category = parts
+        .next()
+        .filter(|category| !category.trim().is_empty())
+        .ok_or_else(invalid)?;
+    let note = parts
+        .next()
+        .map(str::trim)
+        .filter(|note| !note.is_empty())
+        .map(str::to_string);
+
+    Ok(Split {
+        amount,
+        category: resolve_category(category, categories),
+        note,
+    })
+}
+
+/// Parses a `--where` expression.
+fn parse_filter(filter: Option<String>) -> Result<Option<Query>, ExpenseError> {
+    let Some(source) = filter else {
+        return Ok(None);
+    };
+    match Query::parse(&source) {
+        Ok(query) => Ok(Some(query)),
+        Err(error) => Err(ExpenseError::InvalidQuery { source, error }),
+    }
+}
+
+/// Parses a `YYYY-MM-DD` date given on the command line.
+fn parse_date(date: &str) -> Result<NaiveDate, ExpenseError> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|_| ExpenseError::InvalidDate(date.to_string()))
+}
+
+/// Formats a date as the timestamp stored in `added_at`.
+fn date_to_timestamp(date: NaiveDate) -> String {
+    date.format("%Y-%m-%d 00:00:00").to_string()
+}
+
+/// The `YYYY-MM-DD` part of an expense's `added_at`.
+fn expense_day(expense: &Expense) -> &str {
+    expense.added_at.get(..10).unwrap_or_default()
+}
+
+/// The total spent in a month, given as `YYYY-MM`.
+fn month_total(expenses: &[Expense], month: &str) -> f64 {
+    sum_amounts(
+        expenses
+            .iter()
+            .filter(|expense| expense.is_spending() && expense.added_at.starts_with(month))
+            .map(|expense| expense.amount),
+    )
+}
+
+/// The error returned when no expense matches an ID or filter.
+fn not_found() -> ExpenseError {
+    ExpenseError::NotFound("No expense was found".to_string())
 }
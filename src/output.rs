@@ -0,0 +1,810 @@
+use crate::accounts::{Account, Balance};
+use crate::config;
+use crate::error::ExpenseError;
+use crate::forecast::Forecast;
+use crate::journal::OperationKind;
+use crate::payees::Payee;
+use crate::profiles::Profile;
+use crate::recurring::Template;
+use crate::shared::Payment;
+use crate::{prettify_expense_not_found, prettify_expense_table, Column, Expense};
+use chrono::NaiveDate;
+use serde::Serialize;
+use serde_json::json;
+use std::str::FromStr;
+
+/// How command results are printed.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputFormat {
+    /// Human readable comfy-table output.
+    #[default]
+    Table,
+    /// Pretty printed JSON.
+    Json,
+    /// Comma separated values with a header row.
+    Csv,
+    /// Tab separated values without a header, one record per line.
+    Plain,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format.to_lowercase().as_str() {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            "plain" => Ok(OutputFormat::Plain),
+            _ => Err(format!(
+                "unknown output format '{}' (expected table, json, csv or plain)",
+                format
+            )),
+        }
+    }
+}
+
+/// Totals reported by the `summary` command.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Summary {
+    pub category: Option<String>,
+    pub month: Option<u32>,
+    /// The first day of the week, for `summary --week`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub week: Option<String>,
+    pub count: usize,
+    pub total: f64,
+    pub budget: f64,
+    pub difference: f64,
+    /// The income received in the same period.
+    pub income: f64,
+    /// `income - total`.
+    pub net: f64,
+    /// `net` as a percentage of `income`, if there was any income.
+    pub savings_rate: Option<f64>,
+    /// The month-end forecast, for summaries of the current month.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub forecast: Option<Forecast>,
+    /// The field named by `summary --group-by`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group_by: Option<String>,
+    /// Totals per category, payee or account, for `summary --group-by`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<Group>,
+}
+
+/// The total of one category, payee or account, for `summary --group-by`.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Group {
+    pub name: String,
+    pub count: usize,
+    pub total: f64,
+}
+
+/// A row of the `category list` command.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct CategoryEntry {
+    pub name: String,
+    pub custom: bool,
+}
+
+/// A row of the `history` command.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct HistoryEntry {
+    pub number: usize,
+    pub kind: OperationKind,
+    pub summary: String,
+    pub at: String,
+    pub undone: bool,
+}
+
+/// Prints a list of expenses.
+///
+/// # Arguments
+///
+/// * `format` - The output format.
+/// * `expenses` - The expenses to print.
+/// * `columns` - The columns used by the table, CSV and plain formats.
+/// * `first_number` - The number shown in the `No` column of the first row.
+/// * `show_totals` - Whether the table gets a totals footer.
+pub fn print_expenses(
+    format: OutputFormat,
+    expenses: &[Expense],
+    columns: &[Column],
+    first_number: usize,
+    show_totals: bool,
+) {
+    match format {
+        OutputFormat::Table if expenses.is_empty() => prettify_expense_not_found(),
+        OutputFormat::Table => prettify_expense_table(expenses, columns, first_number, show_totals),
+        OutputFormat::Json => print_json(&expenses),
+        OutputFormat::Csv => {
+            println!(
+                "{}",
+                columns
+                    .iter()
+                    .map(|column| csv_field(column.header()))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            );
+            for (offset, expense) in expenses.iter().enumerate() {
+                println!(
+                    "{}",
+                    columns
+                        .iter()
+                        .map(|column| csv_field(&column.cell(first_number + offset, expense)))
+                        .collect::<Vec<_>>()
+                        .join(",")
+                );
+            }
+        }
+        OutputFormat::Plain => {
+            for (offset, expense) in expenses.iter().enumerate() {
+                println!(
+                    "{}",
+                    columns
+                        .iter()
+                        .map(|column| plain_field(&column.cell(first_number + offset, expense)))
+                        .collect::<Vec<_>>()
+                        .join("\t")
+                );
+            }
+        }
+    }
+}
+
+/// Prints a single expense, e.g. the one created by `add`.
+///
+/// # Arguments
+///
+/// * `format` - The output format.
+/// * `expense` - The expense to print.
+pub fn print_expense(format: OutputFormat, expense: &Expense) {
+    match format {
+        OutputFormat::Json => print_json(expense),
+        _ => print_expenses(
+            format,
+            std::slice::from_ref(expense),
+            &Column::DEFAULT,
+            1,
+            false,
+        ),
+    }
+}
+
+/// Prints the totals computed by `summary`.
+///
+/// # Arguments
+///
+/// * `format` - The output format.
+/// * `summary` - The totals to print.
+pub fn print_summary(format: OutputFormat, summary: &Summary) {
+    match format {
+        OutputFormat::Table => {
+            let mut subject = match (&summary.category, summary.month) {
+                (Some(category), _) => format!("{} stuff", category),
+                (None, Some(month)) => format!("the month of {} stuff", month),
+                (None, None) => "all stuff".to_string(),
+            };
+            if let Some(week) = &summary.week {
+                subject = format!("{} in the week of {}", subject, week);
+            }
+            let config = config::current();
+            println!(
+                "\n\t\tTotal spent on {}: {}\n\t\tBudget: {}\n\t\tDifference: {}\n",
+                subject,
+                config.format_amount(summary.total),
+                config.format_amount(summary.budget),
+                config.format_amount(summary.difference)
+            );
+            if let Some(savings_rate) = summary.savings_rate {
+                println!(
+                    "\t\tIncome: {}\n\t\tNet savings: {}\n\t\tSavings rate: {}%\n",
+                    config.format_amount(round_cents(summary.income)),
+                    config.format_amount(round_cents(summary.net)),
+                    round_cents(savings_rate)
+                );
+            }
+            if let Some(forecast) = &summary.forecast {
+                println!(
+                    "\t\tForecast for {}: {} (spent {} + scheduled {} + trend {})",
+                    forecast.month,
+                    config.format_amount(round_cents(forecast.projected)),
+                    config.format_amount(round_cents(forecast.actual)),
+                    config.format_amount(round_cents(forecast.scheduled)),
+                    config.format_amount(round_cents(forecast.trend))
+                );
+                if forecast.over_budget {
+                    println!(
+                        "\t\tWarning: on track to exceed the budget by {}",
+                        config.format_amount(round_cents(forecast.projected - forecast.budget))
+                    );
+                }
+                println!();
+            }
+            if let Some(group_by) = &summary.group_by {
+                let mut table = config.table();
+                table.set_header(vec![group_by.as_str(), "Count", "Total"]);
+                for group in &summary.groups {
+                    table.add_row(vec![
+                        group.name.clone(),
+                        group.count.to_string(),
+                        config.format_amount(round_cents(group.total)),
+                    ]);
+                }
+                println!("{}", table);
+            }
+        }
+        OutputFormat::Json => print_json(summary),
+        OutputFormat::Csv if summary.group_by.is_some() => {
+            println!("name,count,total");
+            for group in &summary.groups {
+                println!("{},{},{}", csv_field(&group.name), group.count, group.total);
+            }
+        }
+        OutputFormat::Csv => {
+            println!("category,month,count,total,budget,difference,income,net,savings_rate");
+            println!(
+                "{},{},{},{},{},{},{},{},{}",
+                csv_field(summary.category.as_deref().unwrap_or("")),
+                summary
+                    .month
+                    .map(|month| month.to_string())
+                    .unwrap_or_default(),
+                summary.count,
+                summary.total,
+                summary.budget,
+                summary.difference,
+                summary.income,
+                summary.net,
+                summary
+                    .savings_rate
+                    .map(|rate| rate.to_string())
+                    .unwrap_or_default()
+            );
+        }
+        OutputFormat::Plain => {
+            println!("total\t{}", summary.total);
+            println!("budget\t{}", summary.budget);
+            println!("difference\t{}", summary.difference);
+            println!("count\t{}", summary.count);
+            println!("income\t{}", summary.income);
+            println!("net\t{}", summary.net);
+            if let Some(savings_rate) = summary.savings_rate {
+                println!("savings_rate\t{}", savings_rate);
+            }
+            if let Some(forecast) = &summary.forecast {
+                println!("forecast\t{}", round_cents(forecast.projected));
+                println!("over_budget\t{}", forecast.over_budget);
+            }
+            for group in &summary.groups {
+                println!("{}\t{}\t{}", group.name, group.count, group.total);
+            }
+        }
+    }
+}
+
+/// Prints the operation journal.
+///
+/// # Arguments
+///
+/// * `format` - The output format.
+/// * `entries` - The journal entries, oldest first.
+pub fn print_history(format: OutputFormat, entries: &[HistoryEntry]) {
+    let state = |entry: &HistoryEntry| if entry.undone { "undone" } else { "applied" };
+    match format {
+        OutputFormat::Table => {
+            let mut table = config::current().table();
+            table.set_header(vec!["No", "Operation", "At", "State"]);
+            if entries.is_empty() {
+                table.add_row(vec!["No history yet"; 4]);
+            }
+            for entry in entries {
+                table.add_row(vec![
+                    entry.number.to_string(),
+                    entry.summary.clone(),
+                    entry.at.clone(),
+                    state(entry).to_string(),
+                ]);
+            }
+            println!("{}", table);
+        }
+        OutputFormat::Json => print_json(entries),
+        OutputFormat::Csv => {
+            println!("number,operation,at,state");
+            for entry in entries {
+                println!(
+                    "{},{},{},{}",
+                    entry.number,
+                    csv_field(&entry.summary),
+                    entry.at,
+                    state(entry)
+                );
+            }
+        }
+        OutputFormat::Plain => {
+            for entry in entries {
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    entry.number,
+                    entry.summary,
+                    entry.at,
+                    state(entry)
+                );
+            }
+        }
+    }
+}
+
+/// Prints recurring expense templates.
+///
+/// # Arguments
+///
+/// * `format` - The output format.
+/// * `templates` - The templates to print.
+/// * `today` - The date the `Next` column counts from.
+pub fn print_templates(format: OutputFormat, templates: &[Template], today: NaiveDate) {
+    // Occurrences up to today have already been generated.
+    let next = |template: &Template| {
+        template
+            .next_after(today)
+            .map(|date| date.to_string())
+            .unwrap_or_else(|| "ended".to_string())
+    };
+    match format {
+        OutputFormat::Table => {
+            let config = config::current();
+            let mut table = config.table();
+            table.set_header(vec![
+                "ID",
+                "Description",
+                "Amount",
+                "Category",
+                "Schedule",
+                "Next",
+            ]);
+            if templates.is_empty() {
+                table.add_row(vec!["No recurring expenses"; 5]);
+            }
+            for template in templates {
+                table.add_row(vec![
+                    template.id.clone(),
+                    template.description.clone(),
+                    config.format_amount(template.amount),
+                    template.category.to_string(),
+                    template.schedule(),
+                    next(template),
+                ]);
+            }
+            println!("{}", table);
+        }
+        OutputFormat::Json => print_json(templates),
+        OutputFormat::Csv => {
+            println!("id,description,amount,category,schedule,next");
+            for template in templates {
+                println!(
+                    "{},{},{},{},{},{}",
+                    template.id,
+                    csv_field(&template.description),
+                    template.amount,
+                    csv_field(&template.category.to_string()),
+                    csv_field(&template.schedule()),
+                    next(template)
+                );
+            }
+        }
+        OutputFormat::Plain => {
+            for template in templates {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}\t{}",
+                    template.id,
+                    template.description,
+                    template.amount,
+                    template.category,
+                    template.schedule(),
+                    next(template)
+                );
+            }
+        }
+    }
+}
+
+/// Prints the profiles in the data home.
+///
+/// # Arguments
+///
+/// * `format` - The output format.
+/// * `profiles` - The profiles to print.
+pub fn print_profiles(format: OutputFormat, profiles: &[Profile]) {
+    let marker = |profile: &Profile| if profile.active { "*" } else { "" };
+    match format {
+        OutputFormat::Table => {
+            let mut table = config::current().table();
+            table.set_header(vec!["Profile", "Active"]);
+            for profile in profiles {
+                table.add_row(vec![profile.name.as_str(), marker(profile)]);
+            }
+            println!("{}", table);
+        }
+        OutputFormat::Json => print_json(profiles),
+        OutputFormat::Csv => {
+            println!("name,active");
+            for profile in profiles {
+                println!("{},{}", csv_field(&profile.name), profile.active);
+            }
+        }
+        OutputFormat::Plain => {
+            for profile in profiles {
+                println!("{}\t{}", profile.name, marker(profile));
+            }
+        }
+    }
+}
+
+/// Prints the categories available in the current profile.
+///
+/// # Arguments
+///
+/// * `format` - The output format.
+/// * `categories` - The built-in categories followed by the custom ones.
+pub fn print_categories(format: OutputFormat, categories: &[CategoryEntry]) {
+    let kind = |entry: &CategoryEntry| if entry.custom { "custom" } else { "built-in" };
+    match format {
+        OutputFormat::Table => {
+            let mut table = config::current().table();
+            table.set_header(vec!["Category", "Kind"]);
+            for entry in categories {
+                table.add_row(vec![entry.name.as_str(), kind(entry)]);
+            }
+            println!("{}", table);
+        }
+        OutputFormat::Json => print_json(categories),
+        OutputFormat::Csv => {
+            println!("name,kind");
+            for entry in categories {
+                println!("{},{}", csv_field(&entry.name), kind(entry));
+            }
+        }
+        OutputFormat::Plain => {
+            for entry in categories {
+                println!("{}\t{}", entry.name, kind(entry));
+            }
+        }
+    }
+}
+
+/// Prints the current profile's accounts.
+///
+/// # Arguments
+///
+/// * `format` - The output format.
+/// * `accounts` - The accounts, in the order they were added.
+pub fn print_accounts(format: OutputFormat, accounts: &[Account]) {
+    match format {
+        OutputFormat::Table => {
+            let config = config::current();
+            let mut table = config.table();
+            table.set_header(vec!["Account", "Kind", "Opening Balance"]);
+            for account in accounts {
+                table.add_row(vec![
+                    account.name.clone(),
+                    account.kind.to_string(),
+                    config.format_amount(account.opening_balance),
+                ]);
+            }
+            println!("{}", table);
+        }
+        OutputFormat::Json => print_json(accounts),
+        OutputFormat::Csv => {
+            println!("name,kind,opening_balance");
+            for account in accounts {
+                println!(
+                    "{},{},{}",
+                    csv_field(&account.name),
+                    account.kind,
+                    account.opening_balance
+                );
+            }
+        }
+        OutputFormat::Plain => {
+            for account in accounts {
+                println!(
+                    "{}\t{}\t{}",
+                    account.name, account.kind, account.opening_balance
+                );
+            }
+        }
+    }
+}
+
+/// Prints the running balance of each account.
+///
+/// # Arguments
+///
+/// * `format` - The output format.
+/// * `balances` - The balances to print.
+pub fn print_balances(format: OutputFormat, balances: &[Balance]) {
+    match format {
+        OutputFormat::Table => {
+            let config = config::current();
+            let mut table = config.table();
+            table.set_header(vec![
+                "Account",
+                "Kind",
+                "Opening",
+                "Income",
+                "Spent",
+                "Transfers",
+                "Balance",
+            ]);
+            for balance in balances {
+                table.add_row(vec![
+                    balance.account.clone(),
+                    balance.kind.to_string(),
+                    config.format_amount(round_cents(balance.opening_balance)),
+                    config.format_amount(round_cents(balance.income)),
+                    config.format_amount(round_cents(balance.spent)),
+                    config.format_amount(round_cents(balance.transferred)),
+                    config.format_amount(round_cents(balance.balance)),
+                ]);
+            }
+            println!("{}", table);
+        }
+        OutputFormat::Json => print_json(balances),
+        OutputFormat::Csv => {
+            println!("account,kind,opening_balance,income,spent,transferred,balance");
+            for balance in balances {
+                println!(
+                    "{},{},{},{},{},{},{}",
+                    csv_field(&balance.account),
+                    balance.kind,
+                    balance.opening_balance,
+                    balance.income,
+                    balance.spent,
+                    balance.transferred,
+                    balance.balance
+                );
+            }
+        }
+        OutputFormat::Plain => {
+            for balance in balances {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                    balance.account,
+                    balance.kind,
+                    balance.opening_balance,
+                    balance.income,
+                    balance.spent,
+                    balance.transferred,
+                    balance.balance
+                );
+            }
+        }
+    }
+}
+
+/// Prints the payments that settle shared expenses.
+///
+/// # Arguments
+///
+/// * `format` - The output format.
+/// * `payments` - The payments to print.
+pub fn print_payments(format: OutputFormat, payments: &[Payment]) {
+    match format {
+        OutputFormat::Table => {
+            let config = config::current();
+            let mut table = config.table();
+            table.set_header(vec!["From", "To", "Amount"]);
+            for payment in payments {
+                table.add_row(vec![
+                    payment.from.clone(),
+                    payment.to.clone(),
+                    config.format_amount(payment.amount),
+                ]);
+            }
+            println!("{}", table);
+        }
+        OutputFormat::Json => print_json(payments),
+        OutputFormat::Csv => {
+            println!("from,to,amount");
+            for payment in payments {
+                println!(
+                    "{},{},{}",
+                    csv_field(&payment.from),
+                    csv_field(&payment.to),
+                    payment.amount
+                );
+            }
+        }
+        OutputFormat::Plain => {
+            for payment in payments {
+                println!("{}\t{}\t{}", payment.from, payment.to, payment.amount);
+            }
+        }
+    }
+}
+
+/// Prints the current profile's payees.
+///
+/// # Arguments
+///
+/// * `format` - The output format.
+/// * `payees` - The payees, in the order they were added.
+pub fn print_payees(format: OutputFormat, payees: &[Payee]) {
+    let category = |payee: &Payee| {
+        payee
+            .category
+            .as_ref()
+            .map(ToString::to_string)
+            .unwrap_or_default()
+    };
+    match format {
+        OutputFormat::Table => {
+            let mut table = config::current().table();
+            table.set_header(vec!["Payee", "Aliases", "Category"]);
+            for payee in payees {
+                table.add_row(vec![
+                    payee.name.clone(),
+                    payee.aliases.join(", "),
+                    category(payee),
+                ]);
+            }
+            println!("{}", table);
+        }
+        OutputFormat::Json => print_json(payees),
+        OutputFormat::Csv => {
+            println!("name,aliases,category");
+            for payee in payees {
+                println!(
+                    "{},{},{}",
+                    csv_field(&payee.name),
+                    csv_field(&payee.aliases.join(";")),
+                    csv_field(&category(payee))
+                );
+            }
+        }
+        OutputFormat::Plain => {
+            for payee in payees {
+                println!(
+                    "{}\t{}\t{}",
+                    payee.name,
+                    payee.aliases.join(","),
+                    category(payee)
+                );
+            }
+        }
+    }
+}
+
+/// Prints configuration settings as key/value pairs.
+///
+/// # Arguments
+///
+/// * `format` - The output format.
+/// * `settings` - The settings, in display order.
+pub fn print_settings(format: OutputFormat, settings: &[(String, String)]) {
+    match format {
+        OutputFormat::Table => {
+            let mut table = config::current().table();
+            table.set_header(vec!["Key", "Value"]);
+            for (key, value) in settings {
+                table.add_row(vec![key, value]);
+            }
+            println!("{}", table);
+        }
+        OutputFormat::Json => print_json(
+            &settings
+                .iter()
+                .map(|(key, value)| (key.clone(), json!(value)))
+                .collect::<serde_json::Map<_, _>>(),
+        ),
+        OutputFormat::Csv => {
+            println!("key,value");
+            for (key, value) in settings {
+                println!("{},{}", csv_field(key), csv_field(value));
+            }
+        }
+        OutputFormat::Plain => {
+            for (key, value) in settings {
+                println!("{}\t{}", key, value);
+            }
+        }
+    }
+}
+
+/// Prints a short status message such as "All clear.".
+///
+/// # Arguments
+///
+/// * `format` - The output format.
+/// * `message` - The message to print.
+pub fn print_message(format: OutputFormat, message: &str) {
+    match format {
+        OutputFormat::Json => print_json(&json!({ "message": message })),
+        OutputFormat::Csv => println!("message\n{}", csv_field(message)),
+        _ => println!("{}", message),
+    }
+}
+
+/// Prints an error to stderr.
+///
+/// Errors never go to stdout, whatever the format, so that a failing command
+/// cannot leave half a table or a stray message in piped output. In JSON mode the error is an object `{"error": {"code": ..., "message": ...}}`
+/// whose `code` is stable across releases and safe to match on in scripts.
+///
+/// # Arguments
+///
+/// * `format` - The output format.
+/// * `code` - A stable, machine-readable error code such as `not_found`.
+/// * `message` - The human readable message.
+pub fn print_error(format: OutputFormat, code: &str, message: &str) {
+    match format {
+        OutputFormat::Json => eprintln!(
+            "{}",
+            json!({ "error": { "code": code, "message": message } })
+        ),
+        _ => eprintln!("{}", message),
+    }
+}
+
+/// Prints any serializable value as pretty JSON.
+///
+/// A value that cannot be serialized is reported as a `storage` error.
+pub fn print_json<T: Serialize + ?Sized>(value: &T) {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => println!("{}", json),
+        Err(error) => {
+            let error = ExpenseError::Storage(error.into());
+            print_error(OutputFormat::Json, error.code(), &error.to_string());
+        }
+    }
+}
+
+/// Rounds an amount to cents for display.
+fn round_cents(amount: f64) -> f64 {
+    // Adding zero turns an amount that rounds to -0.0, such as -0.001, into 0.0.
+    (amount * 100.0).round() / 100.0 + 0.0
+}
+
+/// Escapes a field of plain output, so that every expense stays on one line
+/// with one tab between its fields.
+///
+/// # Arguments
+///
+/// * `field` - The raw field value.
+///
+/// # Returns
+///
+/// The field with backslashes, tabs and line breaks written as `\\`, `\t`,
+/// `\n` and `\r`.
+pub fn plain_field(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for character in field.chars() {
+        match character {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            character => escaped.push(character),
+        }
+    }
+
+    escaped
+}
+
+/// Quotes a CSV field when it contains a separator, quote or newline.
+///
+/// # Arguments
+///
+/// * `field` - The raw field value.
+///
+/// # Returns
+///
+/// The field, quoted and escaped if needed.
+pub fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
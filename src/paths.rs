@@ -0,0 +1,217 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+/// Environment variable naming the directory that holds the ledger and its companion files.
+pub const HOME_VAR: &str = "EXPENSE_TRACKER_HOME";
+
+/// Environment variable naming the profile to use.
+pub const PROFILE_VAR: &str = "EXPENSE_TRACKER_PROFILE";
+
+/// The profile whose files live directly in the data directory.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// File in the data directory recording the profile chosen with `profile switch`.
+pub const ACTIVE_PROFILE_FILE: &str = "active_profile";
+
+/// Environment variable naming the configuration file.
+pub const CONFIG_VAR: &str = "EXPENSE_TRACKER_CONFIG";
+
+/// File name of the ledger inside the data directory.
+pub const LEDGER_FILE: &str = "expenses.json";
+
+static LEDGER: RwLock<Option<PathBuf>> = RwLock::new(None);
+static PROFILE: RwLock<Option<String>> = RwLock::new(None);
+
+/// Uses an explicit ledger file, as given with `--ledger`.
+///
+/// Every other data file (budget, journal, event log, locks) is kept in the
+/// same directory as the ledger.
+///
+/// # Arguments
+///
+/// * `ledger` - Path of the ledger file, or `None` to go back to the default location.
+pub fn set_ledger(ledger: Option<PathBuf>) {
+    if let Ok(mut current) = LEDGER.write() {
+        *current = ledger;
+    }
+}
+
+/// The ledger file given with `--ledger`, if any.
+pub fn ledger_override() -> Option<PathBuf> {
+    LEDGER.read().ok().and_then(|ledger| ledger.clone())
+}
+
+/// Uses a named profile, as given with `--profile`.
+///
+/// # Arguments
+///
+/// * `profile` - The profile name, or `None` to use the active profile.
+pub fn set_profile(profile: Option<String>) {
+    if let Ok(mut current) = PROFILE.write() {
+        *current = profile;
+    }
+}
+
+/// The profile in use.
+///
+/// Resolved from, in order: `--profile`, `$EXPENSE_TRACKER_PROFILE`, the
+/// profile chosen with `profile switch`, and `default`.
+///
+/// # Returns
+///
+/// The profile name.
+pub fn profile() -> String {
+    if let Some(profile) = PROFILE.read().ok().and_then(|profile| profile.clone()) {
+        return profile;
+    }
+    if let Some(profile) = env::var(PROFILE_VAR).ok().filter(|p| !p.is_empty()) {
+        return profile;
+    }
+
+    fs::read_to_string(home_dir().join(ACTIVE_PROFILE_FILE))
+        .ok()
+        .map(|profile| profile.trim().to_string())
+        .filter(|profile| !profile.is_empty())
+        .unwrap_or_else(|| DEFAULT_PROFILE.to_string())
+}
+
+/// Runs `f` against another profile's files, then goes back to the current ones.
+///
+/// # Arguments
+///
+/// * `profile` - The profile to use while `f` runs.
+/// * `f` - The work to do.
+///
+/// # Returns
+///
+/// Whatever `f` returns.
+pub fn with_profile<T>(profile: &str, f: impl FnOnce() -> T) -> T {
+    let ledger = ledger_override();
+    let previous = PROFILE.read().ok().and_then(|profile| profile.clone());
+    set_ledger(None);
+    set_profile(Some(profile.to_string()));
+
+    let result = f();
+
+    set_ledger(ledger);
+    set_profile(previous);
+    result
+}
+
+/// The ledger file in use.
+///
+/// Resolved from, in order: `--ledger`, then `expenses.json` in the profile's
+/// directory (see `profile_dir`).
+///
+/// # Returns
+///
+/// The path of the ledger file.
+pub fn ledger_file() -> PathBuf {
+    if let Some(ledger) = ledger_override() {
+        return ledger;
+    }
+
+    profile_dir(&profile()).join(LEDGER_FILE)
+}
+
+/// The directory holding a profile's files.
+///
+/// The default profile uses the data home itself, so ledgers created before
+/// profiles existed keep working; other profiles use `profiles/<name>` inside it.
+///
+/// # Arguments
+///
+/// * `profile` - The profile name.
+///
+/// # Returns
+///
+/// The profile's directory, which need not exist.
+pub fn profile_dir(profile: &str) -> PathBuf {
+    if profile == DEFAULT_PROFILE {
+        home_dir()
+    } else {
+        home_dir().join("profiles").join(profile)
+    }
+}
+
+/// The directory holding the ledger and its companion files.
+///
+/// # Returns
+///
+/// The parent directory of `ledger_file()`.
+pub fn data_dir() -> PathBuf {
+    match ledger_file().parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// The path of a companion file next to the ledger, e.g. `budget.json`.
+///
+/// # Arguments
+///
+/// * `name` - The file name.
+///
+/// # Returns
+///
+/// The file's path inside `data_dir()`.
+pub fn data_file(name: &str) -> PathBuf {
+    data_dir().join(name)
+}
+
+/// The configuration file.
+///
+/// Resolved from `$EXPENSE_TRACKER_CONFIG`, then `config.toml` in the XDG
+/// config directory (`$XDG_CONFIG_HOME/expense-tracker`, by default
+/// `~/.config/expense-tracker`).
+///
+/// # Returns
+///
+/// The path of the configuration file, which need not exist.
+pub fn config_file() -> PathBuf {
+    if let Some(config) = env::var_os(CONFIG_VAR).filter(|config| !config.is_empty()) {
+        return PathBuf::from(config);
+    }
+
+    let xdg_config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| Path::new(dir).is_absolute())
+        .map(PathBuf::from)
+        .or_else(|| user_home().map(|home| home.join(".config")));
+
+    match xdg_config_home {
+        Some(dir) => dir.join("expense-tracker").join("config.toml"),
+        None => PathBuf::from("config.toml"),
+    }
+}
+
+/// The data home: `$EXPENSE_TRACKER_HOME`, or `$XDG_DATA_HOME/expense-tracker`
+/// (by default `~/.local/share/expense-tracker`).
+///
+/// # Returns
+///
+/// The directory holding the default profile and every other profile.
+pub fn home_dir() -> PathBuf {
+    if let Some(home) = env::var_os(HOME_VAR).filter(|home| !home.is_empty()) {
+        return PathBuf::from(home);
+    }
+
+    let xdg_data_home = env::var_os("XDG_DATA_HOME")
+        .filter(|dir| Path::new(dir).is_absolute())
+        .map(PathBuf::from)
+        .or_else(|| user_home().map(|home| home.join(".local").join("share")));
+
+    match xdg_data_home {
+        Some(dir) => dir.join("expense-tracker"),
+        None => PathBuf::from("."),
+    }
+}
+
+/// The user's home directory.
+pub(crate) fn user_home() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
@@ -0,0 +1,179 @@
+use crate::error::ExpenseError;
+use crate::files::write_atomic;
+use crate::paths::data_file;
+use crate::validation::{validate_description, Limits};
+use crate::{read_to_string_or, Category};
+use serde::{Deserialize, Serialize};
+use std::io;
+
+/// A known payee, with the other spellings that mean the same merchant.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Payee {
+    pub name: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// The category used for this payee when `--category` is not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<Category>,
+}
+
+impl Payee {
+    /// Whether `text` is this payee's name or one of its aliases, ignoring case.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The payee as typed.
+    pub fn matches(&self, text: &str) -> bool {
+        let text = text.trim();
+        self.name.eq_ignore_ascii_case(text)
+            || self
+                .aliases
+                .iter()
+                .any(|alias| alias.eq_ignore_ascii_case(text))
+    }
+}
+
+/// Loads the current profile's payees from `payees.json`.
+///
+/// # Returns
+///
+/// The payees, empty if none have been defined.
+pub fn load_payees() -> Result<Vec<Payee>, ExpenseError> {
+    let payees_file = data_file("payees.json");
+    let payees = read_to_string_or(&payees_file, "[]")?;
+
+    serde_json::from_str(&payees).map_err(|e| ExpenseError::corrupt(&payees_file, e))
+}
+
+/// Saves the current profile's payees to `payees.json`.
+///
+/// # Arguments
+///
+/// * `payees` - The payees to save.
+pub fn save_payees(payees: &[Payee]) -> Result<(), ExpenseError> {
+    let contents = serde_json::to_vec(payees).map_err(io::Error::from)?;
+
+    write_atomic(data_file("payees.json"), &contents)
+}
+
+/// Finds the payee a name or alias refers to.
+///
+/// # Arguments
+///
+/// * `payees` - The payee list.
+/// * `text` - The payee as typed.
+///
+/// # Returns
+///
+/// The matching payee, if any.
+pub fn find_payee<'a>(payees: &'a [Payee], text: &str) -> Option<&'a Payee> {
+    payees.iter().find(|payee| payee.matches(text))
+}
+
+/// Normalizes a payee as typed on the command line.
+///
+/// # Arguments
+///
+/// * `payees` - The payee list.
+/// * `text` - The payee as typed.
+/// * `limits` - The limits an unknown payee is checked against.
+///
+/// # Returns
+///
+/// The canonical name of a known payee, or the trimmed text for an unknown one.
+pub fn normalize_payee(
+    payees: &[Payee],
+    text: &str,
+    limits: &Limits,
+) -> Result<String, ExpenseError> {
+    match find_payee(payees, text) {
+        Some(payee) => Ok(payee.name.clone()),
+        None => validate_description(text, limits),
+    }
+}
+
+/// Adds a payee to the list.
+///
+/// # Arguments
+///
+/// * `payees` - The payee list; updated in place.
+/// * `payee` - The new payee.
+/// * `limits` - The limits its name and aliases are checked against.
+///
+/// # Returns
+///
+/// `ExpenseError::Validation` if its name or an alias is empty or already used.
+pub fn add_payee(
+    payees: &mut Vec<Payee>,
+    mut payee: Payee,
+    limits: &Limits,
+) -> Result<(), ExpenseError> {
+    payee.name = validate_description(&payee.name, limits)?;
+    payee.aliases = payee
+        .aliases
+        .iter()
+        .map(|alias| validate_description(alias, limits))
+        .collect::<Result<_, _>>()?;
+
+    for name in std::iter::once(&payee.name).chain(&payee.aliases) {
+        if find_payee(payees, name).is_some() {
+            return Err(ExpenseError::Validation(format!(
+                "'{}' is already used by another payee",
+                name
+            )));
+        }
+    }
+
+    payees.push(payee);
+    Ok(())
+}
+
+/// Adds an alias to an existing payee.
+///
+/// # Arguments
+///
+/// * `payees` - The payee list; updated in place.
+/// * `name` - The payee, by name or alias.
+/// * `alias` - The new alias.
+/// * `limits` - The limits the alias is checked against.
+pub fn add_alias(
+    payees: &mut [Payee],
+    name: &str,
+    alias: &str,
+    limits: &Limits,
+) -> Result<(), ExpenseError> {
+    let alias = validate_description(alias, limits)?;
+    if find_payee(payees, &alias).is_some() {
+        return Err(ExpenseError::Validation(format!(
+            "'{}' is already used by another payee",
+            alias
+        )));
+    }
+
+    let payee = payees
+        .iter_mut()
+        .find(|payee| payee.matches(name))
+        .ok_or_else(|| unknown_payee(name))?;
+    payee.aliases.push(alias);
+
+    Ok(())
+}
+
+/// Removes a payee from the list. Expenses already paid to it keep the name.
+///
+/// # Arguments
+///
+/// * `payees` - The payee list; updated in place.
+/// * `name` - The payee, by name or alias.
+pub fn remove_payee(payees: &mut Vec<Payee>, name: &str) -> Result<Payee, ExpenseError> {
+    let index = payees
+        .iter()
+        .position(|payee| payee.matches(name))
+        .ok_or_else(|| unknown_payee(name))?;
+
+    Ok(payees.remove(index))
+}
+
+fn unknown_payee(name: &str) -> ExpenseError {
+    ExpenseError::NotFound(format!("No payee named '{}'", name.trim()))
+}
@@ -1,7 +1,8 @@
 use postgres::{Client, NoTls};
 
-pub fn post() -> Result<(), Box<dyn std::error::Error>> {
-    let mut client = Client::connect("host=localhost user=postgres", NoTls)?;
+/// Runs the Postgres example against `connection`, e.g. `host=localhost user=postgres`.
+pub fn post(connection: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = Client::connect(connection, NoTls)?;
 
     client.batch_execute(
         "
@@ -0,0 +1,141 @@
+use crate::error::ExpenseError;
+use crate::files::write_atomic;
+use crate::paths::{home_dir, profile, profile_dir, ACTIVE_PROFILE_FILE, DEFAULT_PROFILE};
+use serde::Serialize;
+use std::fs;
+
+/// A row of `profile list`.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    pub active: bool,
+}
+
+/// Lists the profiles in the data home.
+///
+/// # Returns
+///
+/// The default profile followed by the others in alphabetical order.
+pub fn list_profiles() -> Result<Vec<Profile>, ExpenseError> {
+    let mut names = Vec::new();
+    match fs::read_dir(home_dir().join("profiles")) {
+        Ok(entries) => {
+            for entry in entries {
+                let entry = entry?;
+                if entry.file_type()?.is_dir() {
+                    names.push(entry.file_name().to_string_lossy().into_owned());
+                }
+            }
+        }
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+        Err(error) => return Err(error.into()),
+    }
+    names.sort();
+    names.insert(0, DEFAULT_PROFILE.to_string());
+
+    let active = profile();
+    Ok(names
+        .into_iter()
+        .map(|name| Profile {
+            active: name == active,
+            name,
+        })
+        .collect())
+}
+
+/// Creates an empty profile.
+///
+/// # Arguments
+///
+/// * `name` - The profile name: letters, digits, `-` and `_`.
+///
+/// # Returns
+///
+/// `ExpenseError::Validation` if the name is invalid or already taken.
+pub fn create_profile(name: &str) -> Result<(), ExpenseError> {
+    validate_profile_name(name)?;
+    if profile_exists(name) {
+        return Err(ExpenseError::Validation(format!(
+            "Profile '{}' already exists",
+            name
+        )));
+    }
+
+    fs::create_dir_all(profile_dir(name))?;
+    Ok(())
+}
+
+/// Makes a profile the one used when no `--profile` is given.
+///
+/// # Arguments
+///
+/// * `name` - An existing profile.
+pub fn switch_profile(name: &str) -> Result<(), ExpenseError> {
+    require_profile(name)?;
+    fs::create_dir_all(home_dir())?;
+
+    write_atomic(home_dir().join(ACTIVE_PROFILE_FILE), name.as_bytes())
+}
+
+/// Deletes a profile and all of its files.
+///
+/// # Arguments
+///
+/// * `name` - An existing profile other than `default` and the active one.
+pub fn delete_profile(name: &str) -> Result<(), ExpenseError> {
+    require_profile(name)?;
+    if name == DEFAULT_PROFILE {
+        return Err(ExpenseError::Validation(
+            "The default profile cannot be deleted".to_string(),
+        ));
+    }
+    if name == profile() {
+        return Err(ExpenseError::Validation(format!(
+            "Profile '{}' is in use; switch to another profile first",
+            name
+        )));
+    }
+
+    fs::remove_dir_all(profile_dir(name))?;
+    Ok(())
+}
+
+/// Checks that a profile exists.
+///
+/// # Arguments
+///
+/// * `name` - The profile name.
+///
+/// # Returns
+///
+/// `ExpenseError::NotFound` if there is no such profile.
+pub fn require_profile(name: &str) -> Result<(), ExpenseError> {
+    if profile_exists(name) {
+        Ok(())
+    } else {
+        Err(ExpenseError::NotFound(format!(
+            "Profile '{}' does not exist",
+            name
+        )))
+    }
+}
+
+fn profile_exists(name: &str) -> bool {
+    name == DEFAULT_PROFILE || (validate_profile_name(name).is_ok() && profile_dir(name).is_dir())
+}
+
+fn validate_profile_name(name: &str) -> Result<(), ExpenseError> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(ExpenseError::Validation(format!(
+            "Invalid profile name '{}' (use letters, digits, '-' and '_')",
+            name
+        )))
+    }
+}
@@ -0,0 +1,562 @@
+use crate::Expense;
+use chrono::{NaiveDate, NaiveDateTime};
+use std::fmt;
+
+/// A parsed `--where` expression.
+///
+/// The grammar is intentionally small:
+///
+/// ```text
+/// expr       := and_expr ("or" and_expr)*
+/// and_expr   := unary ("and" unary)*
+/// unary      := "not" unary | "(" expr ")" | "tag:" value | field op value
+/// field      := id | description | amount | category | date | tag | payee | account | kind
+///               | receipt | note | location | "field." key
+/// op         := = | != | > | >= | < | <= | ~
+/// ```
+///
+/// Values may be bare words or quoted with `"` or `'`. `field.KEY` compares the
+/// custom field `KEY` set with `--field`; an expense without it has an empty value.
+///
+/// # Example
+///
+/// `category = food and amount > 20 and date >= 2025-01-01 and tag:work`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    root: Node,
+}
+
+/// An error produced while parsing a query, pointing at the offending token.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryError {
+    pub message: String,
+    pub token: String,
+    pub position: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    Compare(Field, Op, Value),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Field {
+    Id,
+    Description,
+    Amount,
+    Category,
+    Date,
+    Tag,
+    Payee,
+    Account,
+    Kind,
+    Receipt,
+    Note,
+    Location,
+    /// A user-defined field, by lowercase key.
+    Custom(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Contains,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Text(String),
+    Number(f64),
+    Date(NaiveDate),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Word(String),
+    Quoted(String),
+    Op(Op),
+    TagPrefix,
+    LParen,
+    RParen,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    kind: TokenKind,
+    text: String,
+    position: usize,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.token.is_empty() {
+            write!(f, "{} at position {}", self.message, self.position)
+        } else {
+            write!(
+                f,
+                "{} at position {}: '{}'",
+                self.message, self.position, self.token
+            )
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+impl QueryError {
+    fn new(message: impl Into<String>, token: &str, position: usize) -> Self {
+        Self {
+            message: message.into(),
+            token: token.to_string(),
+            position,
+        }
+    }
+
+    /// Renders the error under the original query with a caret under the bad token.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The query string the error was produced from.
+    ///
+    /// # Returns
+    ///
+    /// A multi-line message suitable for printing to the terminal.
+    pub fn pretty(&self, source: &str) -> String {
+        let offset = source
+            .char_indices()
+            .take_while(|(index, _)| *index < self.position)
+            .count();
+        let width = self.token.chars().count().max(1);
+        format!(
+            "{}\n\t{}\n\t{}{}",
+            self,
+            source,
+            " ".repeat(offset),
+            "^".repeat(width)
+        )
+    }
+}
+
+impl Query {
+    /// Parses a query expression.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The expression to parse.
+    ///
+    /// # Returns
+    ///
+    /// The parsed `Query`, or a `QueryError` pointing at the bad token.
+    pub fn parse(source: &str) -> Result<Self, QueryError> {
+        let tokens = tokenize(source)?;
+        if tokens.is_empty() {
+            return Err(QueryError::new("Empty query", "", 0));
+        }
+
+        let mut parser = Parser {
+            tokens,
+            cursor: 0,
+            end: source.len(),
+        };
+        let root = parser.parse_or()?;
+
+        if let Some(token) = parser.peek() {
+            return Err(QueryError::new(
+                "Expected 'and', 'or' or end of query",
+                &token.text,
+                token.position,
+            ));
+        }
+
+        Ok(Self { root })
+    }
+
+    /// Checks whether an expense satisfies the query.
+    ///
+    /// # Arguments
+    ///
+    /// * `expense` - The expense to test.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the expense matches.
+    pub fn matches(&self, expense: &Expense) -> bool {
+        self.root.matches(expense)
+    }
+}
+
+impl std::str::FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        Query::parse(source)
+    }
+}
+
+impl Node {
+    fn matches(&self, expense: &Expense) -> bool {
+        match self {
+            Node::And(left, right) => left.matches(expense) && right.matches(expense),
+            Node::Or(left, right) => left.matches(expense) || right.matches(expense),
+            Node::Not(inner) => !inner.matches(expense),
+            Node::Compare(field, op, value) => compare(expense, field, *op, value),
+        }
+    }
+}
+
+fn compare(expense: &Expense, field: &Field, op: Op, value: &Value) -> bool {
+    match (field, value) {
+        (Field::Amount, Value::Number(number)) => compare_ord(expense.amount, op, *number),
+        (Field::Date, Value::Date(date)) => {
+            match NaiveDateTime::parse_from_str(&expense.added_at, "%Y-%m-%d %H:%M:%S") {
+                Ok(added_at) => compare_ord(added_at.date(), op, *date),
+                Err(_) => false,
+            }
+        }
+        (Field::Tag, Value::Text(text)) => {
+            let found = expense
+                .tags
+                .iter()
+                .any(|tag| compare_text(tag, Op::Eq, text));
+            match op {
+                Op::Ne => !found,
+                Op::Contains => expense
+                    .tags
+                    .iter()
+                    .any(|tag| compare_text(tag, Op::Contains, text)),
+                _ => found,
+            }
+        }
+        (Field::Id, Value::Text(text)) => compare_text(&expense.id, op, text),
+        (Field::Description, Value::Text(text)) => compare_text(&expense.description, op, text),
+        (Field::Category, Value::Text(text)) => {
+            compare_text(&expense.category.to_string(), op, text)
+        }
+        (Field::Payee, Value::Text(text)) => {
+            compare_text(expense.payee.as_deref().unwrap_or_default(), op, text)
+        }
+        (Field::Account, Value::Text(text)) => {
+            compare_text(expense.account.as_deref().unwrap_or_default(), op, text)
+        }
+        (Field::Kind, Value::Text(text)) => compare_text(&expense.kind.to_string(), op, text),
+        (Field::Receipt, Value::Text(text)) => compare_text(
+            expense
+                .receipt
+                .as_ref()
+                .map_or("", |receipt| receipt.name.as_str()),
+            op,
+            text,
+        ),
+        (Field::Note, Value::Text(text)) => {
+            compare_text(expense.note.as_deref().unwrap_or_default(), op, text)
+        }
+        (Field::Location, Value::Text(text)) => {
+            compare_text(expense.location.as_deref().unwrap_or_default(), op, text)
+        }
+        (Field::Custom(key), Value::Text(text)) => {
+            compare_text(expense.fields.get(key).map_or("", String::as_str), op, text)
+        }
+        _ => false,
+    }
+}
+
+fn compare_ord<T: PartialOrd>(left: T, op: Op, right: T) -> bool {
+    match op {
+        Op::Eq => left == right,
+        Op::Ne => left != right,
+        Op::Gt => left > right,
+        Op::Ge => left >= right,
+        Op::Lt => left < right,
+        Op::Le => left <= right,
+        Op::Contains => false,
+    }
+}
+
+fn compare_text(left: &str, op: Op, right: &str) -> bool {
+    let left = left.to_lowercase();
+    let right = right.to_lowercase();
+    match op {
+        Op::Contains => left.contains(&right),
+        _ => compare_ord(left.as_str(), op, right.as_str()),
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+
+    while let Some(&(position, character)) = chars.peek() {
+        if character.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let single = |kind: TokenKind| Token {
+            kind,
+            text: character.to_string(),
+            position,
+        };
+
+        match character {
+            '(' => {
+                chars.next();
+                tokens.push(single(TokenKind::LParen));
+            }
+            ')' => {
+                chars.next();
+                tokens.push(single(TokenKind::RParen));
+            }
+            '=' | '~' => {
+                chars.next();
+                let op = if character == '=' {
+                    Op::Eq
+                } else {
+                    Op::Contains
+                };
+                tokens.push(single(TokenKind::Op(op)));
+            }
+            '!' | '>' | '<' => {
+                chars.next();
+                let followed_by_eq = matches!(chars.peek(), Some((_, '=')));
+                if followed_by_eq {
+                    chars.next();
+                }
+                let op = match (character, followed_by_eq) {
+                    ('!', true) => Op::Ne,
+                    ('>', false) => Op::Gt,
+                    ('>', true) => Op::Ge,
+                    ('<', false) => Op::Lt,
+                    ('<', true) => Op::Le,
+                    _ => return Err(QueryError::new("Expected '!='", "!", position)),
+                };
+                let text = if followed_by_eq {
+                    format!("{}=", character)
+                } else {
+                    character.to_string()
+                };
+                tokens.push(Token {
+                    kind: TokenKind::Op(op),
+                    text,
+                    position,
+                });
+            }
+            '"' | '\'' => {
+                chars.next();
+                let mut value = String::new();
+                let mut closed = false;
+                for (_, next) in chars.by_ref() {
+                    if next == character {
+                        closed = true;
+                        break;
+                    }
+                    value.push(next);
+                }
+                if !closed {
+                    return Err(QueryError::new(
+                        "Unterminated string",
+                        &source[position..],
+                        position,
+                    ));
+                }
+                tokens.push(Token {
+                    text: format!("{}{}{}", character, value, character),
+                    kind: TokenKind::Quoted(value),
+                    position,
+                });
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&(_, next)) = chars.peek() {
+                    if next.is_whitespace() || "()=~!<>\"'".contains(next) {
+                        break;
+                    }
+                    word.push(next);
+                    chars.next();
+                    if word.eq_ignore_ascii_case("tag:") {
+                        break;
+                    }
+                }
+                let kind = if word.eq_ignore_ascii_case("tag:") {
+                    TokenKind::TagPrefix
+                } else {
+                    TokenKind::Word(word.clone())
+                };
+                tokens.push(Token {
+                    kind,
+                    text: word,
+                    position,
+                });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    cursor: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.cursor)
+    }
+
+    fn next(&mut self, expected: &str) -> Result<Token, QueryError> {
+        match self.tokens.get(self.cursor) {
+            Some(token) => {
+                self.cursor += 1;
+                Ok(token.clone())
+            }
+            None => Err(QueryError::new(
+                format!("Unexpected end of query, expected {}", expected),
+                "",
+                self.end,
+            )),
+        }
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(
+            self.peek(),
+            Some(Token { kind: TokenKind::Word(word), .. }) if word.eq_ignore_ascii_case(keyword)
+        )
+    }
+
+    fn parse_or(&mut self) -> Result<Node, QueryError> {
+        let mut node = self.parse_and()?;
+        while self.peek_keyword("or") {
+            self.cursor += 1;
+            node = Node::Or(Box::new(node), Box::new(self.parse_and()?));
+        }
+        Ok(node)
+    }
+
+    fn parse_and(&mut self) -> Result<Node, QueryError> {
+        let mut node = self.parse_unary()?;
+        while self.peek_keyword("and") {
+            self.cursor += 1;
+            node = Node::And(Box::new(node), Box::new(self.parse_unary()?));
+        }
+        Ok(node)
+    }
+
+    fn parse_unary(&mut self) -> Result<Node, QueryError> {
+        if self.peek_keyword("not") {
+            self.cursor += 1;
+            return Ok(Node::Not(Box::new(self.parse_unary()?)));
+        }
+
+        let token = self.next("a condition")?;
+        match token.kind {
+            TokenKind::LParen => {
+                let node = self.parse_or()?;
+                let closing = self.next("')'")?;
+                if closing.kind != TokenKind::RParen {
+                    return Err(QueryError::new(
+                        "Expected ')'",
+                        &closing.text,
+                        closing.position,
+                    ));
+                }
+                Ok(node)
+            }
+            TokenKind::TagPrefix => {
+                let value = self.parse_value(&Field::Tag)?;
+                Ok(Node::Compare(Field::Tag, Op::Eq, value))
+            }
+            TokenKind::Word(word) => {
+                let field = parse_field(&word)
+                    .ok_or_else(|| QueryError::new("Unknown field", &token.text, token.position))?;
+                let op_token = self.next("an operator")?;
+                let op = match op_token.kind {
+                    TokenKind::Op(op) => op,
+                    _ => {
+                        return Err(QueryError::new(
+                            "Expected an operator (=, !=, >, >=, <, <=, ~)",
+                            &op_token.text,
+                            op_token.position,
+                        ))
+                    }
+                };
+                let ordered = matches!(op, Op::Gt | Op::Ge | Op::Lt | Op::Le);
+                let textual = matches!(op, Op::Contains);
+                if (ordered && field == Field::Tag)
+                    || (textual && matches!(field, Field::Amount | Field::Date))
+                {
+                    return Err(QueryError::new(
+                        format!("Operator not supported for field '{}'", word),
+                        &op_token.text,
+                        op_token.position,
+                    ));
+                }
+                let value = self.parse_value(&field)?;
+                Ok(Node::Compare(field, op, value))
+            }
+            _ => Err(QueryError::new(
+                "Expected a field name",
+                &token.text,
+                token.position,
+            )),
+        }
+    }
+
+    fn parse_value(&mut self, field: &Field) -> Result<Value, QueryError> {
+        let token = self.next("a value")?;
+        let raw = match &token.kind {
+            TokenKind::Word(word) => word.clone(),
+            TokenKind::Quoted(value) => value.clone(),
+            _ => {
+                return Err(QueryError::new(
+                    "Expected a value",
+                    &token.text,
+                    token.position,
+                ))
+            }
+        };
+
+        match field {
+            Field::Amount => raw
+                .parse::<f64>()
+                .map(Value::Number)
+                .map_err(|_| QueryError::new("Expected a number", &token.text, token.position)),
+            Field::Date => NaiveDate::parse_from_str(&raw, "%Y-%m-%d")
+                .map(Value::Date)
+                .map_err(|_| {
+                    QueryError::new("Expected a date as YYYY-MM-DD", &token.text, token.position)
+                }),
+            _ => Ok(Value::Text(raw)),
+        }
+    }
+}
+
+fn parse_field(word: &str) -> Option<Field> {
+    match word.to_lowercase().as_str() {
+        "id" => Some(Field::Id),
+        "description" => Some(Field::Description),
+        "amount" => Some(Field::Amount),
+        "category" => Some(Field::Category),
+        "date" => Some(Field::Date),
+        "tag" => Some(Field::Tag),
+        "payee" => Some(Field::Payee),
+        "account" => Some(Field::Account),
+        "kind" => Some(Field::Kind),
+        "receipt" => Some(Field::Receipt),
+        "note" => Some(Field::Note),
+        "location" => Some(Field::Location),
+        word => word
+            .strip_prefix("field.")
+            .filter(|key| !key.is_empty())
+            .map(|key| Field::Custom(key.to_string())),
+    }
+}
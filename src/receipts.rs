@@ -0,0 +1,193 @@
+use crate::error::ExpenseError;
+use crate::files::write_atomic;
+use crate::paths::data_file;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// A receipt file attached to an expense.
+///
+/// The file itself lives in the `attachments` directory next to the ledger,
+/// named after the SHA-256 hash of its contents, so attaching the same file
+/// twice stores it once.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Receipt {
+    /// The hex SHA-256 hash of the file.
+    pub hash: String,
+    /// The original file name, used when exporting the receipt.
+    pub name: String,
+}
+
+impl Receipt {
+    /// Where the receipt is stored: under its hash alone, so the same contents
+    /// are stored once whatever the files they came from were called.
+    ///
+    /// # Returns
+    ///
+    /// The path, or `ExpenseError::Validation` if the recorded hash is not 64
+    /// lowercase hex characters; an edited ledger could otherwise point outside
+    /// the attachments directory.
+    pub fn path(&self) -> Result<PathBuf, ExpenseError> {
+        let valid = self.hash.len() == 64
+            && self
+                .hash
+                .bytes()
+                .all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'));
+        if !valid {
+            return Err(ExpenseError::Validation(format!(
+                "'{}' is not a receipt hash",
+                self.hash
+            )));
+        }
+
+        Ok(data_file("attachments").join(&self.hash))
+    }
+
+    /// The original file name without any directories, safe to join to a path.
+    fn file_name(&self) -> &OsStr {
+        Path::new(&self.name)
+            .file_name()
+            .unwrap_or(OsStr::new("receipt"))
+    }
+}
+
+/// Copies a file into the attachments directory.
+///
+/// # Arguments
+///
+/// * `source` - The receipt to store.
+///
+/// # Returns
+///
+/// The receipt to record on the expense, or `ExpenseError::NotFound` if there
+/// is no such file.
+pub fn store_receipt(source: impl AsRef<Path>) -> Result<Receipt, ExpenseError> {
+    let source = source.as_ref();
+    let contents = fs::read(source).map_err(|error| match error.kind() {
+        io::ErrorKind::NotFound => {
+            ExpenseError::NotFound(format!("No file at {}", source.display()))
+        }
+        _ => error.into(),
+    })?;
+    let name = source
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| ExpenseError::Validation(format!("{} is not a file", source.display())))?;
+
+    let receipt = Receipt {
+        hash: hash(&contents),
+        name,
+    };
+    let path = receipt.path()?;
+    if !path.exists() {
+        fs::create_dir_all(data_file("attachments"))?;
+        write_atomic(&path, &contents)?;
+    }
+
+    Ok(receipt)
+}
+
+/// Copies a stored receipt out of the attachments directory.
+///
+/// # Arguments
+///
+/// * `receipt` - The receipt to export.
+/// * `destination` - A file to write, or a directory to write the receipt into
+///   under its original name.
+/// * `overwrite` - Whether an existing file may be replaced.
+///
+/// # Returns
+///
+/// The path written, `ExpenseError::Validation` if the file exists and
+/// `overwrite` is not set, or `ExpenseError::Corrupt` if the stored file no
+/// longer matches its hash.
+pub fn export_receipt(
+    receipt: &Receipt,
+    destination: impl AsRef<Path>,
+    overwrite: bool,
+) -> Result<PathBuf, ExpenseError> {
+    let contents = read_receipt(receipt)?;
+    let destination = destination.as_ref();
+    let destination = if destination.is_dir() {
+        destination.join(receipt.file_name())
+    } else {
+        destination.to_path_buf()
+    };
+    if !overwrite && destination.exists() {
+        return Err(ExpenseError::Validation(format!(
+            "{} already exists; pass --force to replace it",
+            destination.display()
+        )));
+    }
+
+    write_atomic(&destination, &contents)?;
+    Ok(destination)
+}
+
+/// Opens a stored receipt with the system's default viewer.
+///
+/// # Arguments
+///
+/// * `receipt` - The receipt to open.
+pub fn open_receipt(receipt: &Receipt) -> Result<(), ExpenseError> {
+    let contents = read_receipt(receipt)?;
+    // The stored file has no extension; a copy under the original name lets the
+    // system pick the right viewer.
+    let directory = env::temp_dir().join(format!("expense-tracker-{}", receipt.hash));
+    fs::create_dir_all(&directory)?;
+    let path = directory.join(receipt.file_name());
+    write_atomic(&path, &contents)?;
+
+    let mut command = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        Command::new("xdg-open")
+    };
+    let status = command.arg(&path).status()?;
+    if !status.success() {
+        return Err(ExpenseError::Storage(io::Error::other(format!(
+            "the viewer for {} exited with {}",
+            path.display(),
+            status
+        ))));
+    }
+
+    Ok(())
+}
+
+/// Reads a stored receipt and checks it against its hash.
+fn read_receipt(receipt: &Receipt) -> Result<Vec<u8>, ExpenseError> {
+    let path = receipt.path()?;
+    let contents = fs::read(&path).map_err(|error| match error.kind() {
+        io::ErrorKind::NotFound => ExpenseError::NotFound(format!(
+            "The receipt {} is missing from {}",
+            receipt.name,
+            path.display()
+        )),
+        _ => error.into(),
+    })?;
+    if hash(&contents) != receipt.hash {
+        return Err(ExpenseError::corrupt(
+            &path,
+            "the contents do not match the recorded hash",
+        ));
+    }
+
+    Ok(contents)
+}
+
+fn hash(contents: &[u8]) -> String {
+    Sha256::digest(contents)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
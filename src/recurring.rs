@@ -0,0 +1,324 @@
+use crate::error::ExpenseError;
+use crate::files::write_atomic;
+use crate::paths::data_file;
+use crate::validation::{validate_expense, Limits};
+use crate::{generate_random_id, read_to_string_or, Category, Expense};
+use chrono::{Datelike, Days, Months, NaiveDate, Weekday};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::str::FromStr;
+
+/// How often a template repeats.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// A monthly schedule on a weekday rather than a day of the month, e.g. the
+/// second Tuesday (`nth` 2) or the last Friday (`nth` -1).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct NthWeekday {
+    pub nth: i8,
+    pub weekday: Weekday,
+}
+
+/// A recurring expense such as rent or a subscription.
+///
+/// Occurrences fall on `start` and then every `interval` days, weeks, months
+/// or years. Monthly and yearly schedules keep the day of the month of `start`,
+/// moved back to the last day of shorter months.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Template {
+    pub id: String,
+    pub description: String,
+    pub amount: f64,
+    pub category: Category,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub frequency: Frequency,
+    pub interval: u32,
+    pub start: NaiveDate,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<NaiveDate>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nth_weekday: Option<NthWeekday>,
+    /// The latest occurrence turned into an expense.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_generated: Option<NaiveDate>,
+}
+
+impl FromStr for Frequency {
+    type Err = String;
+
+    fn from_str(frequency: &str) -> Result<Self, Self::Err> {
+        match frequency.to_lowercase().as_str() {
+            "daily" | "day" => Ok(Frequency::Daily),
+            "weekly" | "week" => Ok(Frequency::Weekly),
+            "monthly" | "month" => Ok(Frequency::Monthly),
+            "yearly" | "year" | "annually" => Ok(Frequency::Yearly),
+            _ => Err(format!(
+                "unknown frequency '{}' (expected daily, weekly, monthly or yearly)",
+                frequency
+            )),
+        }
+    }
+}
+
+impl Template {
+    /// Checks that the schedule makes sense.
+    ///
+    /// # Returns
+    ///
+    /// `ExpenseError::Validation` describing the first problem found.
+    pub fn validate(&self) -> Result<(), ExpenseError> {
+        let invalid = |message: &str| Err(ExpenseError::Validation(message.to_string()));
+        if self.interval == 0 {
+            return invalid("The interval must be at least 1");
+        }
+        if self.end.is_some_and(|end| end < self.start) {
+            return invalid("The end date is before the start date");
+        }
+        if let Some(NthWeekday { nth, .. }) = self.nth_weekday {
+            if self.frequency != Frequency::Monthly {
+                return invalid("A weekday schedule (--nth/--weekday) needs --every monthly");
+            }
+            if !(1..=5).contains(&nth) && nth != -1 {
+                return invalid("--nth must be between 1 and 5, or -1 for the last one");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The occurrences in a date range.
+    ///
+    /// # Arguments
+    ///
+    /// * `after` - Only occurrences after this date, or from `start` if `None`.
+    /// * `until` - The last date to include.
+    ///
+    /// # Returns
+    ///
+    /// The occurrence dates in order, stopping at `end`.
+    pub fn occurrences(&self, after: Option<NaiveDate>, until: NaiveDate) -> Vec<NaiveDate> {
+        let until = self.end.map_or(until, |end| end.min(until));
+        let mut dates = Vec::new();
+
+        for step in 0u32.. {
+            let date = match self.nth_weekday {
+                Some(NthWeekday { nth, weekday }) => {
+                    let Some(month) = self.month_of(step) else {
+                        break;
+                    };
+                    if month > until {
+                        break;
+                    }
+                    match weekday_of_month(month.year(), month.month(), weekday, nth) {
+                        Some(date) => date,
+                        // Not every month has a fifth weekday.
+                        None => continue,
+                    }
+                }
+                None => match self.nth_occurrence(step) {
+                    Some(date) => date,
+                    None => break,
+                },
+            };
+            if date > until {
+                break;
+            }
+            if date >= self.start && after.is_none_or(|after| date > after) {
+                dates.push(date);
+            }
+        }
+
+        dates
+    }
+
+    /// The first occurrence after `date`, if the schedule has not ended.
+    ///
+    /// # Arguments
+    ///
+    /// * `date` - The date to look after.
+    pub fn next_after(&self, date: NaiveDate) -> Option<NaiveDate> {
+        // Every schedule repeats at least once in `interval` years plus a few
+        // months (for weekday schedules skipping months without a fifth weekday).
+        let horizon = date.checked_add_months(Months::new(12 * self.interval + 6))?;
+
+        self.occurrences(Some(date), horizon).into_iter().next()
+    }
+
+    /// A readable description of the schedule, e.g. `every 2 months on the 2nd Tue`.
+    pub fn schedule(&self) -> String {
+        let unit = match self.frequency {
+            Frequency::Daily => "day",
+            Frequency::Weekly => "week",
+            Frequency::Monthly => "month",
+            Frequency::Yearly => "year",
+        };
+        let mut schedule = match self.interval {
+            1 => format!("every {}", unit),
+            n => format!("every {} {}s", n, unit),
+        };
+        if let Some(NthWeekday { nth, weekday }) = self.nth_weekday {
+            let nth = match nth {
+                -1 => "last".to_string(),
+                1 => "1st".to_string(),
+                2 => "2nd".to_string(),
+                3 => "3rd".to_string(),
+                n => format!("{}th", n),
+            };
+            schedule.push_str(&format!(" on the {} {}", nth, weekday));
+        }
+        if let Some(end) = self.end {
+            schedule.push_str(&format!(" until {}", end));
+        }
+
+        schedule
+    }
+
+    /// The expense generated for an occurrence.
+    ///
+    /// # Arguments
+    ///
+    /// * `date` - The occurrence date, used as the expense date.
+    pub fn expense_on(&self, date: NaiveDate) -> Expense {
+        let mut expense =
+            Expense::new(self.description.clone(), self.amount, self.category.clone());
+        expense.added_at = date.format("%Y-%m-%d 00:00:00").to_string();
+        expense.tags = self.tags.clone();
+        expense.recurring = Some(self.id.clone());
+
+        expense
+    }
+
+    /// The `step`th occurrence of a day-based schedule, counting from `start`.
+    fn nth_occurrence(&self, step: u32) -> Option<NaiveDate> {
+        let count = step.checked_mul(self.interval)?;
+        match self.frequency {
+            Frequency::Daily => self.start.checked_add_days(Days::new(count.into())),
+            Frequency::Weekly => self.start.checked_add_days(Days::new(u64::from(count) * 7)),
+            Frequency::Monthly => self.start.checked_add_months(Months::new(count)),
+            Frequency::Yearly => self
+                .start
+                .checked_add_months(Months::new(count.checked_mul(12)?)),
+        }
+    }
+
+    /// The first day of the month of the `step`th occurrence of a weekday schedule.
+    fn month_of(&self, step: u32) -> Option<NaiveDate> {
+        self.start
+            .with_day(1)?
+            .checked_add_months(Months::new(step.checked_mul(self.interval)?))
+    }
+}
+
+/// Loads the current profile's templates from `recurring.json`.
+///
+/// # Returns
+///
+/// The templates, empty if none have been defined.
+pub fn load_templates() -> Result<Vec<Template>, ExpenseError> {
+    let templates_file = data_file("recurring.json");
+    let templates = read_to_string_or(&templates_file, "[]")?;
+
+    serde_json::from_str(&templates).map_err(|e| ExpenseError::corrupt(&templates_file, e))
+}
+
+/// Saves the current profile's templates to `recurring.json`.
+///
+/// # Arguments
+///
+/// * `templates` - The templates to save.
+pub fn save_templates(templates: &[Template]) -> Result<(), ExpenseError> {
+    let contents = serde_json::to_vec(templates).map_err(io::Error::from)?;
+
+    write_atomic(data_file("recurring.json"), &contents)
+}
+
+/// Whether any template has an occurrence that has not been generated yet.
+///
+/// # Arguments
+///
+/// * `templates` - The templates.
+/// * `today` - The last date that is due.
+pub fn any_due(templates: &[Template], today: NaiveDate) -> bool {
+    templates.iter().any(|template| {
+        !template
+            .occurrences(template.last_generated, today)
+            .is_empty()
+    })
+}
+
+/// Generates the expenses for every occurrence up to `today`.
+///
+/// Occurrences for which the ledger already holds an expense from the same
+/// template on the same day are skipped, so running this twice, or after an
+/// interrupted run, never duplicates entries.
+///
+/// # Arguments
+///
+/// * `templates` - The templates; `last_generated` is updated in place.
+/// * `expenses` - The ledger; new expenses are appended.
+/// * `today` - The last date that is due.
+/// * `limits` - The limits every generated expense is checked against.
+///
+/// # Returns
+///
+/// The generated expenses, or a `Validation` error naming the first template
+/// whose expense is out of range. `expenses` may then hold part of the run,
+/// so it must not be saved.
+pub fn materialize(
+    templates: &mut [Template],
+    expenses: &mut Vec<Expense>,
+    today: NaiveDate,
+    limits: &Limits,
+) -> Result<Vec<Expense>, ExpenseError> {
+    let mut generated = Vec::new();
+
+    for template in templates.iter_mut() {
+        for date in template.occurrences(template.last_generated, today) {
+            let day = date.format("%Y-%m-%d").to_string();
+            let exists = expenses.iter().any(|expense| {
+                expense.recurring.as_ref() == Some(&template.id)
+                    && expense.added_at.starts_with(&day)
+            });
+            if !exists {
+                let mut expense = template.expense_on(date);
+                validate_expense(&expense, limits).map_err(|error| {
+                    ExpenseError::Validation(format!(
+                        "Recurring expense {} cannot be added for {}: {}",
+                        template.id, day, error
+                    ))
+                })?;
+                while expenses.iter().any(|existing| existing.id == expense.id) {
+                    expense.id = generate_random_id().to_string();
+                }
+                expenses.push(expense.clone());
+                generated.push(expense);
+            }
+            template.last_generated = Some(date);
+        }
+    }
+
+    Ok(generated)
+}
+
+/// The `nth` (1-5, or -1 for the last) `weekday` of a month.
+fn weekday_of_month(year: i32, month: u32, weekday: Weekday, nth: i8) -> Option<NaiveDate> {
+    if nth == -1 {
+        let first_of_next =
+            NaiveDate::from_ymd_opt(year, month, 1)?.checked_add_months(Months::new(1))?;
+        let mut date = first_of_next.pred_opt()?;
+        while date.weekday() != weekday {
+            date = date.pred_opt()?;
+        }
+        return Some(date);
+    }
+
+    NaiveDate::from_weekday_of_month_opt(year, month, weekday, u8::try_from(nth).ok()?)
+}
@@ -0,0 +1,355 @@
+use crate::error::ExpenseError;
+use crate::{sum_amounts, Category, Expense, TransactionKind};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::str::FromStr;
+
+/// Who paid for a shared expense and what each participant owes of it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Shared {
+    pub paid_by: String,
+    pub shares: Vec<Share>,
+}
+
+/// A participant's part of a shared expense.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Share {
+    pub person: String,
+    pub amount: f64,
+}
+
+/// How a shared expense is divided between its participants.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShareMethod {
+    /// Everyone pays the same.
+    Equal,
+    /// In proportion to each participant's weight, e.g. `alice:2,bob:1`.
+    Shares,
+    /// Exact amounts that add up to the total.
+    Exact,
+    /// Percentages that add up to 100.
+    Percent,
+}
+
+/// A payment that settles debts between two people.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Payment {
+    pub from: String,
+    pub to: String,
+    pub amount: f64,
+}
+
+impl FromStr for ShareMethod {
+    type Err = String;
+
+    fn from_str(method: &str) -> Result<Self, Self::Err> {
+        match method.to_lowercase().as_str() {
+            "equal" | "equally" => Ok(ShareMethod::Equal),
+            "shares" | "weights" => Ok(ShareMethod::Shares),
+            "exact" | "amounts" => Ok(ShareMethod::Exact),
+            "percent" | "percentage" | "percentages" => Ok(ShareMethod::Percent),
+            _ => Err(format!(
+                "unknown share method '{}' (expected equal, shares, exact or percent)",
+                method
+            )),
+        }
+    }
+}
+
+impl Payment {
+    /// The ledger entry recording this payment.
+    ///
+    /// It is neither spending nor income; as a shared entry paid by `from`
+    /// and owed by `to`, it cancels out their balances.
+    pub fn to_expense(&self) -> Expense {
+        let mut expense = Expense::new(
+            format!("Settlement: {} pays {}", self.from, self.to),
+            self.amount,
+            Category::Other,
+        );
+        expense.kind = TransactionKind::Settlement;
+        expense.shared = Some(Shared {
+            paid_by: self.from.clone(),
+            shares: vec![Share {
+                person: self.to.clone(),
+                amount: self.amount,
+            }],
+        });
+
+        expense
+    }
+}
+
+/// Divides an amount between participants.
+///
+/// Amounts are worked out in cents; any cent left over by rounding goes to the
+/// first participants, so the shares always add up to `amount` exactly.
+///
+/// # Arguments
+///
+/// * `amount` - The total to divide.
+/// * `method` - How to divide it.
+/// * `participants` - Each person with their weight, exact amount or percentage;
+///   the value is ignored for `ShareMethod::Equal`.
+///
+/// # Returns
+///
+/// One share per participant, or `ExpenseError::Validation` if the values do not
+/// fit the method.
+pub fn divide(
+    amount: f64,
+    method: ShareMethod,
+    participants: &[(String, f64)],
+) -> Result<Vec<Share>, ExpenseError> {
+    let invalid = |message: String| Err(ExpenseError::Validation(message));
+    if participants.is_empty() {
+        return invalid("A shared expense needs at least one participant".to_string());
+    }
+    if participants.iter().any(|(_, value)| *value < 0.0) {
+        return invalid("Shares cannot be negative".to_string());
+    }
+
+    let total: f64 = participants.iter().map(|(_, value)| value).sum();
+    let weights: Vec<f64> = match method {
+        ShareMethod::Equal => vec![1.0; participants.len()],
+        ShareMethod::Shares if total <= 0.0 => {
+            return invalid("The shares add up to zero".to_string());
+        }
+        ShareMethod::Exact if (total - amount).abs() >= 0.005 => {
+            return invalid(format!(
+                "The exact shares add up to {} but the expense is {}",
+                total, amount
+            ));
+        }
+        ShareMethod::Percent if (total - 100.0).abs() >= 0.005 => {
+            return invalid(format!("The percentages add up to {}, not 100", total));
+        }
+        _ => participants.iter().map(|(_, value)| *value).collect(),
+    };
+
+    let to_cents = |amount: f64| (amount * 100.0).round() as i64;
+    let cents = to_cents(amount);
+    let mut parts: Vec<i64> = match method {
+        // Exact amounts are kept as given: dividing by their float total could
+        // floor 5.00 of 6.70 to 4.99.
+        ShareMethod::Exact => participants
+            .iter()
+            .map(|(_, value)| to_cents(*value))
+            .collect(),
+        _ => {
+            let weight_total: f64 = weights.iter().sum();
+            weights
+                .iter()
+                .map(|weight| (cents as f64 * weight / weight_total).floor() as i64)
+                .collect()
+        }
+    };
+    let mut left = cents - parts.iter().sum::<i64>();
+    for part in parts.iter_mut() {
+        if left == 0 {
+            break;
+        }
+        *part += left.signum();
+        left -= left.signum();
+    }
+
+    Ok(participants
+        .iter()
+        .zip(parts)
+        .map(|((person, _), cents)| Share {
+            person: person.trim().to_string(),
+            amount: cents as f64 / 100.0,
+        })
+        .collect())
+}
+
+/// Divides a new amount between the participants of a shared expense, keeping
+/// each one's part of the old amount.
+///
+/// # Arguments
+///
+/// * `shared` - The shares to update in place.
+/// * `amount` - The expense's new amount.
+///
+/// # Returns
+///
+/// `Ok(())`, or `ExpenseError::Validation` if the expense has no participants.
+pub fn rescale(shared: &mut Shared, amount: f64) -> Result<(), ExpenseError> {
+    let participants: Vec<(String, f64)> = shared
+        .shares
+        .iter()
+        .map(|share| (share.person.clone(), share.amount))
+        .collect();
+    // Shares that were all zero give no proportion to keep.
+    let method = if participants.iter().any(|(_, amount)| *amount > 0.0) {
+        ShareMethod::Shares
+    } else {
+        ShareMethod::Equal
+    };
+    shared.shares = divide(amount, method, &participants)?;
+
+    Ok(())
+}
+
+/// Works out what everyone is owed across the shared expenses and settlements.
+///
+/// Names are compared ignoring case and reported as first written.
+///
+/// # Arguments
+///
+/// * `expenses` - The ledger.
+///
+/// # Returns
+///
+/// Each person with a balance: positive if they are owed money, negative if
+/// they owe it.
+pub fn net_balances(expenses: &[Expense]) -> Vec<(String, f64)> {
+    let mut balances: Vec<(String, f64)> = Vec::new();
+    let mut add = |person: &str, amount: f64| match balances
+        .iter_mut()
+        .find(|(name, _)| name.eq_ignore_ascii_case(person))
+    {
+        Some((_, balance)) => *balance += amount,
+        None => balances.push((person.to_string(), amount)),
+    };
+
+    for shared in expenses
+        .iter()
+        .filter_map(|expense| expense.shared.as_ref())
+    {
+        let owed = sum_amounts(shared.shares.iter().map(|share| share.amount));
+        add(&shared.paid_by, owed);
+        for share in &shared.shares {
+            add(&share.person, -share.amount);
+        }
+    }
+
+    balances
+}
+
+/// The most people with a balance for whom `settle` searches for the fewest
+/// payments; it tries every subset of them, so the work doubles with each one.
+pub const SETTLE_EXACT_LIMIT: usize = 20;
+
+/// Plans the payments that bring every balance to zero.
+///
+/// People are first split into as many groups as possible whose balances add
+/// up to zero; within each group the largest debtor repeatedly pays the largest
+/// creditor. A group of `n` people then needs `n - 1` payments, which makes the
+/// plan as short as possible. Beyond `SETTLE_EXACT_LIMIT` people everyone is
+/// treated as one group, which still needs at most one payment fewer than there
+/// are people with a balance.
+///
+/// # Arguments
+///
+/// * `balances` - The balances from `net_balances`.
+///
+/// # Returns
+///
+/// The payments, group by group.
+pub fn settle(balances: &[(String, f64)]) -> Vec<Payment> {
+    let people: Vec<(String, i64)> = balances
+        .iter()
+        .map(|(person, balance)| (person.clone(), (balance * 100.0).round() as i64))
+        .filter(|(_, cents)| *cents != 0)
+        .collect();
+
+    zero_sum_groups(&people)
+        .iter()
+        .flat_map(|group| pay_off(group))
+        .collect()
+}
+
+/// Splits balances in cents into the largest number of groups that each add up
+/// to zero.
+///
+/// For every subset, the best split is found by leaving out one person; when
+/// the subset adds up to zero, it closes one more group. Walking back from
+/// everyone gives an order in which the groups follow each other.
+fn zero_sum_groups(people: &[(String, i64)]) -> Vec<Vec<(String, i64)>> {
+    if people.len() > SETTLE_EXACT_LIMIT {
+        return vec![people.to_vec()];
+    }
+
+    let everyone = (1usize << people.len()) - 1;
+    let mut sums = vec![0i64; everyone + 1];
+    let mut groups = vec![0u8; everyone + 1];
+    for subset in 1..=everyone {
+        let lowest = subset.trailing_zeros() as usize;
+        sums[subset] = sums[subset & (subset - 1)] + people[lowest].1;
+        let best = (0..people.len())
+            .filter(|person| subset & (1 << person) != 0)
+            .map(|person| groups[subset ^ (1 << person)])
+            .max()
+            .unwrap_or(0);
+        groups[subset] = best + u8::from(sums[subset] == 0);
+    }
+
+    let mut order = Vec::new();
+    let mut subset = everyone;
+    while subset != 0 {
+        let closes = u8::from(sums[subset] == 0);
+        let Some(person) = (0..people.len()).find(|person| {
+            subset & (1 << person) != 0 && groups[subset ^ (1 << person)] + closes == groups[subset]
+        }) else {
+            break;
+        };
+        order.push(person);
+        subset ^= 1 << person;
+    }
+    order.reverse();
+
+    let mut split = Vec::new();
+    let mut group = Vec::new();
+    let mut total = 0;
+    for person in order {
+        group.push(people[person].clone());
+        total += people[person].1;
+        if total == 0 {
+            split.push(std::mem::take(&mut group));
+        }
+    }
+    if !group.is_empty() {
+        split.push(group);
+    }
+
+    split
+}
+
+/// Settles one group: the largest debtor repeatedly pays the largest creditor,
+/// which needs at most one payment fewer than there are people in it.
+fn pay_off(group: &[(String, i64)]) -> Vec<Payment> {
+    let mut creditors: Vec<(String, i64)> = group
+        .iter()
+        .filter(|(_, cents)| *cents > 0)
+        .cloned()
+        .collect();
+    let mut debtors: Vec<(String, i64)> = group
+        .iter()
+        .filter(|(_, cents)| *cents < 0)
+        .map(|(person, cents)| (person.clone(), -cents))
+        .collect();
+
+    let mut payments = Vec::new();
+    loop {
+        creditors.sort_by_key(|(_, cents)| Reverse(*cents));
+        debtors.sort_by_key(|(_, cents)| Reverse(*cents));
+        let (Some(creditor), Some(debtor)) = (creditors.first_mut(), debtors.first_mut()) else {
+            break;
+        };
+        if creditor.1 == 0 || debtor.1 == 0 {
+            break;
+        }
+
+        let amount = creditor.1.min(debtor.1);
+        creditor.1 -= amount;
+        debtor.1 -= amount;
+        payments.push(Payment {
+            from: debtor.0.clone(),
+            to: creditor.0.clone(),
+            amount: amount as f64 / 100.0,
+        });
+    }
+
+    payments
+}
@@ -0,0 +1,260 @@
+use crate::categories::{load_categories, BUILT_IN};
+use crate::error::ExpenseError;
+use crate::paths::data_file;
+use crate::store::{load_ledger, Backend};
+use rustyline::completion::{Completer, Pair};
+use rustyline::config::Behavior;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use std::io::{self, stderr, stdin, IsTerminal, Write};
+use std::path::PathBuf;
+
+/// Splits a shell line into arguments the way a POSIX shell would.
+///
+/// # Arguments
+///
+/// * `line` - The line typed at the prompt.
+///
+/// # Returns
+///
+/// The arguments, or `ExpenseError::Validation` if a quote is not closed.
+pub fn split_line(line: &str) -> Result<Vec<String>, ExpenseError> {
+    shlex::split(line).ok_or_else(|| ExpenseError::Validation("Unclosed quote".to_string()))
+}
+
+/// Where the shell keeps the lines typed in earlier sessions.
+pub fn history_file() -> PathBuf {
+    data_file("shell_history.txt")
+}
+
+/// Works out the completions for the word under the cursor.
+///
+/// Subcommands and their flags come from the clap definition of the command
+/// line; the value of `-c`/`--category` completes to a category and the value
+/// of `-i`/`--id` to an expense ID.
+///
+/// # Arguments
+///
+/// * `command` - The clap definition of the whole command line.
+/// * `line` - The line up to the cursor.
+/// * `categories` - The categories to offer.
+/// * `ids` - The expense IDs to offer.
+///
+/// # Returns
+///
+/// Where the word being completed starts, and the sorted candidates for it.
+pub fn candidates(
+    command: &clap::Command,
+    line: &str,
+    categories: &[String],
+    ids: &[String],
+) -> (usize, Vec<String>) {
+    let start = line
+        .rfind(char::is_whitespace)
+        .map_or(0, |position| position + 1);
+    let prefix = &line[start..];
+    let words: Vec<&str> = line[..start].split_whitespace().collect();
+
+    let mut current = command;
+    for word in &words {
+        if let Some(subcommand) = current.find_subcommand(word) {
+            current = subcommand;
+        }
+    }
+
+    let mut found: Vec<String> = match words.last().copied() {
+        Some("-c" | "--category") => categories.to_vec(),
+        Some("-i" | "--id") => ids.to_vec(),
+        _ if prefix.starts_with('-') => current
+            .get_arguments()
+            .chain(command.get_arguments().filter(|arg| arg.is_global_set()))
+            .filter_map(|arg| arg.get_long())
+            .map(|long| format!("--{}", long))
+            .collect(),
+        _ => current
+            .get_subcommands()
+            .map(|subcommand| subcommand.get_name().to_string())
+            .filter(|name| name != "help")
+            .collect(),
+    };
+    found.retain(|candidate| candidate.to_lowercase().starts_with(&prefix.to_lowercase()));
+    found.sort();
+    found.dedup();
+
+    (start, found)
+}
+
+/// Asks a question, such as the ones of a guided `add`.
+///
+/// On a terminal the answer starts out as `default` and can be edited, with
+/// Tab completing to one of `choices`. Otherwise the question is written to
+/// stderr, so that it never mixes with machine-readable output, and an empty
+/// line stands for `default`.
+///
+/// # Arguments
+///
+/// * `question` - The question, without a trailing colon.
+/// * `default` - The suggested answer.
+/// * `choices` - The answers to complete to.
+///
+/// # Returns
+///
+/// The trimmed answer, or `ExpenseError::Validation` if the user gave up with
+/// Ctrl-C or at the end of input.
+pub fn ask(question: &str, default: &str, choices: &[String]) -> Result<String, ExpenseError> {
+    let cancelled = || ExpenseError::Validation("Cancelled".to_string());
+
+    if !stdin().is_terminal() {
+        match default {
+            "" => eprint!("{}: ", question),
+            default => eprint!("{} [{}]: ", question, default),
+        }
+        stderr().flush()?;
+        let mut answer = String::new();
+        if stdin().read_line(&mut answer)? == 0 {
+            return Err(cancelled());
+        }
+        return Ok(match answer.trim() {
+            "" => default.to_string(),
+            answer => answer.to_string(),
+        });
+    }
+
+    // Prompting on the terminal itself keeps stdout free for the result.
+    let config = rustyline::Config::builder()
+        .behavior(Behavior::PreferTerm)
+        .build();
+    let readline_error = |error: ReadlineError| ExpenseError::Storage(io::Error::other(error));
+    let mut editor: Editor<ChoiceHelper, DefaultHistory> =
+        Editor::with_config(config).map_err(readline_error)?;
+    editor.set_helper(Some(ChoiceHelper {
+        choices: choices.to_vec(),
+    }));
+    match editor.readline_with_initial(&format!("{}: ", question), (default, "")) {
+        Ok(answer) => Ok(answer.trim().to_string()),
+        Err(ReadlineError::Interrupted | ReadlineError::Eof) => Err(cancelled()),
+        Err(error) => Err(readline_error(error)),
+    }
+}
+
+/// Completes the answer to a question from a list of choices.
+struct ChoiceHelper {
+    choices: Vec<String>,
+}
+
+impl Completer for ChoiceHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let typed = line[..pos].trim_start().to_lowercase();
+        let found = self
+            .choices
+            .iter()
+            .filter(|choice| choice.to_lowercase().starts_with(&typed))
+            .cloned()
+            .collect();
+        Ok((line.len() - line.trim_start().len(), found))
+    }
+}
+
+impl Hinter for ChoiceHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ChoiceHelper {}
+
+impl Validator for ChoiceHelper {}
+
+impl Helper for ChoiceHelper {}
+
+/// Completion for the `shell` prompt.
+///
+/// The categories and expense IDs are gathered once per command rather than
+/// on every Tab press, so completing stays fast however large the ledger is.
+pub struct ShellHelper {
+    command: clap::Command,
+    backend: Backend,
+    categories: Vec<String>,
+    ids: Vec<String>,
+}
+
+impl ShellHelper {
+    /// Creates the helper with the current categories and expense IDs.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The clap definition of the command line.
+    /// * `backend` - The backend the expense IDs are read from.
+    pub fn new(command: clap::Command, backend: Backend) -> Self {
+        let mut helper = Self {
+            command,
+            backend,
+            categories: Vec::new(),
+            ids: Vec::new(),
+        };
+        helper.refresh();
+        helper
+    }
+
+    /// Gathers the categories and expense IDs again, after a command may have
+    /// changed them.
+    pub fn refresh(&mut self) {
+        // Completion is best effort: a missing or corrupt file only means fewer candidates.
+        self.categories = BUILT_IN
+            .iter()
+            .map(|name| name.to_string())
+            .chain(load_categories().unwrap_or_default())
+            .collect();
+        self.ids = load_ledger(self.backend)
+            .map(|ledger| {
+                ledger
+                    .expenses
+                    .into_iter()
+                    .map(|expense| expense.id)
+                    .collect()
+            })
+            .unwrap_or_default();
+    }
+}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let (start, found) = candidates(&self.command, &line[..pos], &self.categories, &self.ids);
+        Ok((
+            start,
+            found
+                .into_iter()
+                .map(|candidate| Pair {
+                    display: candidate.clone(),
+                    replacement: format!("{} ", candidate),
+                })
+                .collect(),
+        ))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
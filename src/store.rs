@@ -0,0 +1,251 @@
+use crate::error::ExpenseError;
+use crate::events::{self, Event, Snapshot};
+use crate::paths::{data_file, ledger_file};
+use crate::{get_budget, load_expenses, save_expenses, set_budget, Expense};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+use std::time::SystemTime;
+
+/// Where the ledger is persisted.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// `expenses.json` and `budget.json`, rewritten on every change.
+    #[default]
+    Json,
+    /// The append-only `events.jsonl` log with periodic `snapshot.json` snapshots.
+    Events,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(backend: &str) -> Result<Self, Self::Err> {
+        match backend.to_lowercase().as_str() {
+            "json" => Ok(Backend::Json),
+            "events" => Ok(Backend::Events),
+            _ => Err(format!(
+                "unknown backend '{}' (expected json or events)",
+                backend
+            )),
+        }
+    }
+}
+
+/// The expenses and budget loaded from a backend.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Ledger {
+    pub expenses: Vec<Expense>,
+    pub budget: f64,
+}
+
+/// The files a ledger was read from, with the state of each one that exists.
+#[derive(Debug, Clone, PartialEq)]
+struct Stamp {
+    backend: Backend,
+    files: Vec<(PathBuf, Option<FileStamp>)>,
+}
+
+/// A file's modification time, size and a hash of its contents.
+///
+/// The hash catches a rewrite that keeps the size within the resolution of the
+/// modification time, which the metadata alone would miss.
+#[derive(Debug, Clone, PartialEq)]
+struct FileStamp {
+    modified: SystemTime,
+    len: u64,
+    hash: u64,
+}
+
+/// The ledger kept in memory, once `keep_in_memory` has been called.
+static CACHE: RwLock<Option<(Stamp, Ledger)>> = RwLock::new(None);
+static KEEP_IN_MEMORY: AtomicBool = AtomicBool::new(false);
+
+/// Keeps the ledger in memory between commands, as the `shell` does.
+///
+/// The files are still checked before every use, so changes made by another
+/// process are picked up.
+pub fn keep_in_memory() {
+    KEEP_IN_MEMORY.store(true, Ordering::Relaxed);
+}
+
+impl Stamp {
+    fn read(backend: Backend) -> Self {
+        let mut files = vec![ledger_file(), data_file("budget.json")];
+        if backend == Backend::Events {
+            files.push(data_file("events.jsonl"));
+            files.push(data_file("snapshot.json"));
+        }
+
+        Self {
+            backend,
+            files: files
+                .into_iter()
+                .map(|file| {
+                    let stamp = fs::metadata(&file).ok().and_then(|metadata| {
+                        let mut hasher = DefaultHasher::new();
+                        fs::read(&file).ok()?.hash(&mut hasher);
+                        Some(FileStamp {
+                            modified: metadata.modified().ok()?,
+                            len: metadata.len(),
+                            hash: hasher.finish(),
+                        })
+                    });
+                    (file, stamp)
+                })
+                .collect(),
+        }
+    }
+
+    /// Whether both stamps are of the same ledger, whatever its state.
+    fn same_files(&self, other: &Stamp) -> bool {
+        self.backend == other.backend
+            && self
+                .files
+                .iter()
+                .map(|(file, _)| file)
+                .eq(other.files.iter().map(|(file, _)| file))
+    }
+}
+
+/// Updates the ledger kept in memory after a write, or forgets it if it no
+/// longer matches the files.
+///
+/// # Arguments
+///
+/// * `current` - The stamp of the files just written, taken under the lock.
+/// * `update` - Applies the write to the ledger in memory.
+fn refresh_cache(current: Stamp, update: impl FnOnce(&mut Ledger)) {
+    if let Ok(mut cache) = CACHE.write() {
+        *cache = match cache.take() {
+            Some((stamp, mut ledger)) if stamp.same_files(&current) => {
+                update(&mut ledger);
+                Some((current, ledger))
+            }
+            _ => None,
+        };
+    }
+}
+
+/// The stamp of the files just written, if the ledger is kept in memory.
+///
+/// It is taken before the write returns, while the caller still holds the
+/// ledger lock, so no other process can change the files in between and have
+/// its change mistaken for this one.
+fn stamp_written(backend: Backend) -> Option<Stamp> {
+    KEEP_IN_MEMORY
+        .load(Ordering::Relaxed)
+        .then(|| Stamp::read(backend))
+}
+
+/// Loads the ledger from the backend.
+///
+/// The events backend starts from the JSON files the first time it is used, so
+/// switching backends keeps existing expenses.
+///
+/// # Arguments
+///
+/// * `backend` - The backend to read from.
+///
+/// # Returns
+///
+/// The current expenses and budget.
+pub fn load_ledger(backend: Backend) -> Result<Ledger, ExpenseError> {
+    if !KEEP_IN_MEMORY.load(Ordering::Relaxed) {
+        return read_ledger(backend);
+    }
+
+    let stamp = Stamp::read(backend);
+    if let Ok(cache) = CACHE.read() {
+        if let Some((_, ledger)) = cache.as_ref().filter(|(cached, _)| *cached == stamp) {
+            return Ok(ledger.clone());
+        }
+    }
+    let ledger = read_ledger(backend)?;
+    if let Ok(mut cache) = CACHE.write() {
+        *cache = Some((stamp, ledger.clone()));
+    }
+
+    Ok(ledger)
+}
+
+fn read_ledger(backend: Backend) -> Result<Ledger, ExpenseError> {
+    match backend {
+        Backend::Json => Ok(Ledger {
+            expenses: load_expenses()?,
+            budget: get_budget()?,
+        }),
+        Backend::Events => {
+            let state = events::load_state(json_snapshot)?;
+            Ok(Ledger {
+                expenses: state.expenses,
+                budget: state.budget,
+            })
+        }
+    }
+}
+
+/// Persists a change to the expenses.
+///
+/// The caller holds the ledger lock.
+///
+/// # Arguments
+///
+/// * `backend` - The backend to write to.
+/// * `before` - The expenses as they were loaded.
+/// * `after` - The expenses to save.
+pub fn save_ledger(
+    backend: Backend,
+    before: &[Expense],
+    after: &[Expense],
+) -> Result<(), ExpenseError> {
+    match backend {
+        Backend::Json => save_expenses(after)?,
+        Backend::Events => {
+            let mut state = events::load_state(json_snapshot)?;
+            events::append_events(&mut state, events::events_for_changes(before, after))?
+        }
+    }
+
+    if let Some(stamp) = stamp_written(backend) {
+        refresh_cache(stamp, |ledger| ledger.expenses = after.to_vec());
+    }
+    Ok(())
+}
+
+/// Persists a new monthly budget.
+///
+/// The caller holds the ledger lock.
+///
+/// # Arguments
+///
+/// * `backend` - The backend to write to.
+/// * `budget` - The budget to set.
+pub fn save_budget(backend: Backend, budget: f64) -> Result<(), ExpenseError> {
+    match backend {
+        Backend::Json => set_budget(budget)?,
+        Backend::Events => {
+            let mut state = events::load_state(json_snapshot)?;
+            events::append_events(&mut state, vec![Event::BudgetSet { budget }])?
+        }
+    }
+
+    if let Some(stamp) = stamp_written(backend) {
+        refresh_cache(stamp, |ledger| ledger.budget = budget);
+    }
+    Ok(())
+}
+
+/// The JSON files as a snapshot, used to seed the event log.
+fn json_snapshot() -> Result<Snapshot, ExpenseError> {
+    Ok(Snapshot {
+        seq: 0,
+        expenses: load_expenses()?,
+        budget: get_budget()?,
+    })
+}
@@ -28,9 +28,10 @@ struct Record {
     id: Thing,
 }
 
-async fn main_surreal() -> surrealdb::Result<()> {
+/// Runs the SurrealDB example against `address`, e.g. `127.0.0.1:8000`.
+pub async fn main_surreal(address: &str) -> surrealdb::Result<()> {
     // Connect to the server
-    let db = Surreal::new::<Ws>("127.0.0.1:8000").await?;
+    let db = Surreal::new::<Ws>(address).await?;
 
     // Signin as a namespace, database, or root user
     db.signin(Root {
@@ -0,0 +1,828 @@
+use crate::categories::{load_categories, resolve_category};
+use crate::config::{self, Config};
+use crate::error::ExpenseError;
+use crate::files::lock_ledger;
+use crate::journal::{Journal, OperationKind};
+use crate::query::Query;
+use crate::shared::rescale;
+use crate::store::{load_ledger, save_ledger, Backend, Ledger};
+use crate::validation::{
+    validate_amount, validate_date, validate_description, validate_expense, Limits,
+};
+use crate::{
+    convert_from_system_time, expand_splits, filter_expenses, generate_random_id, group_expenses,
+    search_expense_by_id, sort_expenses, sum_amounts, Category, Column, Expense, GroupBy, SortKey,
+};
+use chrono::Utc;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Cell, Clear, Gauge, Paragraph, Row, Table, TableState};
+use ratatui::{Frame, Terminal};
+use std::io::{self, IsTerminal};
+use std::time::SystemTime;
+
+/// The columns of the expense table.
+const COLUMNS: [Column; 6] = [
+    Column::Id,
+    Column::Date,
+    Column::Description,
+    Column::Amount,
+    Column::Category,
+    Column::Tags,
+];
+
+/// The sort keys `s` cycles through, with the name shown in the table title.
+const SORT_KEYS: [(SortKey, &str); 5] = [
+    (SortKey::Date, "date"),
+    (SortKey::Amount, "amount"),
+    (SortKey::Category, "category"),
+    (SortKey::Description, "description"),
+    (SortKey::Payee, "payee"),
+];
+
+/// The fields of the add and edit forms.
+const FORM_FIELDS: [&str; 5] = ["Description", "Amount", "Category", "Date", "Tags"];
+
+const HELP: &str = "a add  e edit  d delete  / filter  s sort  S reverse  r reload  q quit";
+
+/// A change made in the TUI.
+///
+/// Changes are applied to the ledger as it is on disk when they are saved, so
+/// expenses added by other commands in the meantime are kept, and an update
+/// only sets the fields of the form: a note or payee another command gave the
+/// expense since it was loaded survives, as it does with `update`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Edit {
+    Add(Box<Expense>),
+    Update { id: String, changes: Box<Changes> },
+    Delete(String),
+}
+
+/// The fields the add and edit forms set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Changes {
+    pub description: String,
+    pub amount: f64,
+    pub category: Category,
+    /// The date as `YYYY-MM-DD`, or `None` to keep it.
+    pub date: Option<String>,
+    pub tags: Vec<String>,
+}
+
+/// What the event loop should do after a key press.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Quit,
+    Reload,
+    Save(Edit),
+}
+
+/// The state of the terminal UI.
+pub struct App {
+    expenses: Vec<Expense>,
+    budget: f64,
+    categories: Vec<String>,
+    limits: Limits,
+    /// The expenses shown in the table, filtered and sorted.
+    rows: Vec<Expense>,
+    table: TableState,
+    sort: usize,
+    descending: bool,
+    filter: Option<Query>,
+    filter_text: String,
+    mode: Mode,
+    status: String,
+}
+
+enum Mode {
+    Browse,
+    Filter(String),
+    Form(Form),
+    ConfirmDelete(String),
+}
+
+struct Form {
+    /// The expense being edited, or `None` when adding one.
+    id: Option<String>,
+    values: [String; 5],
+    focus: usize,
+    error: Option<String>,
+}
+
+impl Edit {
+    fn kind(&self) -> OperationKind {
+        match self {
+            Edit::Add(_) => OperationKind::Add,
+            Edit::Update { .. } => OperationKind::Update,
+            Edit::Delete(_) => OperationKind::Delete,
+        }
+    }
+
+    /// Applies the change to a ledger.
+    ///
+    /// # Arguments
+    ///
+    /// * `expenses` - The expenses to change in place.
+    /// * `limits` - The limits the added or updated expense is checked against.
+    ///
+    /// # Returns
+    ///
+    /// `ExpenseError::NotFound` if the expense to update or delete is gone, or a
+    /// validation error if the update does not fit the expense as it now is.
+    pub fn apply(&self, expenses: &mut Vec<Expense>, limits: &Limits) -> Result<(), ExpenseError> {
+        let find = |expenses: &[Expense], id: &str| {
+            search_expense_by_id(expenses, id)
+                .ok_or_else(|| ExpenseError::NotFound(format!("Expense {} no longer exists", id)))
+        };
+        match self {
+            Edit::Add(expense) => {
+                let mut expense = Expense::clone(expense);
+                validate_expense(&expense, limits)?;
+                while search_expense_by_id(expenses, &expense.id).is_some() {
+                    expense.id = generate_random_id().to_string();
+                }
+                expenses.push(expense);
+            }
+            Edit::Update { id, changes } => {
+                let index = find(expenses, id)?;
+                let mut expense = expenses[index].clone();
+                changes.apply_to(&mut expense, limits)?;
+                expense.updated_at = convert_from_system_time(SystemTime::now());
+                validate_expense(&expense, limits)?;
+                expenses[index] = expense;
+            }
+            Edit::Delete(id) => {
+                let index = find(expenses, id)?;
+                expenses.remove(index);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Changes {
+    /// Sets the fields of the form on an expense, leaving the others alone.
+    ///
+    /// # Arguments
+    ///
+    /// * `expense` - The expense to change in place.
+    /// * `limits` - The limits a new date is checked against.
+    fn apply_to(&self, expense: &mut Expense, limits: &Limits) -> Result<(), ExpenseError> {
+        if let Some(shared) = expense
+            .shared
+            .as_mut()
+            .filter(|_| expense.amount != self.amount)
+        {
+            // Everyone keeps their part of the new amount, as with `update --amount`.
+            rescale(shared, self.amount)?;
+        }
+        expense.description = self.description.clone();
+        expense.amount = self.amount;
+        expense.category = self.category.clone();
+        // An unchanged date keeps the time of day the expense was added at.
+        if let Some(date) = self
+            .date
+            .as_deref()
+            .filter(|date| expense.added_at.get(..10) != Some(*date))
+        {
+            let date = validate_date(date, limits)?;
+            expense.added_at = date.format("%Y-%m-%d 00:00:00").to_string();
+        }
+        expense.tags = self.tags.clone();
+
+        Ok(())
+    }
+}
+
+impl App {
+    /// Creates the UI state for a ledger, newest expenses first.
+    ///
+    /// # Arguments
+    ///
+    /// * `ledger` - The expenses and budget to show.
+    /// * `categories` - The custom categories of the profile.
+    /// * `limits` - The limits the forms are checked against.
+    pub fn new(ledger: Ledger, categories: Vec<String>, limits: Limits) -> Self {
+        let mut app = Self {
+            expenses: Vec::new(),
+            budget: 0.0,
+            categories,
+            limits,
+            rows: Vec::new(),
+            table: TableState::default(),
+            sort: 0,
+            descending: true,
+            filter: None,
+            filter_text: String::new(),
+            mode: Mode::Browse,
+            status: String::new(),
+        };
+        app.reload(ledger);
+        app
+    }
+
+    /// Replaces the expenses and budget, keeping the filter, order and selection.
+    pub fn reload(&mut self, ledger: Ledger) {
+        self.expenses = ledger.expenses;
+        self.budget = ledger.budget;
+        self.refresh();
+    }
+
+    /// The expenses shown in the table, in display order.
+    pub fn rows(&self) -> &[Expense] {
+        &self.rows
+    }
+
+    /// The highlighted expense, if any.
+    pub fn selected(&self) -> Option<&Expense> {
+        self.table.selected().and_then(|index| self.rows.get(index))
+    }
+
+    /// The message shown in the status line.
+    pub fn status(&self) -> &str {
+        &self.status
+    }
+
+    /// Shows a message in the status line until the next key press.
+    pub fn set_status(&mut self, status: impl Into<String>) {
+        self.status = status.into();
+    }
+
+    /// Highlights the expense with the given ID, if it is shown.
+    pub fn select_id(&mut self, id: &str) {
+        if let Some(index) = self.rows.iter().position(|expense| expense.id == id) {
+            self.table.select(Some(index));
+        }
+    }
+
+    fn refresh(&mut self) {
+        self.rows = filter_expenses(&self.expenses, self.filter.as_ref());
+        sort_expenses(&mut self.rows, SORT_KEYS[self.sort].0, self.descending);
+        let selected = match self.table.selected() {
+            _ if self.rows.is_empty() => None,
+            Some(index) => Some(index.min(self.rows.len() - 1)),
+            None => Some(0),
+        };
+        self.table.select(selected);
+    }
+
+    fn move_by(&mut self, offset: isize) {
+        if let Some(index) = self.table.selected() {
+            let last = self.rows.len().saturating_sub(1) as isize;
+            self.table
+                .select(Some((index as isize + offset).clamp(0, last) as usize));
+        }
+    }
+
+    /// Handles a key press.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key that was pressed.
+    ///
+    /// # Returns
+    ///
+    /// What the event loop should do, if anything beyond redrawing.
+    pub fn handle_key(&mut self, key: KeyEvent) -> Option<Action> {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Some(Action::Quit);
+        }
+        self.status.clear();
+
+        match std::mem::replace(&mut self.mode, Mode::Browse) {
+            Mode::Browse => return self.browse_key(key),
+            Mode::Filter(mut text) => match key.code {
+                KeyCode::Esc => {}
+                KeyCode::Enter => match text.trim() {
+                    "" => {
+                        self.filter = None;
+                        self.filter_text.clear();
+                        self.refresh();
+                    }
+                    source => match Query::parse(source) {
+                        Ok(query) => {
+                            self.filter = Some(query);
+                            self.filter_text = source.to_string();
+                            self.refresh();
+                        }
+                        Err(error) => {
+                            self.status = error.to_string();
+                            self.mode = Mode::Filter(text);
+                        }
+                    },
+                },
+                KeyCode::Backspace => {
+                    text.pop();
+                    self.mode = Mode::Filter(text);
+                }
+                KeyCode::Char(character) => {
+                    text.push(character);
+                    self.mode = Mode::Filter(text);
+                }
+                _ => self.mode = Mode::Filter(text),
+            },
+            Mode::Form(mut form) => {
+                match key.code {
+                    KeyCode::Esc => return None,
+                    KeyCode::Enter => match self.form_edit(&form) {
+                        Ok(edit) => return Some(Action::Save(edit)),
+                        Err(error) => form.error = Some(error.to_string()),
+                    },
+                    KeyCode::Tab | KeyCode::Down => {
+                        form.focus = (form.focus + 1) % FORM_FIELDS.len()
+                    }
+                    KeyCode::BackTab | KeyCode::Up => {
+                        form.focus = (form.focus + FORM_FIELDS.len() - 1) % FORM_FIELDS.len()
+                    }
+                    KeyCode::Backspace => {
+                        form.values[form.focus].pop();
+                    }
+                    KeyCode::Char(character) => form.values[form.focus].push(character),
+                    _ => {}
+                }
+                self.mode = Mode::Form(form);
+            }
+            Mode::ConfirmDelete(id) => match key.code {
+                KeyCode::Char('y') | KeyCode::Char('Y') => {
+                    return Some(Action::Save(Edit::Delete(id)))
+                }
+                _ => self.status = "Nothing deleted.".to_string(),
+            },
+        }
+
+        None
+    }
+
+    fn browse_key(&mut self, key: KeyEvent) -> Option<Action> {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Some(Action::Quit),
+            KeyCode::Char('r') => return Some(Action::Reload),
+            KeyCode::Down | KeyCode::Char('j') => self.move_by(1),
+            KeyCode::Up | KeyCode::Char('k') => self.move_by(-1),
+            KeyCode::PageDown => self.move_by(10),
+            KeyCode::PageUp => self.move_by(-10),
+            KeyCode::Home => self.move_by(isize::MIN / 2),
+            KeyCode::End => self.move_by(isize::MAX / 2),
+            KeyCode::Char('s') => {
+                self.sort = (self.sort + 1) % SORT_KEYS.len();
+                self.refresh();
+            }
+            KeyCode::Char('S') => {
+                self.descending = !self.descending;
+                self.refresh();
+            }
+            KeyCode::Char('/') => self.mode = Mode::Filter(self.filter_text.clone()),
+            KeyCode::Char('a') => {
+                let today = Utc::now().date_naive().format("%Y-%m-%d").to_string();
+                self.mode = Mode::Form(Form {
+                    id: None,
+                    values: [
+                        String::new(),
+                        String::new(),
+                        config::current().default_category,
+                        today,
+                        String::new(),
+                    ],
+                    focus: 0,
+                    error: None,
+                });
+            }
+            KeyCode::Char('e') | KeyCode::Enter => {
+                if let Some(expense) = self.selected() {
+                    self.mode = Mode::Form(Form {
+                        id: Some(expense.id.clone()),
+                        values: [
+                            expense.description.clone(),
+                            expense.amount.to_string(),
+                            expense.category.to_string(),
+                            expense.added_at.get(..10).unwrap_or_default().to_string(),
+                            expense.tags.join(", "),
+                        ],
+                        focus: 0,
+                        error: None,
+                    });
+                }
+            }
+            KeyCode::Char('d') | KeyCode::Delete => {
+                if let Some(expense) = self.selected() {
+                    self.mode = Mode::ConfirmDelete(expense.id.clone());
+                }
+            }
+            _ => {}
+        }
+
+        None
+    }
+
+    /// Builds the change described by a form, checked like the `add` and
+    /// `update` commands check theirs.
+    fn form_edit(&self, form: &Form) -> Result<Edit, ExpenseError> {
+        let [description, amount, category, date, tags] = &form.values;
+        let description = validate_description(description, &self.limits)?;
+        let amount = amount.trim().parse::<f64>().map_err(|_| {
+            ExpenseError::Validation(format!("'{}' is not an amount", amount.trim()))
+        })?;
+        let amount = validate_amount(amount, &self.limits)?;
+        let category = match category.trim() {
+            "" => config::current().default_category,
+            category => category.to_lowercase(),
+        };
+        let changes = Changes {
+            description,
+            amount,
+            category: resolve_category(&category, &self.categories),
+            date: Some(date.trim().to_string()).filter(|date| !date.is_empty()),
+            tags: tags
+                .split(',')
+                .map(str::trim)
+                .filter(|tag| !tag.is_empty())
+                .map(String::from)
+                .collect(),
+        };
+
+        // An update is tried on the expense as shown, so a mistake keeps the form open.
+        let mut expense = match &form.id {
+            Some(id) => self
+                .expenses
+                .iter()
+                .find(|expense| &expense.id == id)
+                .cloned()
+                .ok_or_else(|| {
+                    ExpenseError::NotFound(format!("Expense {} no longer exists", id))
+                })?,
+            None => Expense::new(
+                changes.description.clone(),
+                changes.amount,
+                changes.category.clone(),
+            ),
+        };
+        changes.apply_to(&mut expense, &self.limits)?;
+        validate_expense(&expense, &self.limits)?;
+
+        Ok(match &form.id {
+            Some(id) => Edit::Update {
+                id: id.clone(),
+                changes: Box::new(changes),
+            },
+            None => Edit::Add(Box::new(expense)),
+        })
+    }
+
+    /// Draws the whole screen.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The frame to draw into.
+    pub fn draw(&mut self, frame: &mut Frame) {
+        let config = config::current();
+        let screen = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Min(5),
+                Constraint::Length(1),
+            ])
+            .split(frame.size());
+        let body = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(70), Constraint::Percentage(30)])
+            .split(screen[1]);
+        let side = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(3), Constraint::Length(3)])
+            .split(body[1]);
+
+        self.draw_filter(frame, screen[0]);
+        self.draw_table(frame, body[0], &config);
+        self.draw_categories(frame, side[0], &config);
+        self.draw_budget(frame, side[1], &config);
+
+        let status = match &self.mode {
+            _ if !self.status.is_empty() => self.status.as_str(),
+            Mode::Browse => HELP,
+            Mode::Filter(_) => "Enter apply  Esc cancel  (e.g. category = food and amount > 20)",
+            Mode::Form(_) => "Tab next field  Shift-Tab previous  Enter save  Esc cancel",
+            Mode::ConfirmDelete(_) => "y delete  any other key cancels",
+        };
+        frame.render_widget(
+            Paragraph::new(status).style(Style::default().add_modifier(Modifier::DIM)),
+            screen[2],
+        );
+
+        match &self.mode {
+            Mode::Form(form) => self.draw_form(frame, form),
+            Mode::ConfirmDelete(id) => {
+                let question = match self.expenses.iter().find(|expense| &expense.id == id) {
+                    Some(expense) => format!(
+                        "Delete '{}' ({})? y/n",
+                        expense.description,
+                        config.format_amount(expense.amount)
+                    ),
+                    None => "Delete the expense? y/n".to_string(),
+                };
+                let area = centered(frame.size(), 50, 3);
+                frame.render_widget(Clear, area);
+                frame.render_widget(
+                    Paragraph::new(question).block(Block::default().borders(Borders::ALL)),
+                    area,
+                );
+            }
+            _ => {}
+        }
+    }
+
+    fn draw_filter(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::default().borders(Borders::ALL).title(" Filter ");
+        let paragraph = match &self.mode {
+            Mode::Filter(text) => {
+                frame.set_cursor(area.x + 1 + text.chars().count() as u16, area.y + 1);
+                Paragraph::new(text.as_str())
+            }
+            _ if self.filter_text.is_empty() => Paragraph::new("press / to filter")
+                .style(Style::default().add_modifier(Modifier::DIM)),
+            _ => Paragraph::new(self.filter_text.as_str()),
+        };
+        frame.render_widget(paragraph.block(block), area);
+    }
+
+    fn draw_table(&mut self, frame: &mut Frame, area: Rect, config: &Config) {
+        let header = Row::new(COLUMNS.iter().map(|column| Cell::from(column.header())))
+            .style(Style::default().add_modifier(Modifier::BOLD));
+        let rows = self.rows.iter().enumerate().map(|(index, expense)| {
+            Row::new(
+                COLUMNS
+                    .iter()
+                    .map(|column| Cell::from(column.display(index + 1, expense, config))),
+            )
+        });
+        let widths = [
+            Constraint::Length(6),
+            Constraint::Length(12),
+            Constraint::Min(12),
+            Constraint::Length(12),
+            Constraint::Length(14),
+            Constraint::Length(14),
+        ];
+        let title = format!(
+            " Expenses ({}), by {} {} ",
+            self.rows.len(),
+            SORT_KEYS[self.sort].1,
+            if self.descending { "desc" } else { "asc" }
+        );
+        let table = Table::new(rows, widths)
+            .header(header)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, area, &mut self.table);
+    }
+
+    fn draw_categories(&self, frame: &mut Frame, area: Rect, config: &Config) {
+        let groups = group_expenses(&expand_splits(&self.rows), GroupBy::Category);
+        let rows = groups.iter().map(|group| {
+            Row::new([
+                Cell::from(group.name.clone()),
+                Cell::from(group.count.to_string()),
+                Cell::from(config.format_amount(group.total)),
+            ])
+        });
+        let widths = [
+            Constraint::Min(10),
+            Constraint::Length(4),
+            Constraint::Length(12),
+        ];
+        frame.render_widget(
+            Table::new(rows, widths).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(" By category "),
+            ),
+            area,
+        );
+    }
+
+    fn draw_budget(&self, frame: &mut Frame, area: Rect, config: &Config) {
+        let month = Utc::now().format("%Y-%m").to_string();
+        let spent = sum_amounts(
+            self.expenses
+                .iter()
+                .filter(|expense| expense.is_spending() && expense.added_at.starts_with(&month))
+                .map(|expense| expense.amount),
+        );
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" Budget {} ", month));
+
+        let gauge = if self.budget > 0.0 {
+            let share = spent / self.budget;
+            let color = match share {
+                share if share > 1.0 => Color::Red,
+                share if share >= 0.8 => Color::Yellow,
+                _ => Color::Green,
+            };
+            Gauge::default()
+                .gauge_style(Style::default().fg(color))
+                .ratio(share.clamp(0.0, 1.0))
+                .label(format!(
+                    "{} of {} ({:.0}%)",
+                    config.format_amount(spent),
+                    config.format_amount(self.budget),
+                    share * 100.0
+                ))
+        } else {
+            Gauge::default().ratio(0.0).label(format!(
+                "{} spent, no budget set",
+                config.format_amount(spent)
+            ))
+        };
+        frame.render_widget(gauge.block(block), area);
+    }
+
+    fn draw_form(&self, frame: &mut Frame, form: &Form) {
+        let area = centered(frame.size(), 60, FORM_FIELDS.len() as u16 + 4);
+        let title = match form.id {
+            Some(_) => " Edit expense ",
+            None => " Add expense ",
+        };
+        let label_width = FORM_FIELDS
+            .iter()
+            .map(|label| label.len())
+            .max()
+            .unwrap_or(0)
+            + 2;
+
+        let mut lines: Vec<Line> = FORM_FIELDS
+            .iter()
+            .zip(&form.values)
+            .enumerate()
+            .map(|(index, (label, value))| {
+                let style = if index == form.focus {
+                    Style::default().add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default()
+                };
+                Line::from(vec![
+                    Span::styled(
+                        format!("{:<width$}", format!("{}:", label), width = label_width),
+                        style,
+                    ),
+                    Span::raw(value.clone()),
+                ])
+            })
+            .collect();
+        lines.push(match &form.error {
+            Some(error) => Line::from(Span::styled(error.clone(), Style::default().fg(Color::Red))),
+            // Completion hint for the category field.
+            None if FORM_FIELDS[form.focus] == "Category" => Line::from(Span::styled(
+                category_hint(&form.values[form.focus], &self.categories),
+                Style::default().add_modifier(Modifier::DIM),
+            )),
+            None => Line::default(),
+        });
+
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)),
+            area,
+        );
+        frame.set_cursor(
+            area.x + 1 + (label_width + form.values[form.focus].chars().count()) as u16,
+            area.y + 1 + form.focus as u16,
+        );
+    }
+}
+
+/// The known categories starting with what has been typed so far.
+fn category_hint(typed: &str, custom: &[String]) -> String {
+    let typed = typed.trim().to_lowercase();
+    let matching: Vec<String> = crate::categories::BUILT_IN
+        .iter()
+        .map(|name| name.to_string())
+        .chain(custom.iter().cloned())
+        .filter(|name| name.to_lowercase().starts_with(&typed))
+        .collect();
+    format!("Categories: {}", matching.join(", "))
+}
+
+/// A rectangle of the given width (in percent) and height, centered in `area`.
+fn centered(area: Rect, percent_x: u16, height: u16) -> Rect {
+    let width = area.width * percent_x / 100;
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + area.height.saturating_sub(height) / 2,
+        width,
+        height: height.min(area.height),
+    }
+}
+
+/// Puts the terminal in raw mode on the alternate screen, and back when dropped.
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter() -> Result<Self, ExpenseError> {
+        enable_raw_mode()?;
+        // From here on, dropping the guard restores the terminal, even on error.
+        let guard = TerminalGuard;
+        execute!(io::stdout(), EnterAlternateScreen)?;
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), LeaveAlternateScreen);
+        let _ = disable_raw_mode();
+    }
+}
+
+/// Reads the ledger under a shared lock, as read-only commands do.
+fn read(backend: Backend) -> Result<Ledger, ExpenseError> {
+    let _lock = lock_ledger(false)?;
+    load_ledger(backend)
+}
+
+/// Saves a change under the ledger lock and journals it so it can be undone.
+///
+/// # Returns
+///
+/// The ledger after the change.
+fn save(backend: Backend, edit: &Edit, limits: &Limits) -> Result<Ledger, ExpenseError> {
+    let _lock = lock_ledger(true)?;
+    let mut ledger = load_ledger(backend)?;
+    let before = ledger.expenses.clone();
+    edit.apply(&mut ledger.expenses, limits)?;
+    save_ledger(backend, &before, &ledger.expenses)?;
+    let mut journal = Journal::load()?;
+    if journal.record(edit.kind(), &before, &ledger.expenses) {
+        journal.save()?;
+    }
+
+    Ok(ledger)
+}
+
+/// Runs the full-screen terminal UI until the user quits.
+///
+/// # Arguments
+///
+/// * `backend` - The backend to read and write the ledger with.
+/// * `limits` - The limits new and changed expenses are checked against.
+///
+/// # Returns
+///
+/// `ExpenseError::Validation` if standard output is not a terminal.
+pub fn run_tui(backend: Backend, limits: Limits) -> Result<(), ExpenseError> {
+    if !io::stdout().is_terminal() {
+        return Err(ExpenseError::Validation(
+            "The TUI needs an interactive terminal".to_string(),
+        ));
+    }
+
+    let mut app = App::new(read(backend)?, load_categories()?, limits);
+    let _guard = TerminalGuard::enter()?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+
+    loop {
+        terminal.draw(|frame| app.draw(frame))?;
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        match app.handle_key(key) {
+            Some(Action::Quit) => break,
+            Some(Action::Reload) => {
+                app.reload(read(backend)?);
+                app.set_status("Reloaded.");
+            }
+            Some(Action::Save(edit)) => match save(backend, &edit, &app.limits) {
+                Ok(ledger) => {
+                    app.reload(ledger);
+                    match &edit {
+                        Edit::Add(expense) => {
+                            app.select_id(&expense.id);
+                            app.set_status(format!("Saved '{}'.", expense.description));
+                        }
+                        Edit::Update { id, changes } => {
+                            app.select_id(id);
+                            app.set_status(format!("Saved '{}'.", changes.description));
+                        }
+                        Edit::Delete(_) => app.set_status("Deleted."),
+                    }
+                }
+                Err(error) => app.set_status(error.to_string()),
+            },
+            None => {}
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,286 @@
+use crate::error::ExpenseError;
+use crate::Expense;
+use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Limits applied to every write path (CLI, import, API).
+///
+/// They are read from the `[limits]` table of `config.toml`; missing keys keep
+/// their defaults.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Limits {
+    /// Largest accepted amount.
+    pub max_amount: f64,
+    /// Longest accepted description, in characters.
+    pub max_description_len: usize,
+    /// Longest accepted note, in characters.
+    pub max_note_len: usize,
+    /// Earliest accepted expense date.
+    pub earliest_date: NaiveDate,
+    /// How many days into the future an expense may be dated.
+    pub max_days_ahead: i64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_amount: 1_000_000_000.0,
+            max_description_len: 200,
+            max_note_len: 2000,
+            earliest_date: NaiveDate::from_ymd_opt(1970, 1, 1).unwrap_or_default(),
+            max_days_ahead: 366,
+        }
+    }
+}
+
+/// Checks that an amount is a finite number greater than zero and within limits.
+///
+/// # Arguments
+///
+/// * `amount` - The amount to check.
+/// * `limits` - The limits to apply.
+///
+/// # Returns
+///
+/// The amount, or `ExpenseError::InvalidAmount` describing the problem.
+pub fn validate_amount(amount: f64, limits: &Limits) -> Result<f64, ExpenseError> {
+    if !amount.is_finite() {
+        return Err(ExpenseError::InvalidAmount(format!(
+            "Amount must be a finite number, got {}",
+            amount
+        )));
+    }
+    if amount <= 0.00 {
+        return Err(ExpenseError::InvalidAmount(
+            "Amount should be greater than 0.00".to_string(),
+        ));
+    }
+    if amount > limits.max_amount {
+        return Err(ExpenseError::InvalidAmount(format!(
+            "Amount should not exceed {}",
+            limits.max_amount
+        )));
+    }
+
+    Ok(amount)
+}
+
+/// Checks that a monthly budget is a finite number that is not negative and
+/// within limits. A budget of zero means no budget.
+///
+/// # Arguments
+///
+/// * `budget` - The budget to check.
+/// * `limits` - The limits to apply.
+///
+/// # Returns
+///
+/// The budget, or `ExpenseError::InvalidAmount` describing the problem.
+pub fn validate_budget(budget: f64, limits: &Limits) -> Result<f64, ExpenseError> {
+    if !budget.is_finite() {
+        return Err(ExpenseError::InvalidAmount(format!(
+            "Budget must be a finite number, got {}",
+            budget
+        )));
+    }
+    if budget < 0.00 {
+        return Err(ExpenseError::InvalidAmount(
+            "Budget cannot be negative".to_string(),
+        ));
+    }
+    if budget > limits.max_amount {
+        return Err(ExpenseError::InvalidAmount(format!(
+            "Budget should not exceed {}",
+            limits.max_amount
+        )));
+    }
+
+    // Adding zero stores a budget of -0 as 0.
+    Ok(budget + 0.0)
+}
+
+/// Checks that a description is a non-empty single line within limits.
+///
+/// # Arguments
+///
+/// * `description` - The description to check.
+/// * `limits` - The limits to apply.
+///
+/// # Returns
+///
+/// The trimmed description, or `ExpenseError::Validation` describing the problem.
+pub fn validate_description(description: &str, limits: &Limits) -> Result<String, ExpenseError> {
+    let description = description.trim();
+    if description.is_empty() {
+        return Err(ExpenseError::Validation(
+            "Description cannot be empty".to_string(),
+        ));
+    }
+    if description.chars().any(char::is_control) {
+        return Err(ExpenseError::Validation(
+            "Description must be a single line without control characters".to_string(),
+        ));
+    }
+    if description.chars().count() > limits.max_description_len {
+        return Err(ExpenseError::Validation(format!(
+            "Description should be at most {} characters",
+            limits.max_description_len
+        )));
+    }
+
+    Ok(description.to_string())
+}
+
+/// Checks that a note is within limits. Unlike a description it may span
+/// several lines.
+///
+/// # Arguments
+///
+/// * `note` - The note to check.
+/// * `limits` - The limits to apply.
+///
+/// # Returns
+///
+/// The note without surrounding whitespace, or `ExpenseError::Validation`
+/// describing the problem.
+pub fn validate_note(note: &str, limits: &Limits) -> Result<String, ExpenseError> {
+    let note = note.trim();
+    if note
+        .chars()
+        .any(|character| character.is_control() && !matches!(character, '\n' | '\r' | '\t'))
+    {
+        return Err(ExpenseError::Validation(
+            "Note cannot contain control characters".to_string(),
+        ));
+    }
+    if note.chars().count() > limits.max_note_len {
+        return Err(ExpenseError::Validation(format!(
+            "Note should be at most {} characters",
+            limits.max_note_len
+        )));
+    }
+
+    Ok(note.to_string())
+}
+
+/// Parses a custom field given as `KEY=VALUE`.
+///
+/// Keys are lowercased and may only contain letters, digits, `_` and `-`, so
+/// they can be used as `field.KEY` in a `--where` query.
+///
+/// # Arguments
+///
+/// * `field` - The field to parse.
+/// * `limits` - The limits to apply to the value.
+///
+/// # Returns
+///
+/// The key and the trimmed value, which is empty for `KEY=`, or
+/// `ExpenseError::Validation` describing the problem.
+pub fn validate_field(field: &str, limits: &Limits) -> Result<(String, String), ExpenseError> {
+    let (key, value) = field
+        .split_once('=')
+        .ok_or_else(|| ExpenseError::Validation(format!("Expected KEY=VALUE, got '{}'", field)))?;
+    let key = key.trim().to_lowercase();
+    if key.is_empty()
+        || !key
+            .chars()
+            .all(|character| character.is_alphanumeric() || matches!(character, '_' | '-'))
+    {
+        return Err(ExpenseError::Validation(format!(
+            "Field name '{}' may only contain letters, digits, '_' and '-'",
+            key
+        )));
+    }
+    let value = match value.trim() {
+        "" => String::new(),
+        value => validate_description(value, limits)?,
+    };
+
+    Ok((key, value))
+}
+
+/// Parses a `YYYY-MM-DD` date and checks it is within limits.
+///
+/// # Arguments
+///
+/// * `date` - The date to check.
+/// * `limits` - The limits to apply.
+///
+/// # Returns
+///
+/// The parsed date, `ExpenseError::InvalidDate` if it is malformed, or
+/// `ExpenseError::Validation` if it is out of range.
+pub fn validate_date(date: &str, limits: &Limits) -> Result<NaiveDate, ExpenseError> {
+    let parsed = NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+        .map_err(|_| ExpenseError::InvalidDate(date.to_string()))?;
+    check_date_range(parsed, limits)?;
+
+    Ok(parsed)
+}
+
+fn check_date_range(date: NaiveDate, limits: &Limits) -> Result<(), ExpenseError> {
+    let latest = Utc::now().date_naive() + Duration::days(limits.max_days_ahead);
+    if date < limits.earliest_date || date > latest {
+        return Err(ExpenseError::Validation(format!(
+            "Date {} should be between {} and {}",
+            date, limits.earliest_date, latest
+        )));
+    }
+
+    Ok(())
+}
+
+/// Validates a complete expense before it is written.
+///
+/// # Arguments
+///
+/// * `expense` - The expense to check.
+/// * `limits` - The limits to apply.
+///
+/// # Returns
+///
+/// `Ok` if the amount, description, splits, shares and dates are acceptable, or the first
+/// `ExpenseError` found.
+pub fn validate_expense(expense: &Expense, limits: &Limits) -> Result<(), ExpenseError> {
+    validate_amount(expense.amount, limits)?;
+    validate_description(&expense.description, limits)?;
+    if !expense.splits.is_empty() {
+        for split in &expense.splits {
+            validate_amount(split.amount, limits)?;
+        }
+        let split_total: f64 = expense.splits.iter().map(|split| split.amount).sum();
+        if (split_total - expense.amount).abs() >= 0.005 {
+            return Err(ExpenseError::Validation(format!(
+                "The splits add up to {} but the expense is {}",
+                split_total, expense.amount
+            )));
+        }
+    }
+    if let Some(shared) = &expense.shared {
+        let shared_total: f64 = shared.shares.iter().map(|share| share.amount).sum();
+        if (shared_total - expense.amount).abs() >= 0.005 {
+            return Err(ExpenseError::Validation(format!(
+                "The shares add up to {} but the expense is {}",
+                shared_total, expense.amount
+            )));
+        }
+    }
+    if let Some(note) = &expense.note {
+        validate_note(note, limits)?;
+    }
+    if let Some(location) = &expense.location {
+        validate_description(location, limits)?;
+    }
+    let added_at = parse_timestamp(&expense.added_at)?;
+    parse_timestamp(&expense.updated_at)?;
+    check_date_range(added_at.date(), limits)?;
+
+    Ok(())
+}
+
+fn parse_timestamp(timestamp: &str) -> Result<NaiveDateTime, ExpenseError> {
+    NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S")
+        .map_err(|_| ExpenseError::InvalidDate(timestamp.to_string()))
+}
@@ -1,4 +1,59 @@
 use assert_cmd::Command;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+
+/// A data directory of a single test, removed when the test ends, even if it fails.
+struct Scratch {
+    dir: PathBuf,
+}
+
+/// Creates an empty data directory for the test `name`.
+fn scratch(name: &str) -> Scratch {
+    let dir = std::env::temp_dir().join(format!("expense-tracker-{}-{}", name, std::process::id()));
+    // A directory left over by a killed run would leak its data into this one.
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    Scratch { dir }
+}
+
+impl Scratch {
+    /// The binary, pointed at this directory for its data and `config.toml`
+    /// so tests never touch real data.
+    fn command(&self) -> Command {
+        let mut cmd = Command::cargo_bin("expense-tracker").unwrap();
+        cmd.env("EXPENSE_TRACKER_HOME", &self.dir)
+            .env("EXPENSE_TRACKER_CONFIG", self.dir.join("config.toml"))
+            .env_remove("EXPENSE_TRACKER_BACKEND")
+            .env_remove("EXPENSE_TRACKER_PROFILE");
+        cmd
+    }
+
+    /// Runs the binary with `args` and waits for it to finish.
+    fn run(&self, args: &[&str]) -> Output {
+        self.command().args(args).output().unwrap()
+    }
+}
+
+impl Deref for Scratch {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl AsRef<Path> for Scratch {
+    fn as_ref(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
 
 #[cfg(test)]
 mod test {
@@ -6,7 +61,8 @@ mod test {
 
     #[test]
     fn test_add_command() {
-        let mut cmd = Command::cargo_bin("expense-tracker").unwrap();
+        let dir = scratch("add");
+        let mut cmd = dir.command();
         cmd.arg("add")
             .arg("-d")
             .arg("description")
@@ -18,58 +74,62 @@ mod test {
 
     #[test]
     fn test_update_command() {
-        let mut cmd = Command::cargo_bin("expense-tracker").unwrap();
-        cmd.arg("update").arg("--id").arg("arg").assert().success();
+        let dir = scratch("update");
+        let mut cmd = dir.command();
+        cmd.arg("update").arg("--id").arg("arg").assert().code(3);
 
-        let mut cmd = Command::cargo_bin("expense-tracker").unwrap();
+        let mut cmd = dir.command();
         cmd.arg("update")
             .arg("-i")
             .arg("arg")
             .arg("-d")
             .arg("arg")
             .assert()
-            .success();
+            .code(3);
     }
 
     #[test]
     fn test_delete_command() {
-        let mut cmd = Command::cargo_bin("expense-tracker").unwrap();
+        let dir = scratch("delete");
+        let mut cmd = dir.command();
         cmd.arg("delete").arg("arg").assert().failure();
 
-        let mut cmd = Command::cargo_bin("expense-tracker").unwrap();
+        let mut cmd = dir.command();
         cmd.arg("delete").arg("-d").assert().failure();
 
-        let mut cmd = Command::cargo_bin("expense-tracker").unwrap();
+        let mut cmd = dir.command();
         cmd.arg("delete").arg("-i").assert().failure();
 
-        let mut cmd = Command::cargo_bin("expense-tracker").unwrap();
-        cmd.arg("delete").arg("-i").arg("arg").assert().success();
+        let mut cmd = dir.command();
+        cmd.arg("delete").arg("-i").arg("arg").assert().code(3);
 
-        let mut cmd = Command::cargo_bin("expense-tracker").unwrap();
-        cmd.arg("delete").arg("--id").arg("arg").assert().success();
+        let mut cmd = dir.command();
+        cmd.arg("delete").arg("--id").arg("arg").assert().code(3);
     }
 
     #[test]
     fn test_list_command() {
-        let mut command = Command::cargo_bin("expense-tracker").unwrap();
+        let dir = scratch("list");
+        let mut command = dir.command();
         command.arg("list").arg("-c").arg("food").assert().success();
 
-        let mut cmd = Command::cargo_bin("expense-tracker").unwrap();
+        let mut cmd = dir.command();
         cmd.arg("list").assert().success();
 
-        let mut command = Command::cargo_bin("expense-tracker").unwrap();
+        let mut command = dir.command();
         command.arg("list").arg("arg").assert().failure();
     }
 
     #[test]
     fn test_summary_command() {
-        let mut cmd = Command::cargo_bin("expense-tracker").unwrap();
+        let dir = scratch("summary");
+        let mut cmd = dir.command();
         cmd.arg("summary").arg("-c").arg("food").assert().success();
 
-        let mut command = Command::cargo_bin("expense-tracker").unwrap();
+        let mut command = dir.command();
         command.arg("summary").arg("-m").arg("9").assert().success();
 
-        let mut command = Command::cargo_bin("expense-tracker").unwrap();
+        let mut command = dir.command();
         command
             .arg("summary")
             .arg("-a")
@@ -77,35 +137,1522 @@ mod test {
             .assert()
             .success();
 
-        let mut command = Command::cargo_bin("expense-tracker").unwrap();
+        let mut command = dir.command();
         command.arg("summary").assert().success();
     }
 
     #[test]
     fn test_export_command() {
-        let mut cmd = Command::cargo_bin("expense-tracker").unwrap();
+        let dir = scratch("export");
+        let mut cmd = dir.command();
         cmd.arg("export")
             .arg("--file")
-            .arg("filename")
+            .arg(dir.join("export.json"))
             .assert()
             .success();
     }
 
     #[test]
     fn test_budget_command() {
-        let mut cmd = Command::cargo_bin("expense-tracker").unwrap();
+        let dir = scratch("budget");
+        let mut cmd = dir.command();
         cmd.arg("budget").arg("-b").arg("30.0").assert().success();
 
-        let mut cmd = Command::cargo_bin("expense-tracker").unwrap();
+        let mut cmd = dir.command();
         cmd.arg("budget").arg("arg").assert().failure();
+
+        for budget in ["--budget=-5", "--budget=NaN", "--budget=inf"] {
+            let mut cmd = dir.command();
+            cmd.arg("budget").arg(budget).assert().code(4);
+        }
     }
 
     #[test]
     fn test_clear_command() {
-        let mut command = Command::cargo_bin("expense-tracker").unwrap();
+        let dir = scratch("clear");
+        let mut command = dir.command();
         command.arg("clear").assert().success();
 
-        let mut command = Command::cargo_bin("expense-tracker").unwrap();
+        let mut command = dir.command();
         command.arg("clear").arg("arg").assert().failure();
+
+        let mut command = dir.command();
+        command
+            .arg("clear")
+            .arg("--month")
+            .arg("13")
+            .assert()
+            .code(4);
+    }
+
+    #[test]
+    fn test_partial_clear() {
+        let dir = scratch("clear");
+
+        for (description, category) in [("Lunch", "food"), ("Bus", "transportation")] {
+            let mut cmd = dir.command();
+            cmd.arg("add")
+                .arg("-d")
+                .arg(description)
+                .arg("-a")
+                .arg("10")
+                .arg("-c")
+                .arg(category)
+                .assert()
+                .success();
+        }
+
+        // Declining keeps everything
+        let mut cmd = dir.command();
+        cmd.arg("clear").write_stdin("n\n").assert().success();
+
+        let mut cmd = dir.command();
+        cmd.arg("clear")
+            .arg("--category")
+            .arg("food")
+            .write_stdin("y\n")
+            .assert()
+            .success();
+
+        let mut cmd = dir.command();
+        let output = cmd
+            .arg("--output")
+            .arg("json")
+            .arg("list")
+            .output()
+            .unwrap();
+        let expenses: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(expenses.as_array().unwrap().len(), 1);
+        assert_eq!(expenses[0]["description"], "Bus");
+
+        let mut cmd = dir.command();
+        cmd.arg("clear").arg("--yes").assert().success();
+        let expenses = std::fs::read_to_string(dir.join("expenses.json")).unwrap();
+        assert_eq!(expenses.trim(), "[]");
+    }
+
+    #[test]
+    fn test_where_filter() {
+        let dir = scratch("where-filter");
+        let mut cmd = dir.command();
+        cmd.arg("list")
+            .arg("--where")
+            .arg("category = food and amount > 20")
+            .assert()
+            .success();
+
+        let mut cmd = dir.command();
+        cmd.arg("summary")
+            .arg("--where")
+            .arg("amount >")
+            .assert()
+            .code(6);
+
+        let mut cmd = dir.command();
+        cmd.arg("delete")
+            .arg("--id")
+            .arg("arg")
+            .arg("--where")
+            .arg("amount > 0")
+            .assert()
+            .failure();
+    }
+
+    #[test]
+    fn test_list_sort_and_paginate() {
+        let dir = scratch("page");
+        for (description, amount) in [("A", "5"), ("B", "20"), ("C", "10"), ("D", "15")] {
+            let add = dir.run(&["add", "-d", description, "-a", amount, "-t", "work"]);
+            assert!(add.status.success());
+        }
+        let page = [
+            "list",
+            "--sort",
+            "amount",
+            "--desc",
+            "--limit",
+            "2",
+            "--offset",
+            "1",
+            "--columns",
+            "description,amount,tags",
+        ];
+
+        // B is skipped, then C and D would follow A if the order were ascending.
+        let plain = dir.run(&[&["--output", "plain"], &page[..]].concat());
+        assert!(plain.status.success());
+        assert_eq!(
+            String::from_utf8(plain.stdout).unwrap(),
+            "D\t15\twork\nC\t10\twork\n"
+        );
+
+        let csv = dir.run(&[&["--output", "csv"], &page[..]].concat());
+        let csv = String::from_utf8(csv.stdout).unwrap();
+        assert_eq!(csv.lines().next(), Some("Description,Amount,Tags"));
+
+        let table = dir.run(&page);
+        let table = String::from_utf8(table.stdout).unwrap();
+        let header = table
+            .lines()
+            .find(|line| line.contains("Description"))
+            .unwrap();
+        assert!(header.contains("Amount") && header.contains("Tags"));
+        assert!(!header.contains("ID") && !header.contains("Category"));
+        let totals = table
+            .lines()
+            .find(|line| line.contains("Total (2)"))
+            .unwrap();
+        assert!(totals.contains("25"));
+        assert!(table.contains("Showing 2 of 4 expenses (offset 1)"));
+
+        let mut cmd = dir.command();
+        cmd.arg("list")
+            .arg("--sort")
+            .arg("price")
+            .assert()
+            .failure();
+
+        let mut cmd = dir.command();
+        cmd.arg("list").arg("--desc").assert().failure();
+    }
+
+    #[test]
+    fn test_output_formats() {
+        let dir = scratch("output-formats");
+        let mut cmd = dir.command();
+        let output = cmd
+            .arg("--output")
+            .arg("json")
+            .arg("summary")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert!(summary["total"].is_number());
+        assert!(summary["difference"].is_number());
+
+        let mut cmd = dir.command();
+        let output = cmd
+            .arg("list")
+            .arg("--output")
+            .arg("json")
+            .arg("--where")
+            .arg("amount >")
+            .output()
+            .unwrap();
+        let error: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+        assert_eq!(error["error"]["code"], "invalid_query");
+
+        let mut cmd = dir.command();
+        cmd.arg("--output")
+            .arg("csv")
+            .arg("list")
+            .assert()
+            .success();
+
+        let mut cmd = dir.command();
+        cmd.arg("--output")
+            .arg("xml")
+            .arg("list")
+            .assert()
+            .failure();
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        let dir = scratch("exit");
+        std::fs::write(dir.join("budget.json"), "not a number").unwrap();
+
+        let mut cmd = dir.command();
+        cmd.arg("summary").assert().code(6);
+
+        std::fs::write(dir.join("budget.json"), "10.0").unwrap();
+        let mut cmd = dir.command();
+        cmd.arg("summary").arg("-m").arg("14").assert().code(4);
+
+        let mut cmd = dir.command();
+        let output = cmd
+            .arg("--output")
+            .arg("json")
+            .arg("delete")
+            .arg("--id")
+            .arg("missing")
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(3));
+        let error: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+        assert_eq!(error["error"]["code"], "not_found");
+    }
+
+    #[test]
+    fn test_add_validation() {
+        let dir = scratch("add-validation");
+        for amount in ["-5", "0", "NaN", "inf"] {
+            let mut cmd = dir.command();
+            cmd.arg("add")
+                .arg("-d")
+                .arg("description")
+                .arg("-a")
+                .arg(amount)
+                .assert()
+                .code(4);
+        }
+
+        let mut cmd = dir.command();
+        cmd.arg("add")
+            .arg("-d")
+            .arg("  ")
+            .arg("-a")
+            .arg("10")
+            .assert()
+            .code(4);
+
+        let mut cmd = dir.command();
+        cmd.arg("add")
+            .arg("-d")
+            .arg("description")
+            .arg("-a")
+            .arg("10")
+            .arg("--date")
+            .arg("2025-13-01")
+            .assert()
+            .code(6);
+    }
+
+    #[test]
+    fn test_undo_redo_history() {
+        let dir = scratch("undo");
+        let count = || {
+            let output = dir.run(&["--output", "json", "list"]);
+            let expenses: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+            expenses.as_array().unwrap().len()
+        };
+
+        assert!(dir
+            .run(&["add", "-d", "Lunch", "-a", "10"])
+            .status
+            .success());
+        assert!(dir.run(&["clear", "--yes"]).status.success());
+        assert_eq!(count(), 0);
+
+        assert!(dir.run(&["undo"]).status.success());
+        assert_eq!(count(), 1);
+        assert!(dir.run(&["redo"]).status.success());
+        assert_eq!(count(), 0);
+        assert!(dir.run(&["undo", "--steps", "2"]).status.success());
+        assert_eq!(dir.run(&["undo"]).status.code(), Some(3));
+
+        let output = dir.run(&["--output", "json", "history"]);
+        let history: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(history.as_array().unwrap().len(), 2);
+        assert_eq!(history[0]["undone"], true);
+    }
+
+    #[test]
+    fn test_events_backend() {
+        let dir = scratch("events");
+        let run = |args: &[&str]| {
+            let mut cmd = dir.command();
+            cmd.env("EXPENSE_TRACKER_BACKEND", "events")
+                .args(args)
+                .output()
+                .unwrap()
+        };
+        let count = || {
+            let output = run(&["--output", "json", "list"]);
+            let expenses: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+            expenses.as_array().unwrap().len()
+        };
+
+        assert!(run(&["add", "-d", "Lunch", "-a", "10"]).status.success());
+        assert!(run(&["add", "-d", "Bus", "-a", "2"]).status.success());
+        assert!(run(&["budget", "-b", "100"]).status.success());
+        assert!(run(&["delete", "--where", "description = Bus"])
+            .status
+            .success());
+        assert_eq!(count(), 1);
+        assert!(!dir.join("expenses.json").exists());
+
+        // A half-written trailing event is ignored
+        let events = dir.join("events.jsonl");
+        let mut log = std::fs::read_to_string(&events).unwrap();
+        assert_eq!(log.lines().count(), 4);
+        log.push_str("{\"seq\":5,\"type\":\"Expense");
+        std::fs::write(&events, log).unwrap();
+        assert_eq!(count(), 1);
+
+        let output = run(&["--output", "json", "compact"]);
+        let compaction: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(compaction["events_removed"], 4);
+        assert_eq!(count(), 1);
+        let output = run(&["--output", "json", "summary"]);
+        let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(summary["budget"], 100.0);
+
+        let mut cmd = dir.command();
+        cmd.arg("compact").assert().code(4);
+    }
+
+    #[test]
+    fn test_concurrent_adds() {
+        let dir = scratch("lock");
+        let binary = assert_cmd::cargo::cargo_bin("expense-tracker");
+
+        let children: Vec<_> = (0..8)
+            .map(|n| {
+                std::process::Command::new(&binary)
+                    .env("EXPENSE_TRACKER_HOME", &*dir)
+                    .env("EXPENSE_TRACKER_CONFIG", dir.join("config.toml"))
+                    .env_remove("EXPENSE_TRACKER_BACKEND")
+                    .args([
+                        "--output",
+                        "json",
+                        "add",
+                        "-d",
+                        &format!("Expense {}", n),
+                        "-a",
+                        "1",
+                    ])
+                    .stdout(std::process::Stdio::null())
+                    .spawn()
+                    .unwrap()
+            })
+            .collect();
+        for mut child in children {
+            assert!(child.wait().unwrap().success());
+        }
+
+        let expenses = std::fs::read_to_string(dir.join("expenses.json")).unwrap();
+        let expenses: serde_json::Value = serde_json::from_str(&expenses).unwrap();
+        assert_eq!(expenses.as_array().unwrap().len(), 8);
+    }
+
+    #[test]
+    fn test_data_location() {
+        let dir = scratch("home");
+        let ledger = dir.join("elsewhere").join("ledger.json");
+
+        let mut cmd = dir.command();
+        cmd.args(["add", "-d", "Coffee", "-a", "3"])
+            .assert()
+            .success();
+        assert!(dir.join("expenses.json").exists());
+        assert!(dir.join("journal.json").exists());
+
+        let mut cmd = dir.command();
+        cmd.arg("--ledger")
+            .arg(&ledger)
+            .args(["add", "-d", "Tea", "-a", "2"])
+            .assert()
+            .success();
+        let mut cmd = dir.command();
+        cmd.arg("--ledger")
+            .arg(&ledger)
+            .args(["budget", "-b", "50"])
+            .assert()
+            .success();
+        assert!(ledger.exists());
+        assert!(dir.join("elsewhere").join("budget.json").exists());
+        assert!(!dir.join("budget.json").exists());
+
+        let mut cmd = dir.command();
+        let output = cmd.args(["--output", "json", "list"]).output().unwrap();
+        let expenses: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(expenses.as_array().unwrap().len(), 1);
+        assert_eq!(expenses[0]["description"], "Coffee");
+    }
+
+    #[test]
+    fn test_config_command() {
+        let dir = scratch("config");
+        let config = dir.join("config.toml");
+
+        let output = dir.run(&["config", "get", "default_category"]);
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "other");
+
+        assert!(dir
+            .run(&["config", "set", "default_category", "food"])
+            .status
+            .success());
+        assert!(dir
+            .run(&["config", "set", "backend", "events"])
+            .status
+            .success());
+        assert!(std::fs::read_to_string(&config)
+            .unwrap()
+            .contains("default_category = \"food\""));
+
+        // The configured category and backend apply unless overridden by flags.
+        assert!(dir
+            .run(&["add", "-d", "Lunch", "-a", "12"])
+            .status
+            .success());
+        assert!(dir.join("events.jsonl").exists());
+        assert!(!dir.join("expenses.json").exists());
+        assert!(dir
+            .run(&[
+                "--backend",
+                "json",
+                "add",
+                "-d",
+                "Bus",
+                "-a",
+                "2",
+                "-c",
+                "transportation"
+            ])
+            .status
+            .success());
+        let expenses = std::fs::read_to_string(dir.join("expenses.json")).unwrap();
+        assert!(expenses.contains("Transportation"));
+
+        let output = dir.run(&["--output", "json", "list"]);
+        let expenses: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(expenses[0]["category"], "Food");
+
+        let output = dir.run(&["--output", "json", "config", "list"]);
+        let settings: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(settings["backend"], "events");
+        assert_eq!(settings["week_start"], "monday");
+
+        assert_eq!(dir.run(&["config", "get", "colour"]).status.code(), Some(3));
+        assert_eq!(
+            dir.run(&["config", "set", "week_start", "friday"])
+                .status
+                .code(),
+            Some(4)
+        );
+
+        // Limits come from the [limits] table.
+        assert!(dir
+            .run(&["config", "set", "limits.max_amount", "100"])
+            .status
+            .success());
+        assert!(std::fs::read_to_string(&config)
+            .unwrap()
+            .contains("[limits]"));
+        assert_eq!(
+            dir.run(&["add", "-d", "TV", "-a", "200"]).status.code(),
+            Some(4)
+        );
+        assert_eq!(dir.run(&["budget", "-b", "200"]).status.code(), Some(4));
+        assert_eq!(
+            dir.run(&["config", "set", "limits.max_amount", "0"])
+                .status
+                .code(),
+            Some(4)
+        );
+
+        std::fs::write(&config, "currency = [").unwrap();
+        assert_eq!(dir.run(&["list"]).status.code(), Some(6));
+        assert_eq!(dir.run(&["config", "list"]).status.code(), Some(6));
+        assert!(dir.run(&["--help"]).status.success());
+
+        // `config set` puts the broken file aside and starts again from the defaults.
+        assert!(dir
+            .run(&["config", "set", "currency", "EUR"])
+            .status
+            .success());
+        assert!(dir.join("config.toml.bak").exists());
+        let output = dir.run(&["config", "get", "currency"]);
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "EUR");
+        assert!(dir.run(&["list"]).status.success());
+    }
+
+    #[test]
+    fn test_profiles() {
+        let dir = scratch("profiles");
+        let list = |args: &[&str]| {
+            let output = dir.run(&[&["--output", "json"], args, &["list"]].concat());
+            serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap()
+        };
+
+        assert_eq!(
+            dir.run(&["--profile", "business", "list"]).status.code(),
+            Some(3)
+        );
+        assert!(dir.run(&["profile", "create", "business"]).status.success());
+        assert_eq!(
+            dir.run(&["profile", "create", "business"]).status.code(),
+            Some(4)
+        );
+        assert_eq!(
+            dir.run(&["profile", "create", "../up"]).status.code(),
+            Some(4)
+        );
+
+        assert!(dir
+            .run(&["--profile", "business", "category", "add", "Travel"])
+            .status
+            .success());
+        assert!(dir
+            .run(&[
+                "--profile",
+                "business",
+                "add",
+                "-d",
+                "Flight",
+                "-a",
+                "300",
+                "-c",
+                "travel"
+            ])
+            .status
+            .success());
+        assert!(dir
+            .run(&["add", "-d", "Coffee", "-a", "3"])
+            .status
+            .success());
+
+        let business = list(&["--profile", "business"]);
+        assert_eq!(business.as_array().unwrap().len(), 1);
+        assert_eq!(business[0]["category"], "Travel");
+        assert_eq!(list(&[]).as_array().unwrap().len(), 1);
+
+        // Copying leaves the source alone; the target learns the custom category.
+        assert!(dir
+            .run(&[
+                "--profile",
+                "business",
+                "move",
+                "-w",
+                "category = Travel",
+                "--to",
+                "default",
+                "--copy"
+            ])
+            .status
+            .success());
+        assert_eq!(
+            list(&["--profile", "business"]).as_array().unwrap().len(),
+            1
+        );
+        assert_eq!(list(&[]).as_array().unwrap().len(), 2);
+        let output = dir.run(&["--output", "json", "category", "list"]);
+        assert!(String::from_utf8_lossy(&output.stdout).contains("Travel"));
+
+        assert!(dir
+            .run(&["move", "-w", "description = Coffee", "--to", "business"])
+            .status
+            .success());
+        assert_eq!(
+            list(&["--profile", "business"]).as_array().unwrap().len(),
+            2
+        );
+        assert_eq!(list(&[]).as_array().unwrap().len(), 1);
+
+        assert!(dir.run(&["profile", "switch", "business"]).status.success());
+        assert_eq!(list(&[]).as_array().unwrap().len(), 2);
+        assert_eq!(
+            dir.run(&["profile", "delete", "business", "-y"])
+                .status
+                .code(),
+            Some(4)
+        );
+        assert!(dir.run(&["profile", "switch", "default"]).status.success());
+        assert!(dir
+            .run(&["profile", "delete", "business", "-y"])
+            .status
+            .success());
+        assert_eq!(
+            dir.run(&["profile", "switch", "business"]).status.code(),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn test_opposite_moves() {
+        let dir = scratch("moves");
+        assert!(dir.run(&["profile", "create", "business"]).status.success());
+        for profile in ["default", "business"] {
+            for amount in ["1", "2", "3"] {
+                let add = dir.run(&["--profile", profile, "add", "-d", "Item", "-a", amount]);
+                assert!(add.status.success());
+            }
+        }
+
+        // Moves in opposite directions at the same time must neither hang nor lose expenses.
+        std::thread::scope(|scope| {
+            for n in 0..12 {
+                let (from, to) = match n % 2 {
+                    0 => ("default", "business"),
+                    _ => ("business", "default"),
+                };
+                let dir = &dir;
+                scope.spawn(move || {
+                    let output = dir
+                        .command()
+                        .timeout(std::time::Duration::from_secs(60))
+                        .args(["--profile", from, "move", "-w", "amount > 0", "--to", to])
+                        .output()
+                        .unwrap();
+                    // An earlier move may have left nothing to move.
+                    assert!(matches!(output.status.code(), Some(0 | 3)));
+                });
+            }
+        });
+
+        let count = |profile: &str| {
+            let output = dir.run(&["--output", "json", "--profile", profile, "list"]);
+            let expenses: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+            expenses.as_array().unwrap().len()
+        };
+        assert_eq!(count("default") + count("business"), 6);
+    }
+
+    #[test]
+    fn test_accounts() {
+        let dir = scratch("accounts");
+
+        assert!(dir
+            .run(&["account", "add", "Wallet", "--kind", "cash", "-o", "100"])
+            .status
+            .success());
+        assert!(dir
+            .run(&["account", "add", "Visa", "--kind", "card"])
+            .status
+            .success());
+        assert_eq!(
+            dir.run(&["account", "add", "Amex", "--kind", "crypto"])
+                .status
+                .code(),
+            Some(2)
+        );
+        assert_eq!(
+            dir.run(&["add", "-d", "Taxi", "-a", "12", "--account", "Amex"])
+                .status
+                .code(),
+            Some(3)
+        );
+
+        assert!(dir
+            .run(&["add", "-d", "Lunch", "-a", "12.5", "--account", "wallet"])
+            .status
+            .success());
+        assert!(dir
+            .run(&["add", "-d", "Shoes", "-a", "80", "--account", "Visa"])
+            .status
+            .success());
+        assert!(dir
+            .run(&["add", "-d", "Coffee", "-a", "3"])
+            .status
+            .success());
+
+        let output = dir.run(&["--output", "json", "balance"]);
+        let balances: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(balances[0]["account"], "Wallet");
+        assert_eq!(balances[0]["kind"], "cash");
+        assert_eq!(balances[0]["balance"], 87.5);
+        assert_eq!(balances[1]["balance"], -80.0);
+
+        let output = dir.run(&["--output", "json", "summary", "--account", "Visa"]);
+        let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(summary["count"], 1);
+        assert_eq!(summary["total"], 80.0);
+
+        let output = dir.run(&["--output", "csv", "summary", "--group-by", "account"]);
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "name,count,total\nVisa,1,80\nWallet,1,12.5\n(none),1,3\n"
+        );
+    }
+
+    #[test]
+    fn test_income() {
+        let dir = scratch("income");
+
+        assert!(dir.run(&["budget", "-b", "100"]).status.success());
+        assert!(dir
+            .run(&["add", "-d", "Salary", "-a", "2000", "--income"])
+            .status
+            .success());
+        let output = dir.run(&["add", "-d", "Rent", "-a", "500"]);
+        assert!(output.status.success());
+        // Income does not count against the budget; the rent alone crosses it.
+        assert_eq!(
+            String::from_utf8_lossy(&output.stderr)
+                .matches("Warning")
+                .count(),
+            1
+        );
+
+        let output = dir.run(&["--output", "json", "summary", "-m", "13"]);
+        let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(summary["count"], 1);
+        assert_eq!(summary["total"], 500.0);
+        assert_eq!(summary["income"], 2000.0);
+        assert_eq!(summary["net"], 1500.0);
+        assert_eq!(summary["savings_rate"], 75.0);
+
+        // The salary is listed, but neither added to the footer nor counted in it.
+        let table = String::from_utf8(dir.run(&["list"]).stdout).unwrap();
+        assert!(table.contains("Salary"));
+        let totals = table.lines().find(|line| line.contains("Total (")).unwrap();
+        assert!(totals.contains("Total (1)") && totals.contains("500"));
+
+        let output = dir.run(&["--output", "json", "list", "-w", "kind = income"]);
+        let income: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(income.as_array().unwrap().len(), 1);
+        let id = income[0]["id"].as_str().unwrap().to_string();
+        assert!(dir
+            .run(&["update", "-i", &id, "--kind", "expense"])
+            .status
+            .success());
+        let output = dir.run(&["--output", "json", "summary", "-m", "13"]);
+        let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(summary["total"], 2500.0);
+        assert!(summary["savings_rate"].is_null());
+        for kind in ["transfer", "settlement"] {
+            assert_eq!(
+                dir.run(&["update", "-i", &id, "--kind", kind])
+                    .status
+                    .code(),
+                Some(4)
+            );
+        }
+        assert!(dir
+            .run(&["update", "-i", &id, "--kind", "income"])
+            .status
+            .success());
+
+        // The prompt totals the rent alone, not the salary.
+        let mut cmd = dir.command();
+        let output = cmd.arg("clear").write_stdin("n\n").output().unwrap();
+        assert!(String::from_utf8_lossy(&output.stderr).contains("(total spending 500)"));
+    }
+
+    #[test]
+    fn test_transfers() {
+        let dir = scratch("transfers");
+
+        assert!(dir
+            .run(&["account", "add", "Checking", "-k", "bank", "-o", "1000"])
+            .status
+            .success());
+        assert!(dir
+            .run(&["account", "add", "Visa", "-k", "card"])
+            .status
+            .success());
+        assert!(dir
+            .run(&["add", "-d", "Shoes", "-a", "80", "--account", "visa"])
+            .status
+            .success());
+        assert!(dir
+            .run(&["transfer", "--from", "checking", "--to", "visa", "-a", "80"])
+            .status
+            .success());
+        assert_eq!(
+            dir.run(&["transfer", "--from", "Visa", "--to", "visa", "-a", "5"])
+                .status
+                .code(),
+            Some(4)
+        );
+        assert_eq!(
+            dir.run(&["transfer", "--from", "Visa", "--to", "Amex", "-a", "5"])
+                .status
+                .code(),
+            Some(3)
+        );
+
+        let output = dir.run(&["--output", "json", "balance"]);
+        let balances: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(balances[0]["balance"], 920.0);
+        assert_eq!(balances[1]["balance"], 0.0);
+
+        let output = dir.run(&["--output", "json", "summary", "-m", "13"]);
+        let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(summary["count"], 1);
+        assert_eq!(summary["total"], 80.0);
+        assert_eq!(summary["income"], 0.0);
+
+        let output = dir.run(&["--output", "json", "list", "-w", "kind = transfer"]);
+        let transfers: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(transfers[0]["account"], "Checking");
+        assert_eq!(transfers[0]["to_account"], "Visa");
+        let id = transfers[0]["id"].as_str().unwrap();
+        assert_eq!(
+            dir.run(&["update", "-i", id, "--kind", "transfer"])
+                .status
+                .code(),
+            Some(4)
+        );
+    }
+
+    #[test]
+    fn test_splits() {
+        let dir = scratch("splits");
+
+        assert!(dir.run(&["category", "add", "Household"]).status.success());
+        let output = dir.run(&[
+            "--output",
+            "json",
+            "add",
+            "-d",
+            "Supermarket",
+            "-a",
+            "60",
+            "--split",
+            "42.5:food",
+            "--split",
+            "17.5:household:cleaning",
+        ]);
+        assert!(output.status.success());
+        let expense: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(expense["category"], "Food");
+        assert_eq!(expense["splits"][1]["category"], "Household");
+        assert_eq!(expense["splits"][1]["note"], "cleaning");
+        let id = expense["id"].as_str().unwrap().to_string();
+
+        let mismatched = dir.run(&["add", "-d", "Shop", "-a", "10", "--split", "4:food"]);
+        assert_eq!(mismatched.status.code(), Some(4));
+        let malformed = dir.run(&["add", "-d", "Shop", "-a", "10", "--split", "food"]);
+        assert_eq!(malformed.status.code(), Some(4));
+
+        let output = dir.run(&["--output", "json", "summary", "-c", "household"]);
+        let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(summary["count"], 1);
+        assert_eq!(summary["total"], 17.5);
+
+        let output = dir.run(&["--output", "csv", "summary", "--group-by", "category"]);
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "name,count,total\nFood,1,42.5\nHousehold,1,17.5\n"
+        );
+
+        let output = dir.run(&["--output", "json", "list", "--expand"]);
+        let lines: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(lines.as_array().unwrap().len(), 2);
+        assert_eq!(lines[1]["description"], "Supermarket (cleaning)");
+
+        // Changing the amount needs new splits that add up to it.
+        assert_eq!(
+            dir.run(&["update", "-i", &id, "-a", "70"]).status.code(),
+            Some(4)
+        );
+        assert!(dir
+            .run(&["update", "-i", &id, "-a", "70", "--no-splits"])
+            .status
+            .success());
+    }
+
+    #[test]
+    fn test_settle() {
+        let dir = scratch("settle");
+
+        let output = dir.run(&[
+            "--output",
+            "json",
+            "add",
+            "-d",
+            "Groceries",
+            "-a",
+            "90",
+            "--paid-by",
+            "ann",
+            "--shared-with",
+            "ann,ben,cat",
+        ]);
+        assert!(output.status.success());
+        let expense: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(expense["shared"]["paid_by"], "ann");
+        assert_eq!(expense["shared"]["shares"][2]["amount"], 30.0);
+        assert!(dir
+            .run(&[
+                "add",
+                "-d",
+                "Internet",
+                "-a",
+                "40",
+                "--paid-by",
+                "ben",
+                "--shared-with",
+                "ann:50,ben:25,cat:25",
+                "--share-by",
+                "percent"
+            ])
+            .status
+            .success());
+        assert_eq!(
+            dir.run(&["add", "-d", "Gas", "-a", "10", "--shared-with", "ann,ben"])
+                .status
+                .code(),
+            Some(2)
+        );
+        assert_eq!(
+            dir.run(&[
+                "add",
+                "-d",
+                "Gas",
+                "-a",
+                "10",
+                "--paid-by",
+                "ann",
+                "--shared-with",
+                "ann,ben",
+                "--share-by",
+                "shares"
+            ])
+            .status
+            .code(),
+            Some(4)
+        );
+        // Weights without --share-by would otherwise be shared equally.
+        let output = dir.run(&[
+            "add",
+            "-d",
+            "Gas",
+            "-a",
+            "10",
+            "--paid-by",
+            "ann",
+            "--shared-with",
+            "ann:2,ben:1",
+        ]);
+        assert_eq!(output.status.code(), Some(4));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("--share-by"));
+
+        // A new amount is shared in the old proportions.
+        let output = dir.run(&["--output", "json", "list", "-w", "description = Internet"]);
+        let listed: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let internet = listed[0]["id"].as_str().unwrap().to_string();
+        assert!(dir
+            .run(&["update", "-i", &internet, "-a", "80"])
+            .status
+            .success());
+        let output = dir.run(&["--output", "json", "list", "-w", "description = Internet"]);
+        let listed: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(listed[0]["shared"]["shares"][0]["amount"], 40.0);
+        assert_eq!(listed[0]["shared"]["shares"][2]["amount"], 20.0);
+        assert!(dir
+            .run(&["update", "-i", &internet, "-a", "40"])
+            .status
+            .success());
+
+        // ann is owed 60 - 20, cat owes 30 + 10 and ben is even.
+        let output = dir.run(&["--output", "json", "settle", "--dry-run"]);
+        let payments: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(payments.as_array().unwrap().len(), 1);
+        assert_eq!(payments[0]["from"], "cat");
+        assert_eq!(payments[0]["to"], "ann");
+        assert_eq!(payments[0]["amount"], 40.0);
+
+        assert!(dir.run(&["settle"]).status.success());
+        let output = dir.run(&["settle"]);
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout).trim(),
+            "Everyone is settled up."
+        );
+        let output = dir.run(&["--output", "json", "summary", "-m", "13"]);
+        let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(summary["total"], 130.0);
+    }
+
+    #[test]
+    fn test_receipts() {
+        let dir = scratch("receipts");
+        let scan = dir.join("Scan.PDF");
+        std::fs::write(&scan, b"%PDF-1.4 receipt").unwrap();
+        let scan = scan.to_str().unwrap();
+
+        let output = dir.run(&[
+            "--output",
+            "json",
+            "add",
+            "-d",
+            "Laptop",
+            "-a",
+            "900",
+            "--receipt",
+            scan,
+        ]);
+        assert!(output.status.success());
+        let laptop: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(laptop["receipt"]["name"], "Scan.PDF");
+        let hash = laptop["receipt"]["hash"].as_str().unwrap().to_string();
+        assert_eq!(hash.len(), 64);
+        let stored = dir.join("attachments").join(&hash);
+        assert!(stored.exists());
+
+        let output = dir.run(&["--output", "json", "add", "-d", "Bag", "-a", "40"]);
+        let bag: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let bag_id = bag["id"].as_str().unwrap();
+        assert_eq!(
+            dir.run(&["receipt", "export", "-i", bag_id]).status.code(),
+            Some(3)
+        );
+        assert_eq!(
+            dir.run(&["attach", "-i", bag_id, "missing.pdf"])
+                .status
+                .code(),
+            Some(3)
+        );
+        // The same contents are stored once, whatever the file is called.
+        let copy = dir.join("copy.jpg");
+        std::fs::copy(scan, &copy).unwrap();
+        assert!(dir
+            .run(&["attach", "-i", bag_id, copy.to_str().unwrap()])
+            .status
+            .success());
+        assert!(dir.run(&["attach", "-i", bag_id, scan]).status.success());
+        assert_eq!(
+            std::fs::read_dir(dir.join("attachments")).unwrap().count(),
+            1
+        );
+
+        let output = dir.run(&[
+            "--output",
+            "csv",
+            "list",
+            "--columns",
+            "description,receipt",
+        ]);
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "Description,Receipt\nLaptop,Scan.PDF\nBag,Scan.PDF\n"
+        );
+
+        let out = dir.join("out");
+        std::fs::create_dir_all(&out).unwrap();
+        let laptop_id = laptop["id"].as_str().unwrap();
+        assert!(dir
+            .run(&[
+                "receipt",
+                "export",
+                "-i",
+                laptop_id,
+                "--to",
+                out.to_str().unwrap()
+            ])
+            .status
+            .success());
+        assert_eq!(
+            std::fs::read(out.join("Scan.PDF")).unwrap(),
+            b"%PDF-1.4 receipt"
+        );
+        let export = |force: bool| {
+            let mut args = vec!["receipt", "export", "-i", laptop_id, "--to"];
+            args.push(out.to_str().unwrap());
+            if force {
+                args.push("--force");
+            }
+            dir.run(&args).status.code()
+        };
+        assert_eq!(export(false), Some(4));
+        assert_eq!(export(true), Some(0));
+
+        // A tampered attachment is reported instead of exported.
+        std::fs::write(&stored, b"changed").unwrap();
+        assert_eq!(
+            dir.run(&[
+                "receipt",
+                "export",
+                "-i",
+                laptop_id,
+                "--to",
+                out.to_str().unwrap()
+            ])
+            .status
+            .code(),
+            Some(6)
+        );
+
+        // A hash edited into a path is refused rather than followed.
+        let ledger = std::fs::read_to_string(dir.join("expenses.json")).unwrap();
+        std::fs::write(
+            dir.join("expenses.json"),
+            ledger.replace(&hash, "../../config.toml"),
+        )
+        .unwrap();
+        assert_eq!(
+            dir.run(&["receipt", "export", "-i", laptop_id, "--force"])
+                .status
+                .code(),
+            Some(4)
+        );
+    }
+
+    #[test]
+    fn test_payees() {
+        let dir = scratch("payees");
+
+        assert!(dir
+            .run(&[
+                "payee",
+                "add",
+                "Amazon",
+                "--alias",
+                "AMZN Mktp",
+                "-c",
+                "entertainment"
+            ])
+            .status
+            .success());
+        assert_eq!(
+            dir.run(&["payee", "add", "amzn mktp"]).status.code(),
+            Some(4)
+        );
+        assert!(dir
+            .run(&["payee", "alias", "amazon", "Amazon.com"])
+            .status
+            .success());
+        assert_eq!(dir.run(&["payee", "remove", "Ebay"]).status.code(), Some(3));
+
+        for (payee, amount) in [("AMZN Mktp", "20"), ("amazon.com", "15"), ("Bakery", "5")] {
+            assert!(dir
+                .run(&["add", "-d", "Item", "-a", amount, "-p", payee])
+                .status
+                .success());
+        }
+        let output = dir.run(&["--output", "json", "list"]);
+        let expenses: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(expenses[0]["payee"], "Amazon");
+        assert_eq!(expenses[0]["category"], "Entertainment");
+        assert_eq!(expenses[1]["payee"], "Amazon");
+        assert_eq!(expenses[2]["payee"], "Bakery");
+        assert_eq!(expenses[2]["category"], "Other");
+
+        let output = dir.run(&["--output", "json", "summary", "--group-by", "payee"]);
+        let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(summary["group_by"], "Payee");
+        assert_eq!(summary["groups"][0]["name"], "Amazon");
+        assert_eq!(summary["groups"][0]["count"], 2);
+        assert_eq!(summary["groups"][0]["total"], 35.0);
+        assert_eq!(summary["groups"][1]["name"], "Bakery");
+
+        let output = dir.run(&["--output", "csv", "summary", "--group-by", "payee"]);
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "name,count,total\nAmazon,2,35\nBakery,1,5\n"
+        );
+    }
+
+    #[test]
+    fn test_recurring_command() {
+        let dir = scratch("recurring");
+        let start = (chrono::Utc::now().date_naive() - chrono::Days::new(14)).to_string();
+
+        let output = dir.run(&[
+            "--output",
+            "json",
+            "recurring",
+            "add",
+            "-d",
+            "Newspaper",
+            "-a",
+            "2",
+            "--every",
+            "weekly",
+            "--start",
+            &start,
+        ]);
+        assert!(output.status.success());
+        let templates: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let id = templates[0]["id"].as_str().unwrap().to_string();
+
+        // Today, one and two weeks ago.
+        let output = dir.run(&["--output", "json", "list"]);
+        let expenses: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(expenses.as_array().unwrap().len(), 3);
+        assert_eq!(expenses[0]["recurring"], id.as_str());
+
+        let output = dir.run(&["--output", "json", "recurring", "run"]);
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "[]");
+
+        let output = dir.run(&["--output", "json", "upcoming", "--days", "7"]);
+        let upcoming: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(upcoming.as_array().unwrap().len(), 1);
+        assert_eq!(upcoming[0]["id"], id.as_str());
+
+        let output = dir.run(&["--output", "json", "summary"]);
+        let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert!(summary["forecast"]["scheduled"].as_f64().unwrap() >= 0.0);
+        let output = dir.run(&["--output", "json", "summary", "-c", "food"]);
+        let summary: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert!(summary.get("forecast").is_none());
+
+        assert_eq!(
+            dir.run(&[
+                "recurring",
+                "add",
+                "-d",
+                "X",
+                "-a",
+                "1",
+                "--every",
+                "fortnightly"
+            ])
+            .status
+            .code(),
+            Some(2)
+        );
+
+        // A template edited out of range stops commands that read the ledger,
+        // but can still be listed and removed.
+        let file = dir.join("recurring.json");
+        let templates = std::fs::read_to_string(&file).unwrap();
+        let mut templates: serde_json::Value = serde_json::from_str(&templates).unwrap();
+        templates[0]["amount"] = serde_json::json!(2e9);
+        let start = chrono::Utc::now().date_naive() - chrono::Days::new(21);
+        templates[0]["start"] = serde_json::json!(start.to_string());
+        templates[0]["last_generated"] = serde_json::Value::Null;
+        std::fs::write(&file, templates.to_string()).unwrap();
+        assert_eq!(dir.run(&["list"]).status.code(), Some(4));
+        assert_eq!(dir.run(&["recurring", "run"]).status.code(), Some(4));
+        let output = dir.run(&["recurring", "list"]);
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains(&id));
+        assert!(dir.run(&["recurring", "remove", &id]).status.success());
+        assert!(dir.run(&["list"]).status.success());
+        assert_eq!(
+            dir.run(&["recurring", "remove", &id]).status.code(),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn test_metadata() {
+        let dir = scratch("metadata");
+
+        let output = dir.run(&[
+            "--output",
+            "json",
+            "add",
+            "-d",
+            "Dinner",
+            "-a",
+            "42",
+            "--note",
+            "Team dinner\nwith clients",
+            "--location",
+            "Berlin",
+            "--field",
+            "Project=Apollo",
+            "--field",
+            "client=ACME, Inc.",
+        ]);
+        assert!(output.status.success());
+        let dinner: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(dinner["note"], "Team dinner\nwith clients");
+        assert_eq!(dinner["location"], "Berlin");
+        assert_eq!(dinner["fields"]["project"], "Apollo");
+        let id = dinner["id"].as_str().unwrap().to_string();
+        assert!(dir
+            .run(&["add", "-d", "Coffee", "-a", "3"])
+            .status
+            .success());
+        assert_eq!(
+            dir.run(&["add", "-d", "X", "-a", "1", "--field", "project"])
+                .status
+                .code(),
+            Some(4)
+        );
+
+        let output = dir.run(&[
+            "--output",
+            "json",
+            "list",
+            "-w",
+            "field.project = apollo and note ~ clients",
+        ]);
+        let listed: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(listed.as_array().unwrap().len(), 1);
+        assert_eq!(listed[0]["id"], id.as_str());
+
+        // Plain output keeps the multi-line note on the expense's one line.
+        let output = dir.run(&[
+            "--output",
+            "plain",
+            "list",
+            "-w",
+            "description = dinner",
+            "--columns",
+            "description,note",
+        ]);
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "Dinner\tTeam dinner\\nwith clients\n"
+        );
+
+        let csv = dir.join("export.csv");
+        assert!(dir
+            .run(&["export", "-f", csv.to_str().unwrap()])
+            .status
+            .success());
+        let csv = std::fs::read_to_string(&csv).unwrap();
+        assert!(csv.starts_with(
+            "ID,Description,Amount,Category,Added At,Updated At,Note,Location,Fields\n"
+        ));
+        assert!(csv.contains(
+            ",\"Team dinner\nwith clients\",Berlin,\"client=ACME, Inc.; project=Apollo\"\n"
+        ));
+        let json = dir.join("export.json");
+        assert!(dir
+            .run(&["export", "-f", json.to_str().unwrap()])
+            .status
+            .success());
+        let exported: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&json).unwrap()).unwrap();
+        assert_eq!(exported[0]["fields"]["client"], "ACME, Inc.");
+
+        assert!(dir
+            .run(&[
+                "update",
+                "-i",
+                &id,
+                "--note",
+                "",
+                "--location",
+                "",
+                "--field",
+                "project="
+            ])
+            .status
+            .success());
+        let output = dir.run(&["--output", "json", "list", "-w", "description = dinner"]);
+        let listed: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert!(listed[0].get("note").is_none());
+        assert!(listed[0].get("location").is_none());
+        assert_eq!(
+            listed[0]["fields"],
+            serde_json::json!({"client": "ACME, Inc."})
+        );
+    }
+
+    #[test]
+    fn test_shell() {
+        let dir = scratch("shell");
+        let mut cmd = dir.command();
+        let output = cmd
+            .arg("shell")
+            .write_stdin(
+                "add -d Coffee -a 3 -c food\n\
+                 add -d 'Train ticket' -a 12.5\n\
+                 --output json list -w 'amount > 5'\n\
+                 bogus\n\
+                 shell\n\
+                 budget -b 100\n\
+                 summary --output json\n\
+                 quit\n\
+                 clear -y\n",
+            )
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains("\"description\": \"Train ticket\""));
+        assert!(!stdout.contains("\"description\": \"Coffee\""));
+        assert!(stdout.contains("\"budget\": 100.0"));
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("unrecognized subcommand 'bogus'"));
+        assert!(stderr.contains("Already in the shell"));
+
+        // Nothing after `quit` runs, and the changes were saved.
+        let mut cmd = dir.command();
+        let output = cmd.args(["--output", "json", "list"]).output().unwrap();
+        let listed: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(listed.as_array().unwrap().len(), 2);
+        let history = std::fs::read_to_string(dir.join("shell_history.txt")).unwrap();
+        assert!(history.contains("budget -b 100"));
+    }
+
+    #[test]
+    fn test_shell_sees_outside_changes() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::process::Stdio;
+
+        let dir = scratch("shell-outside");
+        assert!(dir.run(&["add", "-d", "Tea", "-a", "3"]).status.success());
+        let mut shell = std::process::Command::new(assert_cmd::cargo::cargo_bin("expense-tracker"))
+            .env("EXPENSE_TRACKER_HOME", &*dir)
+            .env("EXPENSE_TRACKER_CONFIG", dir.join("config.toml"))
+            .env_remove("EXPENSE_TRACKER_BACKEND")
+            .env_remove("EXPENSE_TRACKER_PROFILE")
+            .arg("shell")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let mut stdin = shell.stdin.take().unwrap();
+        let mut stdout = BufReader::new(shell.stdout.take().unwrap());
+        let mut list = || {
+            writeln!(stdin, "--output plain list --columns description").unwrap();
+            let mut line = String::new();
+            stdout.read_line(&mut line).unwrap();
+            line
+        };
+        assert!(list().contains("Tea"));
+
+        // Another process rewrites the ledger to the same size and mtime.
+        let ledger = dir.join("expenses.json");
+        let modified = std::fs::metadata(&ledger).unwrap().modified().unwrap();
+        let contents = std::fs::read_to_string(&ledger).unwrap();
+        std::fs::write(&ledger, contents.replace("Tea", "Pop")).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&ledger)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        assert!(list().contains("Pop"));
+
+        drop(stdin);
+        assert!(shell.wait().unwrap().success());
+    }
+
+    #[test]
+    fn test_tui_needs_a_terminal() {
+        let dir = scratch("tui");
+        let mut cmd = dir.command();
+        let output = cmd.arg("tui").output().unwrap();
+        assert_eq!(output.status.code(), Some(4));
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("interactive terminal"));
+    }
+
+    #[test]
+    fn test_guided_add() {
+        let dir = scratch("guided");
+        let run = |args: &[&str], input: &str| {
+            let mut cmd = dir.command();
+            cmd.args(args).write_stdin(input).output().unwrap()
+        };
+        assert!(run(
+            &["add", "-d", "Lunch at the cafe", "-a", "9", "-c", "food"],
+            ""
+        )
+        .status
+        .success());
+
+        // An empty answer takes the suggestion: food, from the similar lunch.
+        let output = run(
+            &["--output", "json", "add"],
+            "Cafe lunch with Sam\n12.5\n\n2025-03-01\nwork, team\n",
+        );
+        assert!(output.status.success());
+        let lunch: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(lunch["description"], "Cafe lunch with Sam");
+        assert_eq!(lunch["amount"], 12.5);
+        assert_eq!(lunch["category"], "Food");
+        assert_eq!(lunch["added_at"], "2025-03-01 00:00:00");
+        assert_eq!(lunch["tags"], serde_json::json!(["work", "team"]));
+        let prompts = String::from_utf8(output.stderr).unwrap();
+        assert!(prompts.contains("Category [food]: "));
+
+        // Flags become the suggested answers.
+        let output = run(
+            &["--output", "json", "add", "-c", "transportation"],
+            "Taxi\n20\n\n\n\n",
+        );
+        let taxi: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(taxi["category"], "Transportation");
+
+        assert_eq!(run(&["add"], "Taxi\nabc\n").status.code(), Some(4));
+        assert_eq!(run(&["add"], "Taxi\n").status.code(), Some(4));
+        assert_eq!(run(&["add", "-d", "Taxi"], "").status.code(), Some(2));
+        let output = run(&["--output", "json", "list"], "");
+        let listed: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(listed.as_array().unwrap().len(), 3);
     }
 }
@@ -1,5 +1,22 @@
 use expense_tracker::{
-    convert_from_system_time, get_month_from_date_string, map_category, Category, Expense,
+    clear_expenses, convert_from_system_time,
+    error::EXIT_PARSE,
+    events::{events_for_changes, Event, Record, Snapshot},
+    files::write_atomic,
+    get_month_from_date_string,
+    journal::{Journal, OperationKind},
+    map_category,
+    output::csv_field,
+    query::Query,
+    shell::{candidates, split_line},
+    sort_expenses,
+    store::Ledger,
+    tui::{Action, App, Edit},
+    validation::{
+        validate_amount, validate_budget, validate_date, validate_description, validate_expense,
+        validate_field, validate_note, Limits,
+    },
+    Category, Column, Expense, SortKey,
 };
 use std::time::SystemTime;
 
@@ -38,15 +55,991 @@ mod tests {
 
     #[test]
     fn test_get_month_from_date_string() {
-        let month = get_month_from_date_string("2024-09-05 16:06:22");
+        let month = get_month_from_date_string("2024-09-05 16:06:22").unwrap();
 
         assert_eq!(month, 9);
+
+        let error = get_month_from_date_string("05/09/2024").unwrap_err();
+        assert_eq!(error.code(), "invalid_date");
+        assert_eq!(error.exit_code(), EXIT_PARSE);
     }
 
     #[test]
     fn test_convert_from_system_time() {
         let time = convert_from_system_time(SystemTime::now());
-        assert!(time.contains("2024"));
-        assert!(time.contains("09"));
+        let now = chrono::Utc::now();
+        assert!(time.contains(&now.format("%Y").to_string()));
+        assert!(time.contains(&now.format("%m").to_string()));
+    }
+
+    #[test]
+    fn test_query_matches() {
+        let mut expense = Expense::new("Team lunch".to_string(), 40.0, map_category("food"));
+        expense.added_at = "2025-03-01 12:00:00".to_string();
+        expense.tags = vec!["work".to_string()];
+
+        let query =
+            Query::parse("category = food and amount > 20 and date >= 2025-01-01 and tag:work")
+                .unwrap();
+        assert!(query.matches(&expense));
+
+        let query = Query::parse("description ~ lunch and not (amount <= 40 or tag:home)").unwrap();
+        assert!(!query.matches(&expense));
+
+        let query = Query::parse("category = 'transportation' or tag != home").unwrap();
+        assert!(query.matches(&expense));
+    }
+
+    #[test]
+    fn test_query_parse_errors() {
+        let error = Query::parse("amount > twenty").unwrap_err();
+        assert_eq!(error.token, "twenty");
+        assert_eq!(error.position, 9);
+
+        let error = Query::parse("colour = red").unwrap_err();
+        assert_eq!(error.token, "colour");
+        assert_eq!(error.position, 0);
+
+        let error = Query::parse("amount ~ 20").unwrap_err();
+        assert_eq!(error.token, "~");
+
+        let error = Query::parse("(category = food").unwrap_err();
+        assert_eq!(error.position, 16);
+
+        assert!(Query::parse("date >= 2025-13-01").is_err());
+    }
+
+    #[test]
+    fn test_sort_expenses() {
+        let mut expenses = vec![
+            Expense::new("b".to_string(), 10.0, Category::Food),
+            Expense::new("c".to_string(), 30.0, Category::Other),
+            Expense::new("a".to_string(), 20.0, Category::Entertainment),
+        ];
+
+        sort_expenses(&mut expenses, SortKey::Amount, true);
+        let amounts: Vec<f64> = expenses.iter().map(|expense| expense.amount).collect();
+        assert_eq!(amounts, vec![30.0, 20.0, 10.0]);
+
+        sort_expenses(&mut expenses, SortKey::Description, false);
+        let descriptions: Vec<&str> = expenses
+            .iter()
+            .map(|expense| expense.description.as_str())
+            .collect();
+        assert_eq!(descriptions, vec!["a", "b", "c"]);
+
+        assert_eq!("AMOUNT".parse::<SortKey>(), Ok(SortKey::Amount));
+        assert!("price".parse::<SortKey>().is_err());
+        assert_eq!("tags".parse::<Column>(), Ok(Column::Tags));
+    }
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("Lunch"), "Lunch");
+        assert_eq!(csv_field("Lunch, with team"), "\"Lunch, with team\"");
+        assert_eq!(csv_field("6\" sub"), "\"6\"\" sub\"");
+    }
+
+    #[test]
+    fn test_validation() {
+        let limits = Limits::default();
+
+        assert_eq!(validate_amount(12.5, &limits).unwrap(), 12.5);
+        for amount in [0.0, -5.0, f64::NAN, f64::INFINITY, 2e9] {
+            assert_eq!(
+                validate_amount(amount, &limits).unwrap_err().code(),
+                "invalid_amount"
+            );
+        }
+
+        assert_eq!(validate_description("  Lunch ", &limits).unwrap(), "Lunch");
+        assert!(validate_description("   ", &limits).is_err());
+        assert!(validate_description("two\nlines", &limits).is_err());
+        let strict = Limits {
+            max_description_len: 3,
+            ..Limits::default()
+        };
+        assert!(validate_description("Lunch", &strict).is_err());
+
+        assert!(validate_date("2025-02-28", &limits).is_ok());
+        assert_eq!(
+            validate_date("2025-02-30", &limits).unwrap_err().code(),
+            "invalid_date"
+        );
+        assert_eq!(
+            validate_date("1900-01-01", &limits).unwrap_err().code(),
+            "validation"
+        );
+
+        assert_eq!(validate_budget(0.0, &limits).unwrap(), 0.0);
+        assert_eq!(validate_budget(250.0, &limits).unwrap(), 250.0);
+        for budget in [-1.0, f64::NAN, f64::NEG_INFINITY, 2e9] {
+            assert_eq!(
+                validate_budget(budget, &limits).unwrap_err().code(),
+                "invalid_amount"
+            );
+        }
+
+        let mut expense = Expense::new("Tea".to_string(), 3.0, Category::Food);
+        assert!(validate_expense(&expense, &limits).is_ok());
+        expense.added_at = "yesterday".to_string();
+        assert!(validate_expense(&expense, &limits).is_err());
+    }
+
+    #[test]
+    fn test_clear_expenses() {
+        let mut expenses = vec![
+            Expense::new("Lunch".to_string(), 10.0, Category::Food),
+            Expense::new("Bus".to_string(), 2.0, Category::Transportation),
+            Expense::new("Dinner".to_string(), 20.0, Category::Food),
+        ];
+
+        let cleared = clear_expenses(&mut expenses, |expense| expense.category == Category::Food);
+
+        assert_eq!(cleared.len(), 2);
+        assert_eq!(expenses.len(), 1);
+        assert_eq!(expenses[0].description, "Bus");
+    }
+
+    #[test]
+    fn test_journal_undo_redo() {
+        let lunch = Expense::new("Lunch".to_string(), 10.0, Category::Food);
+        let mut bus = Expense::new("Bus".to_string(), 2.0, Category::Transportation);
+        bus.id = format!("{}-bus", lunch.id);
+        let original = vec![lunch.clone(), bus.clone()];
+
+        let mut journal = Journal::default();
+        let mut expenses = original.clone();
+        expenses.remove(0);
+        expenses[0].amount = 3.0;
+        assert!(journal.record(OperationKind::Delete, &original, &expenses));
+        assert!(!journal.record(OperationKind::Update, &expenses, &expenses));
+
+        let operation = journal.undo(&mut expenses).unwrap();
+        assert_eq!(operation.kind, OperationKind::Delete);
+        assert_eq!(expenses, original);
+        assert!(journal.undo(&mut expenses).is_err());
+
+        journal.redo(&mut expenses).unwrap();
+        assert_eq!(expenses.len(), 1);
+        assert_eq!(expenses[0].amount, 3.0);
+        assert!(journal.redo(&mut expenses).is_err());
+
+        // Reverting refuses to clobber an expense changed outside the journal
+        expenses[0].amount = 4.0;
+        let error = journal.undo(&mut expenses).unwrap_err();
+        assert_eq!(error.code(), "validation");
+        assert!(error.to_string().ends_with("can no longer be undone"));
+
+        // Redoing says so in its own words.
+        expenses[0].amount = 3.0;
+        journal.undo(&mut expenses).unwrap();
+        expenses[1].amount = 5.0;
+        let error = journal.redo(&mut expenses).unwrap_err();
+        assert!(error.to_string().ends_with("can no longer be redone"));
+    }
+
+    #[test]
+    fn test_events_replay() {
+        let lunch = Expense::new("Lunch".to_string(), 10.0, Category::Food);
+        let mut updated = lunch.clone();
+        updated.amount = 12.0;
+
+        let events = events_for_changes(&[], std::slice::from_ref(&lunch));
+        assert_eq!(
+            events,
+            vec![Event::ExpenseAdded {
+                expense: lunch.clone()
+            }]
+        );
+        let events =
+            events_for_changes(std::slice::from_ref(&lunch), std::slice::from_ref(&updated));
+        assert_eq!(
+            events,
+            vec![Event::ExpenseUpdated {
+                expense: updated.clone()
+            }]
+        );
+
+        let mut state = Snapshot::default();
+        let records = [
+            Event::ExpenseAdded { expense: lunch },
+            Event::ExpenseUpdated { expense: updated },
+            Event::BudgetSet { budget: 50.0 },
+        ];
+        for (seq, event) in records.into_iter().enumerate() {
+            state.apply(&Record {
+                seq: seq as u64 + 1,
+                at: "2025-01-01 00:00:00".to_string(),
+                event,
+            });
+        }
+        assert_eq!(state.seq, 3);
+        assert_eq!(state.budget, 50.0);
+        assert_eq!(state.expenses[0].amount, 12.0);
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let dir =
+            std::env::temp_dir().join(format!("expense-tracker-atomic-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("expenses.json");
+
+        write_atomic(&path, b"[1]").unwrap();
+        write_atomic(&path, b"[2]").unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[2]");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_config_settings() {
+        use expense_tracker::config::{Config, TableStyle, KEYS};
+
+        let mut config = Config::default();
+        for key in KEYS {
+            let value = config.get(key).unwrap();
+            config.set(key, &value).unwrap();
+        }
+        assert_eq!(config, Config::default());
+
+        config.set("table_style", "UTF8").unwrap();
+        assert_eq!(config.table_style, TableStyle::Utf8);
+        config.set("budget_thresholds", "100, 50").unwrap();
+        assert_eq!(config.budget_thresholds, vec![50, 100]);
+        assert!(config.set("budget_thresholds", "half").is_err());
+        assert!(config.set("date_format", "%Q").is_err());
+        config.set("limits.max_note_len", "50").unwrap();
+        config.set("limits.earliest_date", "2000-01-01").unwrap();
+        assert_eq!(config.limits.max_note_len, 50);
+        assert_eq!(config.get("limits.earliest_date").unwrap(), "2000-01-01");
+        assert!(config.set("limits.max_description_len", "0").is_err());
+        assert!(config.set("limits.max_days_ahead", "-1").is_err());
+        assert!(config.get("nope").is_err());
+
+        config.set("currency", "$").unwrap();
+        config.set("date_format", "%d/%m/%Y").unwrap();
+        assert_eq!(config.format_amount(12.5), "$12.5");
+        assert_eq!(config.format_date("2024-03-09 10:00:00"), "09/03/2024");
+        assert_eq!(config.format_date("yesterday"), "yesterday");
+
+        let saturday = chrono::NaiveDate::from_ymd_opt(2024, 3, 9).unwrap();
+        assert_eq!(config.week_of(saturday).to_string(), "2024-03-04");
+        config.set("week_start", "sunday").unwrap();
+        assert_eq!(config.week_of(saturday).to_string(), "2024-03-03");
+
+        assert_eq!(config.crossed_threshold(40.0, 60.0, 100.0), Some(50));
+        assert_eq!(config.crossed_threshold(40.0, 120.0, 100.0), Some(100));
+        assert_eq!(config.crossed_threshold(60.0, 70.0, 100.0), None);
+        assert_eq!(config.crossed_threshold(0.0, 70.0, 0.0), None);
+    }
+
+    #[test]
+    fn test_custom_categories() {
+        use expense_tracker::categories::{add_category, remove_category, resolve_category};
+
+        let limits = Limits::default();
+        let mut categories = vec![];
+        add_category(&mut categories, " Travel ", &limits).unwrap();
+        assert!(add_category(&mut categories, "travel", &limits).is_err());
+        assert!(add_category(&mut categories, "Food", &limits).is_err());
+        assert_eq!(categories, vec!["Travel".to_string()]);
+
+        let travel = resolve_category("TRAVEL", &categories);
+        assert_eq!(travel, Category::Custom("Travel".to_string()));
+        assert_eq!(travel.to_string(), "Travel");
+        assert_eq!(resolve_category("food", &categories), Category::Food);
+        assert_eq!(resolve_category("gifts", &categories), Category::Other);
+
+        // Custom categories are stored by name, next to the built-in ones.
+        let expense = Expense::new("Flight".to_string(), 300.0, travel);
+        let json = serde_json::to_string(&expense).unwrap();
+        assert!(json.contains(r#""category":"Travel""#));
+        assert_eq!(serde_json::from_str::<Expense>(&json).unwrap(), expense);
+        let food = serde_json::to_string(&Category::Food).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Category>(&food).unwrap(),
+            Category::Food
+        );
+
+        remove_category(&mut categories, "travel").unwrap();
+        assert!(remove_category(&mut categories, "travel").is_err());
+    }
+
+    #[test]
+    fn test_accounts() {
+        use expense_tracker::accounts::{
+            add_account, balances, find_account, remove_account, Account, AccountKind,
+        };
+
+        let limits = Limits::default();
+        let mut accounts = vec![];
+        let visa = Account {
+            name: " Visa ".to_string(),
+            kind: "credit".parse().unwrap(),
+            opening_balance: 0.0,
+        };
+        add_account(&mut accounts, visa.clone(), &limits).unwrap();
+        assert!(add_account(&mut accounts, visa, &limits).is_err());
+        add_account(
+            &mut accounts,
+            Account {
+                name: "Wallet".to_string(),
+                kind: AccountKind::Cash,
+                opening_balance: 100.0,
+            },
+            &limits,
+        )
+        .unwrap();
+        assert_eq!(
+            find_account(&accounts, "visa").unwrap().kind,
+            AccountKind::Card
+        );
+        assert!(find_account(&accounts, "Amex").is_err());
+        assert!("crypto".parse::<AccountKind>().is_err());
+
+        let paid = |account: Option<&str>, amount: f64| {
+            let mut expense = Expense::new("Item".to_string(), amount, Category::Food);
+            expense.account = account.map(str::to_string);
+            expense
+        };
+        let expenses = [
+            paid(Some("Wallet"), 20.0),
+            paid(Some("Visa"), 50.0),
+            paid(Some("Wallet"), 5.5),
+            paid(None, 8.0),
+        ];
+        let balances = balances(&accounts, &expenses);
+        assert_eq!(balances[0].account, "Visa");
+        assert_eq!(balances[0].balance, -50.0);
+        assert_eq!(balances[1].spent, 25.5);
+        assert_eq!(balances[1].balance, 74.5);
+
+        remove_account(&mut accounts, "WALLET").unwrap();
+        assert!(remove_account(&mut accounts, "Wallet").is_err());
+    }
+
+    #[test]
+    fn test_income() {
+        use expense_tracker::accounts::{balances, Account, AccountKind};
+        use expense_tracker::{group_expenses, GroupBy, TransactionKind};
+
+        let mut lunch = Expense::new("Lunch".to_string(), 12.0, Category::Food);
+        lunch.account = Some("Bank".to_string());
+        let mut salary = Expense::new("Salary".to_string(), 3000.0, Category::Other);
+        salary.kind = "Income".parse().unwrap();
+        salary.account = Some("Bank".to_string());
+        assert!(!salary.is_spending());
+        assert!("gift".parse::<TransactionKind>().is_err());
+
+        // Plain expenses keep their old JSON shape.
+        assert!(!serde_json::to_string(&lunch).unwrap().contains("kind"));
+        let json = serde_json::to_string(&salary).unwrap();
+        assert!(json.contains(r#""kind":"income""#));
+        assert_eq!(serde_json::from_str::<Expense>(&json).unwrap(), salary);
+
+        let ledger = [lunch, salary];
+        let groups = group_expenses(&ledger, GroupBy::Category);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].total, 12.0);
+
+        let bank = Account {
+            name: "Bank".to_string(),
+            kind: AccountKind::Bank,
+            opening_balance: 100.0,
+        };
+        let balance = &balances(&[bank], &ledger)[0];
+        assert_eq!((balance.income, balance.spent), (3000.0, 12.0));
+        assert_eq!(balance.balance, 3088.0);
+    }
+
+    #[test]
+    fn test_transfers() {
+        use expense_tracker::accounts::{balances, Account, AccountKind};
+        use expense_tracker::forecast::forecast;
+        use expense_tracker::{group_expenses, GroupBy};
+
+        let account = |name: &str, kind, opening_balance| Account {
+            name: name.to_string(),
+            kind,
+            opening_balance,
+        };
+        let accounts = [
+            account("Checking", AccountKind::Bank, 1000.0),
+            account("Visa", AccountKind::Card, 0.0),
+        ];
+        let mut shoes = Expense::new("Shoes".to_string(), 80.0, Category::Other);
+        shoes.account = Some("Visa".to_string());
+        let payment = Expense::transfer("Checking".to_string(), "Visa".to_string(), 80.0);
+        assert_eq!(payment.description, "Transfer to Visa");
+        assert!(!payment.is_spending() && !payment.is_income());
+
+        let ledger = [shoes, payment];
+        let balances = balances(&accounts, &ledger);
+        assert_eq!(balances[0].transferred, -80.0);
+        assert_eq!(balances[0].balance, 920.0);
+        assert_eq!(balances[1].spent, 80.0);
+        assert_eq!(balances[1].balance, 0.0);
+
+        // Only the shoes count as spending.
+        assert_eq!(group_expenses(&ledger, GroupBy::Account)[0].total, 80.0);
+        let today = chrono::Utc::now().date_naive();
+        assert_eq!(forecast(&ledger, &[], 0.0, today).actual, 80.0);
+    }
+
+    #[test]
+    fn test_splits() {
+        use expense_tracker::{expand_splits, group_expenses, GroupBy, Split};
+
+        let split = |amount: f64, category: Category, note: Option<&str>| Split {
+            amount,
+            category,
+            note: note.map(str::to_string),
+        };
+        let mut receipt = Expense::new("Supermarket".to_string(), 60.0, Category::Food);
+        receipt.splits = vec![
+            split(45.0, Category::Food, None),
+            split(
+                15.0,
+                Category::Custom("Household".to_string()),
+                Some("soap"),
+            ),
+        ];
+        validate_expense(&receipt, &Limits::default()).unwrap();
+
+        let json = serde_json::to_string(&receipt).unwrap();
+        assert_eq!(serde_json::from_str::<Expense>(&json).unwrap(), receipt);
+
+        let lines = expand_splits(&[receipt.clone()]);
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|line| line.id == receipt.id));
+        assert_eq!(lines[1].description, "Supermarket (soap)");
+        assert_eq!(lines[1].amount, 15.0);
+        let groups = group_expenses(&lines, GroupBy::Category);
+        assert_eq!(
+            (groups[1].name.as_str(), groups[1].total),
+            ("Household", 15.0)
+        );
+
+        receipt.amount = 70.0;
+        assert!(validate_expense(&receipt, &Limits::default()).is_err());
+    }
+
+    #[test]
+    fn test_metadata() {
+        let limits = Limits::default();
+        assert_eq!(
+            validate_note(" Team dinner\nwith clients ", &limits).unwrap(),
+            "Team dinner\nwith clients"
+        );
+        assert!(validate_note("bell\u{7}", &limits).is_err());
+        assert_eq!(
+            validate_field("Project = Apollo", &limits).unwrap(),
+            ("project".to_string(), "Apollo".to_string())
+        );
+        assert_eq!(
+            validate_field("project=", &limits).unwrap(),
+            ("project".to_string(), String::new())
+        );
+        assert!(validate_field("project", &limits).is_err());
+        assert!(validate_field("my project=Apollo", &limits).is_err());
+
+        let mut dinner = Expense::new("Dinner".to_string(), 42.0, Category::Food);
+        dinner.note = Some("Team dinner\nwith clients".to_string());
+        dinner.location = Some("Berlin".to_string());
+        dinner
+            .fields
+            .insert("project".to_string(), "Apollo".to_string());
+        let coffee = Expense::new("Coffee".to_string(), 3.0, Category::Food);
+        let matches = |query: &str| {
+            let query: Query = query.parse().unwrap();
+            [&dinner, &coffee]
+                .iter()
+                .filter(|expense| query.matches(expense))
+                .map(|expense| expense.description.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(matches("note ~ clients"), ["Dinner"]);
+        assert_eq!(matches("location = berlin"), ["Dinner"]);
+        assert_eq!(matches("field.project = apollo"), ["Dinner"]);
+        assert_eq!(matches("field.project != apollo"), ["Coffee"]);
+        assert_eq!(matches("field.client = ''"), ["Dinner", "Coffee"]);
+        assert!("field. = x".parse::<Query>().is_err());
+
+        dinner.note = Some("x".repeat(limits.max_note_len + 1));
+        assert!(validate_expense(&dinner, &limits).is_err());
+    }
+
+    #[test]
+    fn test_shell() {
+        assert_eq!(
+            split_line(r#"add -d "Train ticket" -a 12.5 -w 'tag:work'"#).unwrap(),
+            ["add", "-d", "Train ticket", "-a", "12.5", "-w", "tag:work"]
+        );
+        assert!(split_line("add -d \"Train").is_err());
+
+        let command = clap::Command::new("expense-tracker")
+            .arg(clap::Arg::new("output").long("output").global(true))
+            .subcommand(
+                clap::Command::new("add")
+                    .arg(clap::Arg::new("category").short('c').long("category"))
+                    .arg(clap::Arg::new("description").long("description")),
+            )
+            .subcommand(clap::Command::new("update").arg(clap::Arg::new("id").long("id")))
+            .subcommand(clap::Command::new("category").subcommand(clap::Command::new("list")));
+        let categories = ["food".to_string(), "Groceries".to_string()];
+        let ids = ["1234".to_string(), "5678".to_string()];
+        let complete = |line: &str| candidates(&command, line, &categories, &ids);
+
+        assert_eq!(
+            complete(""),
+            (0, vec!["add".into(), "category".into(), "update".into()])
+        );
+        assert_eq!(complete("up"), (0, vec!["update".to_string()]));
+        assert_eq!(complete("category "), (9, vec!["list".to_string()]));
+        assert_eq!(
+            complete("add --"),
+            (
+                4,
+                vec![
+                    "--category".into(),
+                    "--description".into(),
+                    "--output".into()
+                ]
+            )
+        );
+        assert_eq!(complete("add -c g"), (7, vec!["Groceries".to_string()]));
+        assert_eq!(complete("update --id 5"), (12, vec!["5678".to_string()]));
+    }
+
+    #[test]
+    fn test_tui() {
+        use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        let type_text = |app: &mut App, text: &str| {
+            for character in text.chars() {
+                assert_eq!(app.handle_key(key(KeyCode::Char(character))), None);
+            }
+        };
+
+        let mut coffee = Expense::new("Coffee".to_string(), 3.0, Category::Food);
+        coffee.added_at = "2025-03-01 08:00:00".to_string();
+        let mut train = Expense::new("Train".to_string(), 12.5, Category::Transportation);
+        train.id = format!("{}0", coffee.id);
+        train.added_at = "2025-03-02 08:00:00".to_string();
+        let ledger = Ledger {
+            expenses: vec![coffee.clone(), train.clone()],
+            budget: 100.0,
+        };
+        let mut app = App::new(ledger.clone(), vec![], Limits::default());
+
+        // Newest first; `s` sorts by amount, `S` reverses.
+        assert_eq!(app.selected(), Some(&train));
+        app.handle_key(key(KeyCode::Char('s')));
+        app.handle_key(key(KeyCode::Char('S')));
+        assert_eq!(app.rows()[0], coffee);
+
+        app.handle_key(key(KeyCode::Char('/')));
+        type_text(&mut app, "amount >");
+        app.handle_key(key(KeyCode::Enter));
+        assert!(!app.status().is_empty());
+        type_text(&mut app, " 5");
+        app.handle_key(key(KeyCode::Enter));
+        assert_eq!(app.rows(), [train.clone()]);
+
+        let mut terminal =
+            ratatui::Terminal::new(ratatui::backend::TestBackend::new(100, 20)).unwrap();
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("Train"));
+        assert!(!screen.contains("Coffee"));
+        assert!(screen.contains("amount > 5"));
+        assert!(screen.contains("By category"));
+
+        // Editing keeps the ID and time of day.
+        app.handle_key(key(KeyCode::Char('e')));
+        app.handle_key(key(KeyCode::Tab));
+        app.handle_key(key(KeyCode::Backspace));
+        app.handle_key(key(KeyCode::Backspace));
+        let Some(Action::Save(edited)) = app.handle_key(key(KeyCode::Enter)) else {
+            panic!("expected an update");
+        };
+        let Edit::Update { id, changes } = &edited else {
+            panic!("expected an update");
+        };
+        assert_eq!(id, &train.id);
+        assert_eq!(changes.amount, 12.0);
+
+        // Only the fields of the form are saved over the expense as it now is.
+        let mut expenses = ledger.expenses.clone();
+        expenses[1].note = Some("Return ticket".to_string());
+        edited.apply(&mut expenses, &Limits::default()).unwrap();
+        assert_eq!(expenses[1].amount, 12.0);
+        assert_eq!(expenses[1].added_at, train.added_at);
+        assert_eq!(expenses[1].note.as_deref(), Some("Return ticket"));
+
+        // A bad amount keeps the form open.
+        app.handle_key(key(KeyCode::Char('a')));
+        type_text(&mut app, "Lunch");
+        app.handle_key(key(KeyCode::Tab));
+        type_text(&mut app, "abc");
+        assert_eq!(app.handle_key(key(KeyCode::Enter)), None);
+        for _ in 0..3 {
+            app.handle_key(key(KeyCode::Backspace));
+        }
+        type_text(&mut app, "8");
+        let Some(Action::Save(Edit::Add(lunch))) = app.handle_key(key(KeyCode::Enter)) else {
+            panic!("expected an addition");
+        };
+        assert_eq!(lunch.description, "Lunch");
+
+        app.handle_key(key(KeyCode::Char('d')));
+        assert_eq!(
+            app.handle_key(key(KeyCode::Char('y'))),
+            Some(Action::Save(Edit::Delete(train.id.clone())))
+        );
+
+        let mut expenses = ledger.expenses.clone();
+        Edit::Add(lunch)
+            .apply(&mut expenses, &Limits::default())
+            .unwrap();
+        Edit::Delete(train.id.clone())
+            .apply(&mut expenses, &Limits::default())
+            .unwrap();
+        assert_eq!(expenses.len(), 2);
+        assert_eq!(
+            Edit::Delete(train.id.clone())
+                .apply(&mut expenses, &Limits::default())
+                .unwrap_err()
+                .code(),
+            "not_found"
+        );
+        assert_eq!(app.handle_key(key(KeyCode::Char('q'))), Some(Action::Quit));
+    }
+
+    #[test]
+    fn test_suggest_category() {
+        use expense_tracker::categories::suggest_category;
+        use expense_tracker::TransactionKind;
+
+        let mut salary = Expense::new("Lunch money".to_string(), 900.0, Category::Other);
+        salary.kind = TransactionKind::Income;
+        let ledger = [
+            Expense::new("Lunch at the cafe".to_string(), 9.0, Category::Food),
+            Expense::new("Cafe".to_string(), 3.0, Category::Food),
+            Expense::new("Bus ticket".to_string(), 2.0, Category::Transportation),
+            Expense::new(
+                "Concert ticket".to_string(),
+                40.0,
+                Category::Custom("Music".to_string()),
+            ),
+            salary,
+        ];
+
+        assert_eq!(
+            suggest_category(&ledger, "Cafe LUNCH with Sam"),
+            Some(Category::Food)
+        );
+        assert_eq!(
+            suggest_category(&ledger, "Bus ticket to town"),
+            Some(Category::Transportation)
+        );
+        assert_eq!(
+            suggest_category(&ledger, "ticket for the concert"),
+            Some(Category::Custom("Music".to_string()))
+        );
+        // Short words and income do not count.
+        assert_eq!(suggest_category(&ledger, "at a"), None);
+        assert_eq!(suggest_category(&ledger, "money"), None);
+    }
+
+    #[test]
+    fn test_shared_expenses() {
+        use expense_tracker::shared::{divide, net_balances, settle, ShareMethod, Shared};
+
+        let people = |pairs: &[(&str, f64)]| -> Vec<(String, f64)> {
+            pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), *value))
+                .collect()
+        };
+        let amounts = |method, pairs: &[(&str, f64)]| -> Vec<f64> {
+            divide(100.0, method, &people(pairs))
+                .unwrap()
+                .iter()
+                .map(|share| share.amount)
+                .collect()
+        };
+        let trio = [("Ann", 0.0), ("Ben", 0.0), ("Cat", 0.0)];
+        assert_eq!(
+            amounts(ShareMethod::Equal, &trio),
+            vec![33.34, 33.33, 33.33]
+        );
+        assert_eq!(
+            amounts(ShareMethod::Shares, &[("Ann", 3.0), ("Ben", 1.0)]),
+            vec![75.0, 25.0]
+        );
+        assert_eq!(
+            amounts(ShareMethod::Percent, &[("Ann", 60.0), ("Ben", 40.0)]),
+            vec![60.0, 40.0]
+        );
+        let exact = divide(
+            6.7,
+            ShareMethod::Exact,
+            &people(&[("Alice", 5.0), ("Bob", 0.03), ("Carol", 1.67)]),
+        )
+        .unwrap();
+        assert_eq!(
+            exact.iter().map(|share| share.amount).collect::<Vec<_>>(),
+            vec![5.0, 0.03, 1.67]
+        );
+        assert!(divide(100.0, ShareMethod::Exact, &people(&[("Ann", 60.0)])).is_err());
+        assert!(divide(100.0, ShareMethod::Percent, &people(&[("Ann", 60.0)])).is_err());
+        assert!("halves".parse::<ShareMethod>().is_err());
+
+        let shared = |paid_by: &str, amount: f64| {
+            let mut expense = Expense::new("Dinner".to_string(), amount, Category::Food);
+            expense.shared = Some(Shared {
+                paid_by: paid_by.to_string(),
+                shares: divide(amount, ShareMethod::Equal, &people(&trio)).unwrap(),
+            });
+            expense
+        };
+        let mut ledger = vec![shared("Ann", 90.0), shared("ben", 30.0)];
+        let balances = net_balances(&ledger);
+        assert_eq!(
+            balances,
+            people(&[("Ann", 50.0), ("Ben", -10.0), ("Cat", -40.0)])
+        );
+
+        let payments = settle(&balances);
+        assert_eq!(payments.len(), 2);
+        assert_eq!(
+            (payments[0].from.as_str(), payments[0].to.as_str()),
+            ("Cat", "Ann")
+        );
+        assert_eq!(payments[0].amount, 40.0);
+        assert_eq!(payments[1].amount, 10.0);
+
+        // Paying the largest creditor first would take four payments here;
+        // settling Dan with Eve on their own takes three.
+        let overlapping = people(&[
+            ("Ann", -6.0),
+            ("Ben", 2.0),
+            ("Cat", 4.0),
+            ("Dan", -5.0),
+            ("Eve", 5.0),
+        ]);
+        let shortest = settle(&overlapping);
+        assert_eq!(shortest.len(), 3);
+        assert!(shortest
+            .iter()
+            .any(|payment| payment.from == "Dan" && payment.to == "Eve" && payment.amount == 5.0));
+
+        // Recorded settlements bring everyone back to zero.
+        ledger.extend(payments.iter().map(|payment| payment.to_expense()));
+        assert!(!ledger[2].is_spending() && !ledger[2].is_income());
+        assert!(settle(&net_balances(&ledger)).is_empty());
+    }
+
+    #[test]
+    fn test_payees() {
+        use expense_tracker::payees::{add_alias, add_payee, normalize_payee, Payee};
+        use expense_tracker::{group_expenses, GroupBy};
+
+        let limits = Limits::default();
+        let mut payees = vec![];
+        add_payee(
+            &mut payees,
+            Payee {
+                name: "Amazon".to_string(),
+                aliases: vec!["AMZN Mktp".to_string()],
+                category: Some(Category::Other),
+            },
+            &limits,
+        )
+        .unwrap();
+        add_alias(&mut payees, "amazon", "Amazon.com", &limits).unwrap();
+        assert!(add_alias(&mut payees, "Amazon", "amzn mktp", &limits).is_err());
+        assert!(add_alias(&mut payees, "Ebay", "eBay Inc", &limits).is_err());
+        assert_eq!(
+            normalize_payee(&payees, " amzn mktp ", &limits).unwrap(),
+            "Amazon"
+        );
+        assert_eq!(
+            normalize_payee(&payees, "AMAZON.COM", &limits).unwrap(),
+            "Amazon"
+        );
+        assert_eq!(
+            normalize_payee(&payees, " Bakery ", &limits).unwrap(),
+            "Bakery"
+        );
+
+        let paid = |payee: Option<&str>, amount: f64| {
+            let mut expense = Expense::new("Item".to_string(), amount, Category::Food);
+            expense.payee = payee.map(str::to_string);
+            expense
+        };
+        let expenses = [
+            paid(Some("Amazon"), 20.0),
+            paid(Some("Bakery"), 5.0),
+            paid(Some("Amazon"), 15.0),
+            paid(None, 8.0),
+        ];
+        let groups = group_expenses(&expenses, GroupBy::Payee);
+        let names: Vec<&str> = groups.iter().map(|group| group.name.as_str()).collect();
+        assert_eq!(names, vec!["Amazon", "(none)", "Bakery"]);
+        assert_eq!((groups[0].count, groups[0].total), (2, 35.0));
+        assert_eq!(group_expenses(&expenses, GroupBy::Category).len(), 1);
+    }
+
+    #[test]
+    fn test_recurring_templates() {
+        use chrono::{NaiveDate, Weekday};
+        use expense_tracker::error::ExpenseError;
+        use expense_tracker::recurring::{materialize, Frequency, NthWeekday, Template};
+        use expense_tracker::validation::Limits;
+
+        let date = |text: &str| NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap();
+        let mut rent = Template {
+            id: "1".to_string(),
+            description: "Rent".to_string(),
+            amount: 1200.0,
+            category: Category::Other,
+            tags: vec![],
+            frequency: Frequency::Monthly,
+            interval: 1,
+            start: date("2024-01-31"),
+            end: None,
+            nth_weekday: None,
+            last_generated: None,
+        };
+        assert_eq!(
+            rent.occurrences(None, date("2024-04-15")),
+            vec![date("2024-01-31"), date("2024-02-29"), date("2024-03-31")]
+        );
+        assert_eq!(
+            rent.next_after(date("2024-03-31")),
+            Some(date("2024-04-30"))
+        );
+
+        let mut gym = rent.clone();
+        gym.nth_weekday = Some(NthWeekday {
+            nth: -1,
+            weekday: Weekday::Fri,
+        });
+        gym.end = Some(date("2024-03-29"));
+        assert_eq!(
+            gym.occurrences(None, date("2024-12-31")),
+            vec![date("2024-02-23"), date("2024-03-29")]
+        );
+        assert_eq!(
+            gym.schedule(),
+            "every month on the last Fri until 2024-03-29"
+        );
+        gym.frequency = Frequency::Weekly;
+        assert!(gym.validate().is_err());
+
+        let mut fifth = gym.clone();
+        fifth.frequency = Frequency::Monthly;
+        fifth.end = None;
+        fifth.nth_weekday = Some(NthWeekday {
+            nth: 5,
+            weekday: Weekday::Mon,
+        });
+        assert_eq!(
+            fifth.occurrences(None, date("2024-06-30")),
+            vec![date("2024-04-29")]
+        );
+
+        // Materializing twice, or after losing `last_generated`, adds nothing new.
+        let mut expenses = vec![];
+        let mut templates = vec![rent.clone()];
+        let limits = Limits::default();
+        let generated =
+            materialize(&mut templates, &mut expenses, date("2024-02-29"), &limits).unwrap();
+        assert_eq!(generated.len(), 2);
+        assert_eq!(generated[1].added_at, "2024-02-29 00:00:00");
+        assert_eq!(generated[1].recurring.as_deref(), Some("1"));
+        assert_eq!(templates[0].last_generated, Some(date("2024-02-29")));
+        assert!(
+            materialize(&mut templates, &mut expenses, date("2024-02-29"), &limits)
+                .unwrap()
+                .is_empty()
+        );
+        rent.last_generated = None;
+        let mut templates = vec![rent.clone()];
+        assert_eq!(
+            materialize(&mut templates, &mut expenses, date("2024-03-31"), &limits)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(expenses.len(), 3);
+
+        // A template edited out of range is refused rather than written.
+        rent.id = "2".to_string();
+        rent.amount = limits.max_amount * 2.0;
+        let mut templates = vec![rent];
+        assert!(matches!(
+            materialize(&mut templates, &mut expenses, date("2024-03-31"), &limits),
+            Err(ExpenseError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn test_forecast() {
+        use chrono::NaiveDate;
+        use expense_tracker::forecast::{forecast, upcoming};
+        use expense_tracker::recurring::{Frequency, Template};
+
+        let date = |text: &str| NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap();
+        let today = date("2024-04-10");
+        let paper = Template {
+            id: "7".to_string(),
+            description: "Paper".to_string(),
+            amount: 5.0,
+            category: Category::Other,
+            tags: vec![],
+            frequency: Frequency::Weekly,
+            interval: 1,
+            start: date("2024-04-03"),
+            end: None,
+            nth_weekday: None,
+            last_generated: Some(today),
+        };
+
+        let coming = upcoming(std::slice::from_ref(&paper), today, 14);
+        assert_eq!(coming.len(), 2);
+        assert_eq!(coming[0].id, "7");
+        assert_eq!(coming[0].added_at, "2024-04-17 00:00:00");
+
+        let mut groceries = Expense::new("Groceries".to_string(), 100.0, Category::Food);
+        groceries.added_at = "2024-04-02 12:00:00".to_string();
+        let mut last_month = groceries.clone();
+        last_month.added_at = "2024-03-30 12:00:00".to_string();
+        let recurring = paper.expense_on(date("2024-04-10"));
+
+        // An empty month is 0, not -0.
+        let empty = forecast(&[], &[], 0.0, today);
+        assert!(empty.actual.is_sign_positive() && empty.scheduled.is_sign_positive());
+
+        // 100 of one-off spending in 10 days continues for the 20 days left.
+        let forecast = forecast(&[groceries, last_month, recurring], &[paper], 300.0, today);
+        assert_eq!(forecast.month, "2024-04");
+        assert_eq!(forecast.actual, 105.0);
+        assert_eq!(forecast.scheduled, 10.0);
+        assert_eq!(forecast.trend, 200.0);
+        assert_eq!(forecast.projected, 315.0);
+        assert!(forecast.over_budget);
     }
 }